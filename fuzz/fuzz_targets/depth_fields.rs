@@ -0,0 +1,50 @@
+// Well-formed depth frames with arbitrary version, price and quantity strings.
+#![no_main]
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use prost::Message;
+
+use mexc_spot_public::book::handle_diff_update;
+use mexc_spot_public::mexc_pb::{push_data_v3_api_wrapper::Body, PublicAggreDepthV3ApiItem, PublicAggreDepthsV3Api, PushDataV3ApiWrapper};
+use mexc_spot_public::types::{BookSide, RevSide};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    snap_ver: u64,
+    last_to_ver: Option<u64>,
+    from: String,
+    to: String,
+    asks: Vec<(String, String)>,
+    bids: Vec<(String, String)>,
+}
+
+fuzz_target!(|input: Input| {
+    let items = |l: Vec<(String, String)>| l.into_iter().map(|(price, quantity)| PublicAggreDepthV3ApiItem { price, quantity }).collect();
+    let frame = PushDataV3ApiWrapper {
+        channel: String::new(),
+        symbol: None,
+        symbol_id: None,
+        create_time: None,
+        send_time: None,
+        body: Some(Body::PublicAggreDepths(PublicAggreDepthsV3Api {
+            asks: items(input.asks),
+            bids: items(input.bids),
+            event_type: String::new(),
+            from_version: input.from,
+            to_version: input.to,
+        })),
+    }
+    .encode_to_vec();
+
+    let (mut snap_ver, mut last_to_ver) = (input.snap_ver, input.last_to_ver);
+    let mut asks = BookSide::new();
+    let mut bids = RevSide::new();
+    if handle_diff_update(frame.into(), &mut asks, &mut bids, &mut snap_ver, &mut last_to_ver).is_ok() {
+        assert!(snap_ver > input.snap_ver);
+        for (p, q) in asks.iter().map(|(p, q)| (p.0, *q)).chain(bids.iter().map(|(p, q)| ((p.0).0, *q))) {
+            assert!(p.is_finite() && p > 0.0 && q.is_finite() && q > 0.0);
+        }
+    } else {
+        assert!(snap_ver == input.snap_ver && asks.is_empty() && bids.is_empty());
+    }
+});
@@ -0,0 +1,18 @@
+// Raw WS frames straight into the book builder, from any starting version.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use mexc_spot_public::book::handle_diff_update;
+use mexc_spot_public::types::{BookSide, RevSide};
+
+fuzz_target!(|input: (u64, Option<u64>, &[u8])| {
+    let (mut snap_ver, mut last_to_ver, frame) = input;
+    let mut asks = BookSide::new();
+    let mut bids = RevSide::new();
+    let before = snap_ver;
+    if handle_diff_update(bytes::Bytes::copy_from_slice(frame), &mut asks, &mut bids, &mut snap_ver, &mut last_to_ver).is_ok() {
+        assert!(snap_ver > before);
+    } else {
+        assert!(snap_ver == before && asks.is_empty() && bids.is_empty());
+    }
+});
@@ -0,0 +1,11 @@
+// Numeric fields as they arrive from WS and REST.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use mexc_spot_public::error::parse_num;
+
+fuzz_target!(|s: &str| {
+    let _ = parse_num::<f64>("price", s);
+    let _ = parse_num::<u64>("toVersion", s);
+    let _ = parse_num::<i64>("time", s);
+});
@@ -0,0 +1,290 @@
+// align.rs
+//
+// Trade-to-book alignment, live (`record` with `[align] enabled`, stored as
+// `trade_aligned` events) or offline (`align`). Each trade is matched to the
+// book as it was just before it: the last book state older than the trade,
+// from a short history of top-`levels` states, since REST-polled trades
+// arrive after the book has already moved on.
+//
+// Times are exchange times where known (frame send time, trade time) and
+// receive times otherwise; snapshots only have the latter.
+//
+// From the pre-trade book the trade gets an aggressor (at or through the ask
+// is a buy, at or through the bid a sell), the levels it walked, the visible
+// quantity left at its price after earlier trades against the same book, and
+// its slippage from the touch. Trades that visible liquidity does not explain
+// are flagged as hidden/iceberg suspects:
+// - `inside_spread`: printed between the best bid and ask;
+// - `off_level`: printed through the touch at a price with no visible level;
+// - `exceeds_visible`: larger than what was visible at its price.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+
+use crate::config::AlignConfig;
+use crate::replay::{replay_events, BookReplay};
+use crate::types::{BookSide, RevSide, TradeEvent};
+
+/// Relative tolerance for price and quantity comparisons.
+const EPS: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggressor {
+    Buy,
+    Sell,
+}
+
+impl Aggressor {
+    /// From a stored `TradeEvent::side`.
+    pub fn from_side(side: &str) -> Option<Self> {
+        match side {
+            "BUY" => Some(Self::Buy),
+            "SELL" => Some(Self::Sell),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mismatch {
+    InsideSpread,
+    OffLevel,
+    ExceedsVisible,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlignedTrade {
+    #[serde(flatten)]
+    pub trade: TradeEvent,
+    /// The pre-trade book; all `None` when there was no valid one.
+    pub book_version: Option<u64>,
+    pub book_ts_ms: Option<i64>,
+    /// Best `[price, qty]`.
+    pub bid: Option<[f64; 2]>,
+    pub ask: Option<[f64; 2]>,
+    /// Inferred from the book; inside the spread, the side of mid it printed on.
+    pub aggressor: Option<Aggressor>,
+    /// Whether `aggressor` agrees with `side` (from `isBuyerMaker`).
+    pub side_agrees: Option<bool>,
+    /// Opposite levels at or better than the trade price.
+    pub levels_walked: u32,
+    /// Visible quantity at the trade price before it.
+    pub visible_qty: Option<f64>,
+    /// Distance from the aggressor's touch; positive is worse for the aggressor.
+    pub slippage_bps: Option<f64>,
+    pub mismatch: Option<Mismatch>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AlignStats {
+    pub trades: u64,
+    /// Had a pre-trade book.
+    pub aligned: u64,
+    /// Aggressor and `isBuyerMaker` side both known and equal.
+    pub side_agrees: u64,
+    pub side_disagrees: u64,
+    pub inside_spread: u64,
+    pub off_level: u64,
+    pub exceeds_visible: u64,
+}
+
+/// Top levels as of `ts_ms`; `valid` false from a break until the next snapshot.
+struct State {
+    ts_ms: i64,
+    version: u64,
+    valid: bool,
+    bids: Vec<[f64; 2]>,
+    asks: Vec<[f64; 2]>,
+}
+
+/// Matches one symbol's trades against its recent book states.
+pub struct Aligner {
+    levels: usize,
+    history_ms: i64,
+    states: VecDeque<State>,
+    /// Quantity taken per (side is ask, price) by trades aligned to `consumed.0`.
+    consumed: (u64, HashMap<(bool, u64), f64>),
+    pub stats: AlignStats,
+}
+
+fn same(a: f64, b: f64) -> bool {
+    (a - b).abs() <= EPS * a.abs().max(b.abs())
+}
+
+impl Aligner {
+    pub fn new(cfg: &AlignConfig) -> Self {
+        Self {
+            levels: cfg.levels.max(1),
+            history_ms: cfg.history_ms as i64,
+            states: VecDeque::new(),
+            consumed: (0, HashMap::new()),
+            stats: AlignStats::default(),
+        }
+    }
+
+    fn push(&mut self, mut s: State) {
+        // mixed exchange and receive times may step back a little; keep the history sorted
+        if let Some(last) = self.states.back() {
+            s.ts_ms = s.ts_ms.max(last.ts_ms);
+        }
+        let horizon = s.ts_ms - self.history_ms;
+        self.states.push_back(s);
+        // keep the state that was current at the horizon
+        while self.states.get(1).is_some_and(|s| s.ts_ms <= horizon) {
+            self.states.pop_front();
+        }
+    }
+
+    /// The book as of `ts_ms` (exchange time of the frame if known).
+    pub fn on_book(&mut self, ts_ms: i64, version: u64, asks: &BookSide, bids: &RevSide) {
+        self.push(State {
+            ts_ms,
+            version,
+            valid: true,
+            bids: bids.iter().take(self.levels).map(|(p, q)| [(p.0).0, *q]).collect(),
+            asks: asks.iter().take(self.levels).map(|(p, q)| [p.0, *q]).collect(),
+        });
+    }
+
+    /// The book is unusable from `ts_ms` until the next `on_book`.
+    pub fn on_break(&mut self, ts_ms: i64) {
+        if self.states.back().is_some_and(|s| !s.valid) {
+            return;
+        }
+        self.push(State { ts_ms, version: 0, valid: false, bids: Vec::new(), asks: Vec::new() });
+    }
+
+    pub fn on_trade(&mut self, t: &TradeEvent) -> AlignedTrade {
+        self.stats.trades += 1;
+        let side = t.side.as_deref().and_then(Aggressor::from_side);
+        let mut out = AlignedTrade {
+            trade: t.clone(),
+            book_version: None,
+            book_ts_ms: None,
+            bid: None,
+            ask: None,
+            aggressor: None,
+            side_agrees: None,
+            levels_walked: 0,
+            visible_qty: None,
+            slippage_bps: None,
+            mismatch: None,
+        };
+        let at = t.ts_exch_ms.unwrap_or(t.ts_recv_ms);
+        let i = self.states.partition_point(|s| s.ts_ms < at);
+        let Some(book) = i.checked_sub(1).and_then(|i| self.states.get(i)).filter(|s| s.valid) else {
+            return out;
+        };
+        let (bid, ask) = (book.bids.first().copied(), book.asks.first().copied());
+        if bid.is_none() && ask.is_none() {
+            return out;
+        }
+        self.stats.aligned += 1;
+        out.book_version = Some(book.version);
+        out.book_ts_ms = Some(book.ts_ms);
+        out.bid = bid;
+        out.ask = ask;
+        if self.consumed.0 != book.version {
+            self.consumed = (book.version, HashMap::new());
+        }
+
+        let p = t.price;
+        let (aggressor, levels, touch) = match (bid, ask) {
+            (_, Some(a)) if p >= a[0] || same(p, a[0]) => (Aggressor::Buy, &book.asks, a[0]),
+            (Some(b), _) if p <= b[0] || same(p, b[0]) => (Aggressor::Sell, &book.bids, b[0]),
+            (Some(b), Some(a)) => {
+                // printed inside the spread: nothing visible was there
+                out.mismatch = Some(Mismatch::InsideSpread);
+                self.stats.inside_spread += 1;
+                let mid = (b[0] + a[0]) / 2.0;
+                let agg = if same(p, mid) { None } else if p > mid { Some(Aggressor::Buy) } else { Some(Aggressor::Sell) };
+                out.aggressor = agg;
+                out.slippage_bps = agg.map(|g| match g {
+                    Aggressor::Buy => (p - a[0]) / a[0] * 1e4,
+                    Aggressor::Sell => (b[0] - p) / b[0] * 1e4,
+                });
+                count_side(&mut self.stats, &mut out, side);
+                return out;
+            }
+            // one-sided book, on the empty side
+            _ => return out,
+        };
+        out.aggressor = Some(aggressor);
+        count_side(&mut self.stats, &mut out, side);
+        out.slippage_bps = Some(match aggressor {
+            Aggressor::Buy => (p - touch) / touch * 1e4,
+            Aggressor::Sell => (touch - p) / touch * 1e4,
+        });
+        let through = |l: &[f64; 2]| match aggressor {
+            Aggressor::Buy => l[0] <= p || same(l[0], p),
+            Aggressor::Sell => l[0] >= p || same(l[0], p),
+        };
+        let walked = levels.iter().take_while(|l| through(l)).count();
+        out.levels_walked = walked as u32;
+        match levels[..walked].last().filter(|l| same(l[0], p)) {
+            Some(l) => {
+                let key = (aggressor == Aggressor::Buy, l[0].to_bits());
+                let taken = self.consumed.1.entry(key).or_insert(0.0);
+                let visible = (l[1] - *taken).max(0.0);
+                out.visible_qty = Some(visible);
+                *taken += t.qty;
+                if t.qty > visible && !same(t.qty, visible) {
+                    out.mismatch = Some(Mismatch::ExceedsVisible);
+                    self.stats.exceeds_visible += 1;
+                }
+            }
+            // beyond the levels kept there is no telling
+            None if walked < levels.len() => {
+                out.visible_qty = Some(0.0);
+                out.mismatch = Some(Mismatch::OffLevel);
+                self.stats.off_level += 1;
+            }
+            None => {}
+        }
+        out
+    }
+}
+
+/// Sets `side_agrees` from the aggressor and the `isBuyerMaker` side.
+fn count_side(stats: &mut AlignStats, out: &mut AlignedTrade, side: Option<Aggressor>) {
+    let (Some(a), Some(s)) = (out.aggressor, side) else { return };
+    out.side_agrees = Some(a == s);
+    if a == s {
+        stats.side_agrees += 1;
+    } else {
+        stats.side_disagrees += 1;
+    }
+}
+
+/// Replays `symbol` and calls `on_trade` with every trade from `from_ms` on,
+/// aligned to the book; the data before `from_ms`, back to the last
+/// snapshot, provides the book history.
+pub fn replay_aligned<P: AsRef<Path>>(
+    root: P,
+    symbol: &str,
+    from_ms: i64,
+    to_ms: i64,
+    cfg: &AlignConfig,
+    mut on_trade: impl FnMut(&AlignedTrade) -> Result<()>,
+) -> Result<(BookReplay, AlignStats)> {
+    let mut aligner = Aligner::new(cfg);
+    let book = replay_events(root, symbol, from_ms, to_ms, |book, ev, changed| {
+        match ev.kind.as_str() {
+            "trade" if ev.ts_ms >= from_ms => {
+                if let Ok(t) = ev.payload_as::<TradeEvent>() {
+                    on_trade(&aligner.on_trade(&t))?;
+                }
+            }
+            "depth_snapshot" | "depth_pb_raw" if changed => {
+                aligner.on_book(book.ts_exch_ms.unwrap_or(ev.ts_ms), book.depth.version, &book.depth.asks, &book.depth.bids)
+            }
+            "depth_pb_raw" if !book.depth.valid => aligner.on_break(ev.ts_ms),
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok((book, aligner.stats))
+}
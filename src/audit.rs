@@ -0,0 +1,337 @@
+// audit.rs
+//
+// Offline data-quality audit: replays a symbol's recorded partitions through
+// the recorder's own depth step (`book::DepthBook::apply_frame`) and counts
+// what looks wrong, per hour. Depth diffs come from `raw.pb.zst` or legacy
+// `depth_pb_raw` lines; stored `depth_snapshot`s (re)seed the book, levels
+// pruned from a crossed book are removed as the `crossed_book` event says, and
+// stored `depth_delta`s and aligned snapshots are compared to the reconstruction.
+//
+// Stored snapshots only keep the top 50 levels, so only the top
+// `compare_levels` are compared. Levels are checked against the tick and lot
+// size of the last stored `exchange_info`, when there is one.
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::book::{apply_pruned, best, DepthBook};
+use crate::error::IngestError;
+use crate::store::{list_partitions, read_partition, StoredEvent};
+use crate::types::{CrossedBook, DepthDelta, DepthSnapshot, ExchangeInfoEvent, Precision, TradeEvent};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditOptions {
+    /// No depth update for longer than this is a stale period.
+    pub stale_ms: i64,
+    /// Levels further than this fraction from the mid are outside the sane band.
+    pub price_band: f64,
+    pub compare_levels: usize,
+}
+
+impl Default for AuditOptions {
+    fn default() -> Self {
+        Self { stale_ms: 5_000, price_band: 0.5, compare_levels: 10 }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HourReport {
+    pub partition: String,
+    pub depth_frames: u64,
+    pub applied_deltas: u64,
+    pub snapshots: u64,
+    pub trades: u64,
+    pub read_errors: u64,
+    /// Version gaps `handle_diff_update` could not bridge.
+    pub gaps: u64,
+    /// Snapshots that re-seeded an existing (or broken) book.
+    pub resyncs: u64,
+    /// From the gap to the snapshot that fixed it.
+    pub resync_ms_total: i64,
+    pub resync_ms_max: i64,
+    pub crossed: u64,
+    pub crossed_ms_total: i64,
+    pub stale_periods: u64,
+    pub stale_ms_total: i64,
+    pub stale_ms_max: i64,
+    /// Negative or non-finite quantities in diffs (which break the book), non-positive ones in snapshots.
+    pub bad_qty: u64,
+    /// Non-positive, non-finite or out-of-band prices.
+    pub bad_price: u64,
+    /// Prices or quantities not on the symbol's tick/lot grid.
+    pub off_grid: u64,
+    pub snapshot_checks: u64,
+    pub snapshot_mismatches: u64,
+    pub delta_checks: u64,
+    pub delta_mismatches: u64,
+    pub trades_checked: u64,
+    pub trades_outside_spread: u64,
+}
+
+impl HourReport {
+    fn add(&mut self, o: &HourReport) {
+        self.depth_frames += o.depth_frames;
+        self.applied_deltas += o.applied_deltas;
+        self.snapshots += o.snapshots;
+        self.trades += o.trades;
+        self.read_errors += o.read_errors;
+        self.gaps += o.gaps;
+        self.resyncs += o.resyncs;
+        self.resync_ms_total += o.resync_ms_total;
+        self.resync_ms_max = self.resync_ms_max.max(o.resync_ms_max);
+        self.crossed += o.crossed;
+        self.crossed_ms_total += o.crossed_ms_total;
+        self.stale_periods += o.stale_periods;
+        self.stale_ms_total += o.stale_ms_total;
+        self.stale_ms_max = self.stale_ms_max.max(o.stale_ms_max);
+        self.bad_qty += o.bad_qty;
+        self.bad_price += o.bad_price;
+        self.off_grid += o.off_grid;
+        self.snapshot_checks += o.snapshot_checks;
+        self.snapshot_mismatches += o.snapshot_mismatches;
+        self.delta_checks += o.delta_checks;
+        self.delta_mismatches += o.delta_mismatches;
+        self.trades_checked += o.trades_checked;
+        self.trades_outside_spread += o.trades_outside_spread;
+    }
+
+    /// One line for the human summary.
+    pub fn summary_line(&self) -> String {
+        format!(
+            "{}: {} frames, {} gaps, {} resyncs ({} ms), {} crossed ({} ms), {} stale ({} ms max), \
+             {} bad qty, {} bad px, {} off grid, snap {}/{} bad, delta {}/{} bad, trades {}/{} outside spread",
+            self.partition,
+            self.depth_frames,
+            self.gaps,
+            self.resyncs,
+            self.resync_ms_total,
+            self.crossed,
+            self.crossed_ms_total,
+            self.stale_periods,
+            self.stale_ms_max,
+            self.bad_qty,
+            self.bad_price,
+            self.off_grid,
+            self.snapshot_mismatches,
+            self.snapshot_checks,
+            self.delta_mismatches,
+            self.delta_checks,
+            self.trades_outside_spread,
+            self.trades_checked,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditReport {
+    pub symbol: String,
+    pub from_date: String,
+    pub to_date: String,
+    pub options: AuditOptions,
+    pub hours: Vec<HourReport>,
+    pub total: HourReport,
+}
+
+/// Replay state carried across partitions.
+#[derive(Default)]
+struct Replay {
+    depth: DepthBook,
+    last_update_ms: Option<i64>,
+    broken_since_ms: Option<i64>,
+    crossed_since_ms: Option<i64>,
+    precision: Option<Precision>,
+}
+
+impl Replay {
+    fn top_matches(&self, asks: &[[f64; 2]], bids: &[[f64; 2]], n: usize) -> bool {
+        let ours_a = self.depth.asks.iter().take(n).map(|(p, q)| [p.0, *q]);
+        let ours_b = self.depth.bids.iter().take(n).map(|(p, q)| [(p.0).0, *q]);
+        let n_a = n.min(asks.len());
+        let n_b = n.min(bids.len());
+        ours_a.take(n_a).eq(asks.iter().take(n_a).copied()) && ours_b.take(n_b).eq(bids.iter().take(n_b).copied())
+    }
+
+    fn mid(&self) -> Option<f64> {
+        best(&self.depth.asks, &self.depth.bids).map(|(b, a)| (a + b) / 2.0)
+    }
+
+    fn depth_update(&mut self, ts_ms: i64, r: &mut HourReport, opts: &AuditOptions) {
+        if let Some(prev) = self.last_update_ms {
+            let idle = ts_ms - prev;
+            if idle > opts.stale_ms {
+                r.stale_periods += 1;
+                r.stale_ms_total += idle;
+                r.stale_ms_max = r.stale_ms_max.max(idle);
+            }
+        }
+        self.last_update_ms = Some(ts_ms);
+    }
+
+    fn check_crossed(&mut self, ts_ms: i64, r: &mut HourReport) {
+        let crossed = best(&self.depth.asks, &self.depth.bids).is_some_and(|(b, a)| b >= a);
+        match (crossed, self.crossed_since_ms) {
+            (true, None) => {
+                r.crossed += 1;
+                self.crossed_since_ms = Some(ts_ms);
+            }
+            (false, Some(since)) => {
+                r.crossed_ms_total += ts_ms - since;
+                self.crossed_since_ms = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn check_levels(&self, levels: &[[f64; 2]], snapshot: bool, r: &mut HourReport, opts: &AuditOptions) {
+        let mid = self.mid();
+        for [p, q] in levels {
+            let bad_q = !q.is_finite() || *q < 0.0 || (snapshot && *q == 0.0);
+            if bad_q {
+                r.bad_qty += 1;
+            }
+            let out_of_band = mid.is_some_and(|m| (p - m).abs() > m * opts.price_band);
+            if !p.is_finite() || *p <= 0.0 || out_of_band {
+                r.bad_price += 1;
+            }
+            if let Some(pr) = self.precision {
+                if (p.is_finite() && !pr.price_on_grid(*p)) || (q.is_finite() && !pr.qty_on_grid(*q)) {
+                    r.off_grid += 1;
+                }
+            }
+        }
+    }
+
+    fn event(&mut self, ev: StoredEvent, r: &mut HourReport, opts: &AuditOptions) {
+        match ev.kind.as_str() {
+            "depth_pb_raw" => {
+                let Some(Ok(raw)) = ev.raw() else {
+                    r.read_errors += 1;
+                    return;
+                };
+                r.depth_frames += 1;
+                self.depth_update(ev.ts_ms, r, opts);
+                if !self.depth.valid {
+                    return;
+                }
+                let out = self.depth.apply_frame(&ev.symbol, raw.into(), ev.ts_ms);
+                if let Some(d) = &out.delta {
+                    r.applied_deltas += 1;
+                    self.check_levels(&d.asks, false, r, opts);
+                    self.check_levels(&d.bids, false, r, opts);
+                    self.check_crossed(ev.ts_ms, r);
+                }
+                match out.error {
+                    Some(IngestError::Decode(_)) => r.read_errors += 1,
+                    Some(e) if out.broke => {
+                        match e {
+                            IngestError::SequenceGap { .. } => r.gaps += 1,
+                            IngestError::Parse { field: "price", .. } => r.bad_price += 1,
+                            IngestError::Parse { field: "quantity", .. } => r.bad_qty += 1,
+                            _ => r.read_errors += 1,
+                        }
+                        self.broken_since_ms = Some(ev.ts_ms);
+                    }
+                    _ => {}
+                }
+            }
+            "depth_snapshot" => {
+                let Ok(s) = ev.payload_as::<DepthSnapshot>() else {
+                    r.read_errors += 1;
+                    return;
+                };
+                r.snapshots += 1;
+                if self.depth.valid && self.depth.version == s.last_update_id {
+                    r.snapshot_checks += 1;
+                    if !self.top_matches(&s.asks, &s.bids, opts.compare_levels) {
+                        r.snapshot_mismatches += 1;
+                    }
+                }
+                if self.depth.valid || self.broken_since_ms.is_some() {
+                    r.resyncs += 1;
+                    let took = self.broken_since_ms.take().map_or(0, |t| (ev.ts_ms - t).max(0));
+                    r.resync_ms_total += took;
+                    r.resync_ms_max = r.resync_ms_max.max(took);
+                }
+                self.depth.load_snapshot(&s);
+                self.check_levels(&s.asks, true, r, opts);
+                self.check_levels(&s.bids, true, r, opts);
+                self.check_crossed(ev.ts_ms, r);
+            }
+            "depth_delta" => {
+                let Ok(d) = ev.payload_as::<DepthDelta>() else {
+                    r.read_errors += 1;
+                    return;
+                };
+                if self.depth.valid && d.to_version == self.depth.version {
+                    r.delta_checks += 1;
+                    if !self.top_matches(&d.asks, &d.bids, opts.compare_levels) {
+                        r.delta_mismatches += 1;
+                    }
+                }
+            }
+            // the recorder's prune, so the book stays comparable to its deltas
+            "crossed_book" => match ev.payload_as::<CrossedBook>() {
+                Ok(c) if self.depth.valid => {
+                    apply_pruned(&mut self.depth.asks, &mut self.depth.bids, &c);
+                    self.check_crossed(ev.ts_ms, r);
+                }
+                Ok(_) => {}
+                Err(_) => r.read_errors += 1,
+            },
+            "exchange_info" => match ev.payload_as::<ExchangeInfoEvent>() {
+                Ok(e) => self.precision = Some(e.info.precision),
+                Err(_) => r.read_errors += 1,
+            },
+            "trade" => {
+                let Ok(t) = ev.payload_as::<TradeEvent>() else {
+                    r.read_errors += 1;
+                    return;
+                };
+                r.trades += 1;
+                if let Some((bid, ask)) = best(&self.depth.asks, &self.depth.bids).filter(|_| self.depth.valid) {
+                    r.trades_checked += 1;
+                    let eps = t.price.abs() * 1e-9;
+                    if t.price < bid - eps || t.price > ask + eps {
+                        r.trades_outside_spread += 1;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Audits `symbol` for partitions dated `from_date..=to_date` (`YYYY-MM-DD`).
+pub fn audit_symbol<P: AsRef<Path>>(
+    root: P,
+    symbol: &str,
+    from_date: &str,
+    to_date: &str,
+    opts: &AuditOptions,
+) -> Result<AuditReport> {
+    let mut replay = Replay::default();
+    let mut hours = Vec::new();
+    let mut total = HourReport { partition: "total".to_string(), ..Default::default() };
+    for part in list_partitions(root)? {
+        if part.symbol != symbol || part.date.as_str() < from_date || part.date.as_str() > to_date {
+            continue;
+        }
+        let mut r = HourReport { partition: part.rel_dir().display().to_string(), ..Default::default() };
+        for ev in read_partition(&part)? {
+            match ev {
+                Ok(ev) => replay.event(ev, &mut r, opts),
+                Err(_) => r.read_errors += 1,
+            }
+        }
+        total.add(&r);
+        hours.push(r);
+    }
+    Ok(AuditReport {
+        symbol: symbol.to_string(),
+        from_date: from_date.to_string(),
+        to_date: to_date.to_string(),
+        options: opts.clone(),
+        hours,
+        total,
+    })
+}
@@ -0,0 +1,331 @@
+// book.rs
+//
+// L2 book maintenance shared by the live recorder and offline replay (audit).
+// `DepthBook::apply_frame` is the per-frame step of the recorder's depth loop;
+// fixtures, `replay` and `audit` run stored frames through the same step.
+use bytes::Bytes;
+use ordered_float::OrderedFloat;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+use crate::error::{parse_num, IngestError, Recovery};
+use crate::mexc_pb::{self, PushDataV3ApiWrapper};
+use crate::types::{AppliedDelta, BookSide, CrossedBook, DepthSnapshot, Precision, RevSide};
+
+/// Version jumps up to this size are bridged: the aggregated stream skips
+/// versions between frames. Larger ones are a [`IngestError::SequenceGap`].
+pub const MAX_VERSION_GAP: u64 = 1000;
+
+/// Applies one aggregated-depth WS frame to the book. Frames that are not
+/// aggregated depth or are already covered by `snap_ver` come back as
+/// [`IngestError::UnexpectedBody`] / [`IngestError::Stale`] (skip them); see
+/// [`IngestError::recovery`] for the rest. The book and versions are only
+/// touched once the whole frame has been validated.
+pub fn handle_diff_update(
+    buf: Bytes,
+    asks: &mut BookSide,
+    bids: &mut RevSide,
+    snap_ver: &mut u64,
+    last_to_ver: &mut Option<u64>,
+) -> Result<AppliedDelta, IngestError> {
+    use mexc_pb::push_data_v3_api_wrapper::Body;
+
+    let wrapper = PushDataV3ApiWrapper::decode(buf)?;
+    let send_time = wrapper.send_time;
+    let Some(body) = wrapper.body else {
+        return Err(IngestError::UnexpectedBody(format!("no body on {}", wrapper.channel)));
+    };
+    let Body::PublicAggreDepths(delta) = body else {
+        return Err(IngestError::UnexpectedBody(format!("non-depth body on {}", wrapper.channel)));
+    };
+
+    let from_v: u64 = parse_num("fromVersion", &delta.from_version)?;
+    let to_v: u64 = parse_num("toVersion", &delta.to_version)?;
+    if from_v > to_v {
+        return Err(IngestError::UnexpectedBody(format!("fromVersion {from_v} > toVersion {to_v}")));
+    }
+
+    if to_v <= *snap_ver {
+        return Err(IngestError::Stale { from: from_v, to: to_v, version: *snap_ver });
+    }
+
+    // parse everything first so a bad level leaves the book untouched
+    let asks_in = delta.asks.iter().map(|it| parse_level(&it.price, &it.quantity)).collect::<Result<Vec<_>, _>>()?;
+    let bids_in = delta.bids.iter().map(|it| parse_level(&it.price, &it.quantity)).collect::<Result<Vec<_>, _>>()?;
+
+    let needed = *snap_ver + 1;
+    if from_v > needed && last_to_ver.is_some() && from_v - needed > MAX_VERSION_GAP {
+        return Err(IngestError::SequenceGap { needed, from: from_v, to: to_v });
+    }
+
+    for &[p, q] in &asks_in {
+        if q == 0.0 { asks.remove(&OrderedFloat(p)); } else { asks.insert(OrderedFloat(p), q); }
+    }
+    for &[p, q] in &bids_in {
+        if q == 0.0 { bids.remove(&Reverse(OrderedFloat(p))); } else { bids.insert(Reverse(OrderedFloat(p)), q); }
+    }
+    let applied = AppliedDelta { from_version: from_v, to_version: to_v, ts_exch_ms: send_time, bids: bids_in, asks: asks_in };
+
+    *snap_ver = to_v;
+    *last_to_ver = Some(to_v);
+
+    Ok(applied)
+}
+
+/// A wire `[price, qty]` level: finite, positive price and non-negative quantity.
+fn parse_level(price: &str, qty: &str) -> Result<[f64; 2], IngestError> {
+    let p: f64 = parse_num("price", price)?;
+    let q: f64 = parse_num("quantity", qty)?;
+    if !p.is_finite() || p <= 0.0 {
+        return Err(IngestError::Parse { field: "price", value: price.to_string() });
+    }
+    if !q.is_finite() || q < 0.0 {
+        return Err(IngestError::Parse { field: "quantity", value: qty.to_string() });
+    }
+    Ok([p, q])
+}
+
+/// Replaces the book with `[price, qty]` levels, e.g. a stored `depth_snapshot`.
+pub fn load_levels(asks: &mut BookSide, bids: &mut RevSide, ask_levels: &[[f64; 2]], bid_levels: &[[f64; 2]]) {
+    asks.clear();
+    bids.clear();
+    for [p, q] in ask_levels {
+        asks.insert(OrderedFloat(*p), *q);
+    }
+    for [p, q] in bid_levels {
+        bids.insert(Reverse(OrderedFloat(*p)), *q);
+    }
+}
+
+/// Best bid and ask prices, if both sides are non-empty.
+pub fn best(asks: &BookSide, bids: &RevSide) -> Option<(f64, f64)> {
+    let (ask, _) = asks.first_key_value()?;
+    let (bid, _) = bids.first_key_value()?;
+    Some((bid.0 .0, ask.0))
+}
+
+/// Removes the levels a `prune` episode dropped, for replaying stored data.
+pub fn apply_pruned(asks: &mut BookSide, bids: &mut RevSide, ev: &CrossedBook) {
+    for p in &ev.pruned_asks {
+        asks.remove(&OrderedFloat(*p));
+    }
+    for p in &ev.pruned_bids {
+        bids.remove(&Reverse(OrderedFloat(*p)));
+    }
+}
+
+/// What to do when an applied diff leaves `best bid >= best ask`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CrossedPolicy {
+    /// Reload the REST snapshot right away.
+    #[default]
+    Resync,
+    /// Trust the diff that caused the cross and drop the opposite-side levels it
+    /// overlaps; resync if that does not uncross the book.
+    Prune,
+    /// Keep going for up to `tolerate_updates` crossed updates, then resync.
+    Tolerate,
+}
+
+#[derive(Debug, Default)]
+pub struct CrossedOutcome {
+    /// A new crossed episode started with this update.
+    pub started: bool,
+    /// Set when an episode ended; record it as a `crossed_book` event.
+    pub event: Option<CrossedBook>,
+    pub resync: bool,
+}
+
+/// Tracks crossed episodes across updates and applies the [`CrossedPolicy`].
+#[derive(Debug, Clone)]
+pub struct CrossedGuard {
+    policy: CrossedPolicy,
+    tolerate_updates: u32,
+    open: Option<CrossedBook>,
+}
+
+impl CrossedGuard {
+    pub fn new(policy: CrossedPolicy, tolerate_updates: u32) -> Self {
+        Self { policy, tolerate_updates, open: None }
+    }
+
+    /// Forget any open episode, e.g. after the book was reloaded.
+    pub fn reset(&mut self) {
+        self.open = None;
+    }
+
+    fn close(&mut self, mut ev: CrossedBook, action: &str, ts_recv_ms: i64) -> Option<CrossedBook> {
+        ev.action = action.to_string();
+        ev.duration_ms = ts_recv_ms - ev.ts_recv_ms;
+        self.open = None;
+        Some(ev)
+    }
+
+    /// Call after every applied diff. Levels removed by `prune` are added to
+    /// `applied` with quantity 0, so whoever mirrors the book from the
+    /// published deltas removes them too.
+    pub fn check(
+        &mut self,
+        symbol: &str,
+        asks: &mut BookSide,
+        bids: &mut RevSide,
+        applied: &mut AppliedDelta,
+        ts_recv_ms: i64,
+    ) -> CrossedOutcome {
+        let mut out = CrossedOutcome::default();
+        let Some((bid, ask)) = best(asks, bids).filter(|(b, a)| b >= a) else {
+            if let Some(ev) = self.open.take() {
+                out.event = self.close(ev, "recovered", ts_recv_ms);
+            }
+            return out;
+        };
+
+        let mut ev = match self.open.take() {
+            Some(mut ev) => {
+                ev.updates += 1;
+                ev
+            }
+            None => {
+                out.started = true;
+                CrossedBook {
+                    symbol: symbol.to_string(),
+                    ts_recv_ms,
+                    version: applied.to_version,
+                    best_bid: bid,
+                    best_ask: ask,
+                    bids: bids.iter().take_while(|(p, _)| (p.0).0 >= ask).map(|(p, q)| [(p.0).0, *q]).collect(),
+                    asks: asks.iter().take_while(|(p, _)| p.0 <= bid).map(|(p, q)| [p.0, *q]).collect(),
+                    action: String::new(),
+                    pruned_levels: 0,
+                    pruned_asks: Vec::new(),
+                    pruned_bids: Vec::new(),
+                    updates: 1,
+                    duration_ms: 0,
+                }
+            }
+        };
+
+        match self.policy {
+            CrossedPolicy::Resync => {
+                out.event = self.close(ev, "resync", ts_recv_ms);
+                out.resync = true;
+            }
+            CrossedPolicy::Prune => {
+                let top = |levels: &[[f64; 2]], f: fn(f64, f64) -> f64, init| {
+                    levels.iter().filter(|l| l[1] > 0.0).map(|l| l[0]).fold(init, f)
+                };
+                let new_bid = top(&applied.bids, f64::max, f64::NEG_INFINITY);
+                let new_ask = top(&applied.asks, f64::min, f64::INFINITY);
+                if new_bid >= ask {
+                    ev.pruned_asks = asks.range(..=OrderedFloat(new_bid)).map(|(p, _)| p.0).collect();
+                }
+                if new_ask <= bid {
+                    ev.pruned_bids = bids.range(..=Reverse(OrderedFloat(new_ask))).map(|(p, _)| (p.0).0).collect();
+                }
+                apply_pruned(asks, bids, &ev);
+                applied.asks.extend(ev.pruned_asks.iter().map(|p| [*p, 0.0]));
+                applied.bids.extend(ev.pruned_bids.iter().map(|p| [*p, 0.0]));
+                ev.pruned_levels = ev.pruned_asks.len() + ev.pruned_bids.len();
+                let still = best(asks, bids).is_some_and(|(b, a)| b >= a);
+                out.resync = still;
+                out.event = self.close(ev, if still { "resync" } else { "prune" }, ts_recv_ms);
+            }
+            CrossedPolicy::Tolerate => {
+                if ev.updates > self.tolerate_updates {
+                    out.event = self.close(ev, "resync", ts_recv_ms);
+                    out.resync = true;
+                } else {
+                    self.open = Some(ev);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// What [`DepthBook::apply_frame`] did with one frame.
+#[derive(Debug, Default)]
+pub struct FrameOutcome {
+    /// The applied diff, with any levels the guard pruned as quantity 0; `None`
+    /// while the book is broken.
+    pub delta: Option<AppliedDelta>,
+    pub crossed: CrossedOutcome,
+    /// Why the frame was skipped or broke the book.
+    pub error: Option<IngestError>,
+    /// Levels of the applied diff off the tick or lot grid of [`DepthBook::precision`].
+    pub off_grid: usize,
+    /// This frame broke a valid book; it needs a snapshot.
+    pub broke: bool,
+}
+
+/// One symbol's book as the depth loop keeps it.
+#[derive(Debug, Clone, Default)]
+pub struct DepthBook {
+    pub asks: BookSide,
+    pub bids: RevSide,
+    pub version: u64,
+    last_to_ver: Option<u64>,
+    /// Seeded by a snapshot and not broken since.
+    pub valid: bool,
+    /// `None` offline, where the stored `crossed_book` events say what the recorder did.
+    crossed: Option<CrossedGuard>,
+    /// The symbol's tick and lot size from `exchangeInfo`, if known.
+    pub precision: Option<Precision>,
+}
+
+impl DepthBook {
+    /// An empty book, waiting for its first snapshot.
+    pub fn new(crossed: Option<CrossedGuard>) -> Self {
+        Self { crossed, ..Default::default() }
+    }
+
+    /// The levels were just replaced by a snapshot at `version`.
+    pub fn resynced(&mut self, version: u64) {
+        self.version = version;
+        self.last_to_ver = None;
+        self.valid = true;
+        if let Some(g) = &mut self.crossed {
+            g.reset();
+        }
+    }
+
+    pub fn load_snapshot(&mut self, s: &DepthSnapshot) {
+        load_levels(&mut self.asks, &mut self.bids, &s.asks, &s.bids);
+        self.resynced(s.last_update_id);
+    }
+
+    /// Marks the book unusable until the next snapshot; false if it already was.
+    pub fn break_book(&mut self) -> bool {
+        std::mem::replace(&mut self.valid, false)
+    }
+
+    /// Applies one aggregated-depth frame and runs the crossed-book guard.
+    /// A broken book keeps taking frames, without running the guard or reporting
+    /// a delta; only a snapshot makes it valid again.
+    pub fn apply_frame(&mut self, symbol: &str, buf: Bytes, ts_recv_ms: i64) -> FrameOutcome {
+        let mut out = FrameOutcome::default();
+        match handle_diff_update(buf, &mut self.asks, &mut self.bids, &mut self.version, &mut self.last_to_ver) {
+            Ok(_) if !self.valid => {}
+            Ok(mut d) => {
+                if let Some(pr) = self.precision {
+                    let off = |[p, q]: &[f64; 2]| !pr.price_on_grid(*p) || !pr.qty_on_grid(*q);
+                    out.off_grid = d.asks.iter().chain(&d.bids).filter(|l| off(l)).count();
+                }
+                if let Some(g) = &mut self.crossed {
+                    out.crossed = g.check(symbol, &mut self.asks, &mut self.bids, &mut d, ts_recv_ms);
+                }
+                if let Some(ev) = out.crossed.event.as_ref().filter(|_| out.crossed.resync) {
+                    out.error = Some(IngestError::CrossedBook { bid: ev.best_bid, ask: ev.best_ask });
+                }
+                out.delta = Some(d);
+            }
+            Err(e) => out.error = Some(e),
+        }
+        if out.error.as_ref().is_some_and(|e| e.recovery() != Recovery::Skip) {
+            out.broke = self.break_book();
+        }
+        out
+    }
+}
@@ -0,0 +1,148 @@
+// clock.rs
+//
+// Receive timestamps for the whole process. Wall clock is read once at start
+// and then advanced with the monotonic clock, so NTP steps cannot reorder or
+// duplicate timestamps. `reanchor()` (called periodically) re-reads the wall
+// clock and reports how far the two have drifted apart; output never goes
+// backwards across a re-anchor, it holds until the new anchor catches up.
+//
+// Every stamp also gets a process-wide sequence number, so events received in
+// the same nanosecond (or on different tasks) still have a total order. Time
+// and sequence are taken under one lock, so the two orders always agree.
+use serde::{Deserialize, Serialize};
+use std::sync::{LazyLock, Mutex, RwLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Stamp {
+    pub ts_ns: i64,
+    pub recv_seq: u64,
+}
+
+impl Stamp {
+    pub fn ms(&self) -> i64 {
+        self.ts_ns.div_euclid(1_000_000)
+    }
+}
+
+/// Recorded as a `clock_anchor` event whenever the anchor is refreshed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockAnchorSample {
+    /// Clock output just before re-anchoring.
+    pub mono_ns: i64,
+    /// `SystemTime::now()` at the same instant.
+    pub wall_ns: i64,
+    /// `wall_ns - mono_ns`; positive means the system clock is ahead of ours.
+    pub drift_ns: i64,
+    pub uptime_ns: i64,
+}
+
+#[derive(Clone, Copy)]
+struct Anchor {
+    mono: Instant,
+    wall_ns: i64,
+}
+
+pub struct Clock {
+    start: Instant,
+    anchor: RwLock<Anchor>,
+    /// Last time handed out and the next sequence number.
+    state: Mutex<(i64, u64)>,
+}
+
+fn system_ns() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as i64).unwrap_or(0)
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            anchor: RwLock::new(Anchor { mono: now, wall_ns: system_ns() }),
+            state: Mutex::new((0, 0)),
+        }
+    }
+
+    fn raw_ns(&self) -> i64 {
+        let a = *self.anchor.read().unwrap();
+        a.wall_ns + a.mono.elapsed().as_nanos() as i64
+    }
+
+    /// The next strictly increasing time, given the last one handed out.
+    fn next_ns(&self, last: &mut i64) -> i64 {
+        *last = self.raw_ns().max(*last + 1);
+        *last
+    }
+
+    /// Strictly increasing nanosecond wall-clock time.
+    pub fn now_ns(&self) -> i64 {
+        let mut st = self.state.lock().unwrap();
+        self.next_ns(&mut st.0)
+    }
+
+    pub fn stamp(&self) -> Stamp {
+        let mut st = self.state.lock().unwrap();
+        let ts_ns = self.next_ns(&mut st.0);
+        let recv_seq = st.1;
+        st.1 += 1;
+        Stamp { ts_ns, recv_seq }
+    }
+
+    pub fn reanchor(&self) -> ClockAnchorSample {
+        let mut a = self.anchor.write().unwrap();
+        let mono = Instant::now();
+        let mono_ns = a.wall_ns + mono.duration_since(a.mono).as_nanos() as i64;
+        let wall_ns = system_ns();
+        *a = Anchor { mono, wall_ns };
+        ClockAnchorSample {
+            mono_ns,
+            wall_ns,
+            drift_ns: wall_ns - mono_ns,
+            uptime_ns: mono.duration_since(self.start).as_nanos() as i64,
+        }
+    }
+}
+
+static CLOCK: LazyLock<Clock> = LazyLock::new(Clock::new);
+
+/// The process clock.
+pub fn clock() -> &'static Clock {
+    &CLOCK
+}
+
+#[inline]
+pub fn stamp() -> Stamp {
+    CLOCK.stamp()
+}
+
+#[inline]
+pub fn now_ns() -> i64 {
+    CLOCK.now_ns()
+}
+
+/// Unix ms from user input: an integer (already ms), an RFC 3339 time or a
+/// `YYYY-MM-DD` date (its UTC midnight).
+pub fn parse_time_ms(s: &str) -> anyhow::Result<i64> {
+    let s = s.trim();
+    if let Ok(ms) = s.parse::<i64>() {
+        return Ok(ms);
+    }
+    let t = match time::OffsetDateTime::parse(s, &time::format_description::well_known::Rfc3339) {
+        Ok(t) => t,
+        Err(_) => {
+            let fmt = time::macros::format_description!("[year]-[month]-[day]");
+            time::Date::parse(s, &fmt)
+                .map_err(|_| anyhow::anyhow!("{s:?}: expected unix ms, RFC 3339 or YYYY-MM-DD"))?
+                .midnight()
+                .assume_utc()
+        }
+    };
+    Ok((t.unix_timestamp_nanos() / 1_000_000) as i64)
+}
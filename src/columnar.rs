@@ -0,0 +1,335 @@
+// columnar.rs
+//
+// Parquet tables, one per event kind, written next to `events.ndjson.zst` in
+// the same `symbol=/date=/hour=` partition (`<dir>/<kind>.parquet`). A file
+// only ever covers one hour, so row groups never straddle a partition.
+//
+// Files are written as `<name>.parquet.inprogress` and renamed when closed
+// (hour rollover or shutdown); readers never see a file without a footer. A
+// restart within the same hour adds `<kind>.1.parquet`, `<kind>.2.parquet`...
+use anyhow::Result;
+use arrow::array::{
+    ArrayRef, BinaryBuilder, Float64Builder, Int64Builder, ListBuilder, StringBuilder, UInt64Builder,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Rows are handed to the arrow writer in batches of this size.
+const BATCH_ROWS: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
+enum Col {
+    I64(&'static str),
+    U64(&'static str),
+    F64(&'static str),
+    Str(&'static str),
+    /// `[[price, qty], ...]` split into `<name>_price` / `<name>_qty` list columns.
+    Levels(&'static str),
+    /// Whole payload as a JSON string; for kinds without a typed table.
+    Json,
+    /// Raw frame bytes.
+    Bytes,
+}
+
+use Col::*;
+
+const DEPTH_DELTA: &[Col] = &[I64("ts_recv_ms"), U64("from_version"), U64("to_version"), Levels("bids"), Levels("asks")];
+const DEPTH_SNAPSHOT: &[Col] = &[I64("ts_recv_ms"), U64("last_update_id"), Levels("bids"), Levels("asks")];
+const TRADE: &[Col] = &[
+    I64("ts_recv_ms"),
+    U64("id"),
+    F64("price"),
+    F64("qty"),
+    Str("side"),
+    I64("ts_exch_ms"),
+];
+const BOOK_TICKER: &[Col] = &[I64("ts_recv_ms"), F64("bid_price"), F64("bid_qty"), F64("ask_price"), F64("ask_qty")];
+/// `depth_pb_raw` frames decoded offline by the converter.
+const DEPTH_PB: &[Col] = &[
+    Str("channel"),
+    I64("send_time_ms"),
+    Str("event_type"),
+    U64("from_version"),
+    U64("to_version"),
+    Levels("bids"),
+    Levels("asks"),
+];
+const RAW: &[Col] = &[Bytes];
+const OTHER: &[Col] = &[Json];
+
+fn table_cols(kind: &str) -> &'static [Col] {
+    match kind {
+        "depth_delta" => DEPTH_DELTA,
+        "depth_snapshot" => DEPTH_SNAPSHOT,
+        "trade" => TRADE,
+        "book_ticker" => BOOK_TICKER,
+        "depth_pb" => DEPTH_PB,
+        "depth_pb_raw" => RAW,
+        _ => OTHER,
+    }
+}
+
+fn schema_for(cols: &[Col]) -> SchemaRef {
+    let list_f64 = || DataType::List(Arc::new(Field::new("item", DataType::Float64, true)));
+    let mut fields = vec![
+        Field::new("ts_ms", DataType::Int64, false),
+        Field::new("ts_ns", DataType::Int64, true),
+        Field::new("recv_seq", DataType::UInt64, true),
+        Field::new("seq", DataType::UInt64, true),
+        Field::new("symbol", DataType::Utf8, false),
+    ];
+    for c in cols {
+        match *c {
+            I64(n) => fields.push(Field::new(n, DataType::Int64, true)),
+            U64(n) => fields.push(Field::new(n, DataType::UInt64, true)),
+            F64(n) => fields.push(Field::new(n, DataType::Float64, true)),
+            Str(n) => fields.push(Field::new(n, DataType::Utf8, true)),
+            Levels(n) => {
+                fields.push(Field::new(format!("{n}_price"), list_f64(), true));
+                fields.push(Field::new(format!("{n}_qty"), list_f64(), true));
+            }
+            Json => fields.push(Field::new("payload", DataType::Utf8, true)),
+            Bytes => fields.push(Field::new("payload", DataType::Binary, true)),
+        }
+    }
+    Arc::new(Schema::new(fields))
+}
+
+#[derive(Debug, Clone)]
+pub enum RowPayload {
+    Json(serde_json::Value),
+    Raw(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub ts_ms: i64,
+    pub ts_ns: Option<i64>,
+    pub recv_seq: Option<u64>,
+    pub seq: Option<u64>,
+    pub symbol: String,
+    pub payload: RowPayload,
+}
+
+impl Row {
+    fn field(&self, name: &str) -> Option<&serde_json::Value> {
+        match &self.payload {
+            RowPayload::Json(v) => v.get(name).filter(|v| !v.is_null()),
+            RowPayload::Raw(_) => None,
+        }
+    }
+}
+
+fn build_batch(schema: &SchemaRef, cols: &[Col], rows: &[Row]) -> Result<RecordBatch> {
+    let mut arrays: Vec<ArrayRef> = Vec::with_capacity(schema.fields().len());
+    let mut ts = Int64Builder::with_capacity(rows.len());
+    let mut ts_ns = Int64Builder::with_capacity(rows.len());
+    let mut recv_seq = UInt64Builder::with_capacity(rows.len());
+    let mut seq = UInt64Builder::with_capacity(rows.len());
+    let mut sym = StringBuilder::new();
+    for r in rows {
+        ts.append_value(r.ts_ms);
+        ts_ns.append_option(r.ts_ns);
+        recv_seq.append_option(r.recv_seq);
+        seq.append_option(r.seq);
+        sym.append_value(&r.symbol);
+    }
+    arrays.push(Arc::new(ts.finish()));
+    arrays.push(Arc::new(ts_ns.finish()));
+    arrays.push(Arc::new(recv_seq.finish()));
+    arrays.push(Arc::new(seq.finish()));
+    arrays.push(Arc::new(sym.finish()));
+
+    for c in cols {
+        match *c {
+            I64(n) => {
+                let mut b = Int64Builder::with_capacity(rows.len());
+                rows.iter().for_each(|r| b.append_option(r.field(n).and_then(|v| v.as_i64())));
+                arrays.push(Arc::new(b.finish()));
+            }
+            U64(n) => {
+                let mut b = UInt64Builder::with_capacity(rows.len());
+                rows.iter().for_each(|r| b.append_option(r.field(n).and_then(|v| v.as_u64())));
+                arrays.push(Arc::new(b.finish()));
+            }
+            F64(n) => {
+                let mut b = Float64Builder::with_capacity(rows.len());
+                rows.iter().for_each(|r| b.append_option(r.field(n).and_then(|v| v.as_f64())));
+                arrays.push(Arc::new(b.finish()));
+            }
+            Str(n) => {
+                let mut b = StringBuilder::new();
+                for r in rows {
+                    match r.field(n) {
+                        Some(serde_json::Value::String(s)) => b.append_value(s),
+                        Some(v) => b.append_value(v.to_string()),
+                        None => b.append_null(),
+                    }
+                }
+                arrays.push(Arc::new(b.finish()));
+            }
+            Levels(n) => {
+                let mut px = ListBuilder::new(Float64Builder::new());
+                let mut qty = ListBuilder::new(Float64Builder::new());
+                for r in rows {
+                    match r.field(n).and_then(|v| v.as_array()) {
+                        Some(levels) => {
+                            for l in levels {
+                                px.values().append_option(l.get(0).and_then(|v| v.as_f64()));
+                                qty.values().append_option(l.get(1).and_then(|v| v.as_f64()));
+                            }
+                            px.append(true);
+                            qty.append(true);
+                        }
+                        None => {
+                            px.append(false);
+                            qty.append(false);
+                        }
+                    }
+                }
+                arrays.push(Arc::new(px.finish()));
+                arrays.push(Arc::new(qty.finish()));
+            }
+            Json => {
+                let mut b = StringBuilder::new();
+                for r in rows {
+                    match &r.payload {
+                        RowPayload::Json(v) => b.append_value(v.to_string()),
+                        RowPayload::Raw(_) => b.append_null(),
+                    }
+                }
+                arrays.push(Arc::new(b.finish()));
+            }
+            Bytes => {
+                let mut b = BinaryBuilder::new();
+                for r in rows {
+                    match &r.payload {
+                        RowPayload::Raw(v) => b.append_value(v),
+                        RowPayload::Json(_) => b.append_null(),
+                    }
+                }
+                arrays.push(Arc::new(b.finish()));
+            }
+        }
+    }
+    Ok(RecordBatch::try_new(schema.clone(), arrays)?)
+}
+
+struct OpenTable {
+    dir: PathBuf,
+    tmp: PathBuf,
+    dst: PathBuf,
+    cols: &'static [Col],
+    schema: SchemaRef,
+    writer: ArrowWriter<File>,
+    rows: Vec<Row>,
+}
+
+impl OpenTable {
+    fn write_pending(&mut self) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let batch = build_batch(&self.schema, self.cols, &self.rows)?;
+        self.writer.write(&batch)?;
+        self.rows.clear();
+        Ok(())
+    }
+
+    fn close(mut self) -> Result<PathBuf> {
+        self.write_pending()?;
+        self.writer.close()?;
+        std::fs::rename(&self.tmp, &self.dst)?;
+        Ok(self.dst)
+    }
+}
+
+/// Long-lived parquet writers keyed by (symbol, kind), rolled per partition dir.
+pub struct ParquetSink {
+    row_group_rows: usize,
+    open: HashMap<(String, String), OpenTable>,
+}
+
+impl ParquetSink {
+    pub fn new(row_group_rows: usize) -> Self {
+        Self { row_group_rows, open: HashMap::new() }
+    }
+
+    fn open_table(&self, dir: &Path, kind: &str) -> Result<OpenTable> {
+        create_dir_all(dir)?;
+        let mut dst = dir.join(format!("{kind}.parquet"));
+        let mut n = 0;
+        while dst.exists() || dst.with_extension("parquet.inprogress").exists() {
+            n += 1;
+            dst = dir.join(format!("{kind}.{n}.parquet"));
+        }
+        let tmp = dst.with_extension("parquet.inprogress");
+        let cols = table_cols(kind);
+        let schema = schema_for(cols);
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::try_new(3)?))
+            .set_max_row_group_size(self.row_group_rows)
+            .build();
+        let writer = ArrowWriter::try_new(File::create(&tmp)?, schema.clone(), Some(props))?;
+        Ok(OpenTable { dir: dir.to_path_buf(), tmp, dst, cols, schema, writer, rows: Vec::with_capacity(BATCH_ROWS) })
+    }
+
+    /// `dir` is the partition directory the row belongs to; a different dir
+    /// than the open file's closes that file first.
+    pub fn append(&mut self, dir: &Path, kind: &str, row: Row) -> Result<()> {
+        let key = (row.symbol.clone(), kind.to_string());
+        if self.open.get(&key).is_some_and(|t| t.dir != dir) {
+            if let Some(t) = self.open.remove(&key) {
+                t.close()?;
+            }
+        }
+        let t = match self.open.get_mut(&key) {
+            Some(t) => t,
+            None => {
+                let t = self.open_table(dir, kind)?;
+                self.open.entry(key).or_insert(t)
+            }
+        };
+        t.rows.push(row);
+        if t.rows.len() >= BATCH_ROWS {
+            t.write_pending()?;
+        }
+        Ok(())
+    }
+
+    /// Closes the files open in partition `dir`, if any.
+    pub fn close_dir(&mut self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let keys: Vec<_> = self.open.iter().filter(|(_, t)| t.dir == dir).map(|(k, _)| k.clone()).collect();
+        let mut done = Vec::new();
+        for k in keys {
+            if let Some(t) = self.open.remove(&k) {
+                done.push(t.close()?);
+            }
+        }
+        Ok(done)
+    }
+
+    /// Closes every open file; returns the paths written.
+    pub fn close_all(&mut self) -> Result<Vec<PathBuf>> {
+        let mut done = Vec::new();
+        for (_, t) in self.open.drain() {
+            done.push(t.close()?);
+        }
+        Ok(done)
+    }
+}
+
+impl Drop for ParquetSink {
+    fn drop(&mut self) {
+        if let Err(e) = self.close_all() {
+            tracing::error!(error = %e, "parquet close failed");
+        }
+    }
+}
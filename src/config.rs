@@ -0,0 +1,469 @@
+// config.rs
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::book::CrossedPolicy;
+use crate::sampler::SampleFormat;
+use crate::store::{RawCapture, StorageFormat};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub exchange: ExchangeConfig,
+    #[serde(default)]
+    pub websocket: WebsocketConfig,
+    #[serde(default)]
+    pub rest: RestConfig,
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub shm: ShmConfig,
+    #[serde(default)]
+    pub multicast: MulticastConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub validation: ValidationConfig,
+    #[serde(default)]
+    pub book: BookConfig,
+    #[serde(default)]
+    pub listings: ListingsConfig,
+    #[serde(default)]
+    pub features: FeaturesConfig,
+    #[serde(default)]
+    pub sampler: SamplerConfig,
+    #[serde(default)]
+    pub align: AlignConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+}
+
+/// Base URLs; point both at `mock::MockExchange` to run offline.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExchangeConfig {
+    /// Recorded by `record` unless `--symbols` is given.
+    #[serde(default = "default_symbols")]
+    pub symbols: Vec<String>,
+    #[serde(default = "default_rest_url")]
+    pub rest_url: String,
+    /// Sent as `X-MEXC-APIKEY`; falls back to the `MEXC_API_KEY` environment variable.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// How often symbol metadata is re-read from `/api/v3/exchangeInfo`.
+    #[serde(default = "default_info_refresh_secs")]
+    pub info_refresh_secs: u64,
+}
+
+impl Default for ExchangeConfig {
+    fn default() -> Self {
+        Self {
+            symbols: default_symbols(),
+            rest_url: default_rest_url(),
+            api_key: None,
+            info_refresh_secs: default_info_refresh_secs(),
+        }
+    }
+}
+
+fn default_symbols() -> Vec<String> { vec!["BTCUSDT".to_string()] }
+fn default_rest_url() -> String { "https://api.mexc.com".to_string() }
+fn default_info_refresh_secs() -> u64 { 3600 }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebsocketConfig {
+    #[serde(default = "default_ws_url")]
+    pub url: String,
+    /// First reconnect delay after the depth socket drops, doubled per failed
+    /// attempt up to `max_reconnect_backoff_ms`.
+    #[serde(default = "default_ws_reconnect_backoff_ms")]
+    pub reconnect_backoff_ms: u64,
+    #[serde(default = "default_ws_max_reconnect_backoff_ms")]
+    pub max_reconnect_backoff_ms: u64,
+}
+
+impl Default for WebsocketConfig {
+    fn default() -> Self {
+        Self {
+            url: default_ws_url(),
+            reconnect_backoff_ms: default_ws_reconnect_backoff_ms(),
+            max_reconnect_backoff_ms: default_ws_max_reconnect_backoff_ms(),
+        }
+    }
+}
+
+fn default_ws_url() -> String { "wss://wbs-api.mexc.com/ws".to_string() }
+fn default_ws_reconnect_backoff_ms() -> u64 { 500 }
+fn default_ws_max_reconnect_backoff_ms() -> u64 { 30_000 }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RestConfig {
+    #[serde(default = "default_rest_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Extra attempts on network errors, 429/418 and 5xx.
+    #[serde(default = "default_rest_max_retries")]
+    pub max_retries: u32,
+    /// First retry delay, doubled per attempt up to `max_backoff_ms`; `Retry-After` wins.
+    #[serde(default = "default_rest_backoff_ms")]
+    pub backoff_ms: u64,
+    #[serde(default = "default_rest_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Request weight allowed per `weight_window_secs`, shared by every symbol.
+    #[serde(default = "default_rest_weight_limit")]
+    pub weight_limit: u32,
+    #[serde(default = "default_rest_weight_window_secs")]
+    pub weight_window_secs: u64,
+}
+
+impl Default for RestConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: default_rest_timeout_ms(),
+            max_retries: default_rest_max_retries(),
+            backoff_ms: default_rest_backoff_ms(),
+            max_backoff_ms: default_rest_max_backoff_ms(),
+            weight_limit: default_rest_weight_limit(),
+            weight_window_secs: default_rest_weight_window_secs(),
+        }
+    }
+}
+
+fn default_rest_timeout_ms() -> u64 { 5000 }
+fn default_rest_max_retries() -> u32 { 3 }
+fn default_rest_backoff_ms() -> u64 { 250 }
+fn default_rest_max_backoff_ms() -> u64 { 10_000 }
+fn default_rest_weight_limit() -> u32 { 500 }
+fn default_rest_weight_window_secs() -> u64 { 10 }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageConfig {
+    /// Data root; `--data-dir` overrides it.
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
+    #[serde(default)]
+    pub format: StorageFormat,
+    #[serde(default = "default_parquet_row_group_rows")]
+    pub parquet_row_group_rows: usize,
+    #[serde(default)]
+    pub raw_capture: RawCapture,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: default_data_dir(),
+            format: StorageFormat::default(),
+            parquet_row_group_rows: default_parquet_row_group_rows(),
+            raw_capture: RawCapture::default(),
+        }
+    }
+}
+
+fn default_data_dir() -> String { "data".to_string() }
+fn default_parquet_row_group_rows() -> usize { 65536 }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_server_bind")]
+    pub bind: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self { enabled: false, bind: default_server_bind() }
+    }
+}
+
+fn default_server_bind() -> String { "127.0.0.1:8765".to_string() }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShmConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_shm_dir")]
+    pub dir: String,
+    #[serde(default = "default_shm_levels")]
+    pub levels: usize,
+}
+
+impl Default for ShmConfig {
+    fn default() -> Self {
+        Self { enabled: false, dir: default_shm_dir(), levels: default_shm_levels() }
+    }
+}
+
+fn default_shm_dir() -> String { "/dev/shm".to_string() }
+fn default_shm_levels() -> usize { 10 }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MulticastConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_mcast_group")]
+    pub group: String,
+    #[serde(default = "default_mcast_interface")]
+    pub interface: String,
+    #[serde(default = "default_mcast_ttl")]
+    pub ttl: u32,
+    #[serde(default = "default_mcast_snapshot_interval_ms")]
+    pub snapshot_interval_ms: u64,
+    #[serde(default = "default_mcast_snapshot_levels")]
+    pub snapshot_levels: usize,
+    #[serde(default = "default_mcast_retransmit_bind")]
+    pub retransmit_bind: String,
+    #[serde(default = "default_mcast_retransmit_buffer")]
+    pub retransmit_buffer: usize,
+}
+
+impl Default for MulticastConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            group: default_mcast_group(),
+            interface: default_mcast_interface(),
+            ttl: default_mcast_ttl(),
+            snapshot_interval_ms: default_mcast_snapshot_interval_ms(),
+            snapshot_levels: default_mcast_snapshot_levels(),
+            retransmit_bind: default_mcast_retransmit_bind(),
+            retransmit_buffer: default_mcast_retransmit_buffer(),
+        }
+    }
+}
+
+fn default_mcast_group() -> String { "239.255.42.1:30001".to_string() }
+fn default_mcast_interface() -> String { "127.0.0.1".to_string() }
+fn default_mcast_ttl() -> u32 { 1 }
+fn default_mcast_snapshot_interval_ms() -> u64 { 1000 }
+fn default_mcast_snapshot_levels() -> usize { 20 }
+fn default_mcast_retransmit_bind() -> String { "127.0.0.1:30002".to_string() }
+fn default_mcast_retransmit_buffer() -> usize { 65536 }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ValidationConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_validation_interval_secs")]
+    pub interval_secs: u64,
+    /// REST `limit`; levels are compared within the price range it covers.
+    #[serde(default = "default_validation_depth")]
+    pub depth: usize,
+    /// Resync when more than this fraction of compared levels differ.
+    #[serde(default = "default_validation_max_mismatch_ratio")]
+    pub max_mismatch_ratio: f64,
+    /// Applied deltas kept to roll a (lagging) REST snapshot forward.
+    #[serde(default = "default_validation_buffer_deltas")]
+    pub buffer_deltas: usize,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: default_validation_interval_secs(),
+            depth: default_validation_depth(),
+            max_mismatch_ratio: default_validation_max_mismatch_ratio(),
+            buffer_deltas: default_validation_buffer_deltas(),
+        }
+    }
+}
+
+fn default_validation_interval_secs() -> u64 { 60 }
+fn default_validation_depth() -> usize { 100 }
+fn default_validation_max_mismatch_ratio() -> f64 { 0.05 }
+fn default_validation_buffer_deltas() -> usize { 5000 }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BookConfig {
+    #[serde(default)]
+    pub crossed_policy: CrossedPolicy,
+    /// Crossed updates accepted under `tolerate` before resyncing.
+    #[serde(default = "default_crossed_tolerate_updates")]
+    pub crossed_tolerate_updates: u32,
+}
+
+impl Default for BookConfig {
+    fn default() -> Self {
+        Self { crossed_policy: CrossedPolicy::default(), crossed_tolerate_updates: default_crossed_tolerate_updates() }
+    }
+}
+
+fn default_crossed_tolerate_updates() -> u32 { 10 }
+
+/// What to do about symbols that appear in `/api/v3/exchangeInfo` while recording.
+/// Delisted symbols always stop; paused ones stop until they are back online.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListingsConfig {
+    /// Start recording new listings whose quote asset is in `quote_assets`.
+    #[serde(default)]
+    pub auto_record: bool,
+    #[serde(default = "default_listings_quote_assets")]
+    pub quote_assets: Vec<String>,
+    /// Auto-started symbols are not added beyond this many recorded symbols.
+    #[serde(default = "default_listings_max_symbols")]
+    pub max_symbols: usize,
+}
+
+impl Default for ListingsConfig {
+    fn default() -> Self {
+        Self { auto_record: false, quote_assets: default_listings_quote_assets(), max_symbols: default_listings_max_symbols() }
+    }
+}
+
+fn default_listings_quote_assets() -> Vec<String> { vec!["USDT".to_string()] }
+fn default_listings_max_symbols() -> usize { 50 }
+
+/// Order flow and microstructure features (`features::FeatureEngine`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeaturesConfig {
+    /// Compute features while recording; stored as `features` events and
+    /// served on `features.<SYMBOL>`. The `features` command ignores this.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Levels per side for depth imbalance, weighted mid and slope.
+    #[serde(default = "default_features_levels")]
+    pub levels: usize,
+    /// Rolling windows for OFI, trade volume, VWAP and realized volatility.
+    #[serde(default = "default_features_windows_ms")]
+    pub windows_ms: Vec<u64>,
+    /// At most one row per this many ms of book time; 0 emits on every book update.
+    #[serde(default = "default_features_interval_ms")]
+    pub interval_ms: u64,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            levels: default_features_levels(),
+            windows_ms: default_features_windows_ms(),
+            interval_ms: default_features_interval_ms(),
+        }
+    }
+}
+
+fn default_features_levels() -> usize { 5 }
+fn default_features_windows_ms() -> Vec<u64> { vec![1000, 10_000, 60_000] }
+fn default_features_interval_ms() -> u64 { 100 }
+
+/// Fixed-interval book samples (`sampler::Sampler`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct SamplerConfig {
+    /// Sample while recording, into hourly files under `dir`. The `sample`
+    /// command ignores this.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_sampler_interval_ms")]
+    pub interval_ms: u64,
+    /// Levels per side.
+    #[serde(default = "default_sampler_levels")]
+    pub levels: usize,
+    #[serde(default)]
+    pub format: SampleFormat,
+    #[serde(default = "default_sampler_dir")]
+    pub dir: String,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_ms: default_sampler_interval_ms(),
+            levels: default_sampler_levels(),
+            format: SampleFormat::default(),
+            dir: default_sampler_dir(),
+        }
+    }
+}
+
+fn default_sampler_interval_ms() -> u64 { 1000 }
+fn default_sampler_levels() -> usize { 10 }
+fn default_sampler_dir() -> String { "samples".to_string() }
+
+/// Trade-to-book alignment (`align::Aligner`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlignConfig {
+    /// Align trades while recording; stored as `trade_aligned` events. The
+    /// `align` command ignores this.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Levels per side kept per book state.
+    #[serde(default = "default_align_levels")]
+    pub levels: usize,
+    /// How far back book states are kept; trades older than that go unaligned.
+    #[serde(default = "default_align_history_ms")]
+    pub history_ms: u64,
+}
+
+impl Default for AlignConfig {
+    fn default() -> Self {
+        Self { enabled: false, levels: default_align_levels(), history_ms: default_align_history_ms() }
+    }
+}
+
+fn default_align_levels() -> usize { 20 }
+fn default_align_history_ms() -> u64 { 10_000 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoggingConfig {
+    /// `TRACE`..`ERROR`, or any `RUST_LOG`-style filter.
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    #[serde(default)]
+    pub format: LogFormat,
+    #[serde(default)]
+    pub save_logs: bool,
+    #[serde(default = "default_log_file_path")]
+    pub log_file_path: String,
+    /// Truncate `log_file_path` at start instead of rotating.
+    #[serde(default)]
+    pub rewrite_last_logs: bool,
+    #[serde(default)]
+    pub rotation: LogRotation,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            format: LogFormat::default(),
+            save_logs: false,
+            log_file_path: default_log_file_path(),
+            rewrite_last_logs: false,
+            rotation: LogRotation::default(),
+        }
+    }
+}
+
+fn default_log_level() -> String { "info".to_string() }
+fn default_log_file_path() -> String { "logs/app.log".to_string() }
+
+impl Config {
+    /// Missing file means defaults; a file that exists but does not parse is an error.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+}
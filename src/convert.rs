@@ -0,0 +1,240 @@
+// convert.rs
+//
+// Offline NDJSON -> Parquet conversion of an existing data root. The output
+// mirrors the `symbol=/date=/hour=` layout with one table per kind (see
+// `columnar`); `depth_pb_raw` frames are decoded into a typed `depth_pb`
+// table, frames that are not aggregated depth stay raw in `depth_pb_raw`.
+// Frames captured in `raw.pb.zst` are decoded the same way.
+//
+// Each finished partition gets `_manifest.json`, written last; a partition
+// with a manifest is skipped on the next run, one without is redone from
+// scratch.
+use anyhow::{anyhow, Result};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tracing::{error, info};
+
+use crate::clock;
+use crate::columnar::{ParquetSink, Row, RowPayload};
+use crate::mexc_pb::{push_data_v3_api_wrapper::Body, PushDataV3ApiWrapper};
+use crate::rawlog::read_raw_frames;
+use crate::store::{list_partitions, read_events, Partition};
+
+pub const MANIFEST_NAME: &str = "_manifest.json";
+
+#[derive(Debug, Clone)]
+pub struct ConvertOptions {
+    pub jobs: usize,
+    pub row_group_rows: usize,
+    /// Also convert the hour that is still being recorded.
+    pub include_open_hour: bool,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self { jobs: 4, row_group_rows: 65536, include_open_hour: false }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConvertManifest {
+    pub sources: Vec<String>,
+    pub source_bytes: u64,
+    pub converted_at_ms: i64,
+    pub rows: BTreeMap<String, u64>,
+    pub min_ts_ms: Option<i64>,
+    pub max_ts_ms: Option<i64>,
+    pub bad_lines: u64,
+    /// The source ended in the middle of a zstd frame or line.
+    pub truncated: bool,
+    pub files: Vec<String>,
+}
+
+impl ConvertManifest {
+    fn note(&mut self, kind: &str, ts_ms: i64) {
+        self.min_ts_ms = Some(self.min_ts_ms.map_or(ts_ms, |t| t.min(ts_ms)));
+        self.max_ts_ms = Some(self.max_ts_ms.map_or(ts_ms, |t| t.max(ts_ms)));
+        *self.rows.entry(kind.to_string()).or_default() += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ConvertSummary {
+    pub converted: usize,
+    pub skipped: usize,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+pub fn convert_all<P: AsRef<Path>, Q: AsRef<Path>>(src: P, out: Q, opts: &ConvertOptions) -> Result<ConvertSummary> {
+    let src = src.as_ref();
+    let out = out.as_ref();
+    std::fs::create_dir_all(out)?;
+    if std::fs::canonicalize(src)? == std::fs::canonicalize(out)? {
+        return Err(anyhow!("output root must differ from the data root"));
+    }
+
+    let now = clock::stamp().ms();
+    let mut todo = Vec::new();
+    let mut summary = ConvertSummary::default();
+    for part in list_partitions(src)? {
+        let done = out.join(part.rel_dir()).join(MANIFEST_NAME).exists();
+        let end_ms = match part.end_ms() {
+            Ok(t) => t,
+            Err(e) => {
+                error!(partition = %part.dir.display(), error = %e, "convert failed");
+                summary.failed.push((part.dir.clone(), e.to_string()));
+                continue;
+            }
+        };
+        let open = end_ms > now && !opts.include_open_hour;
+        let has_data = part.events_path().exists() || part.raw_path().exists();
+        if done || open || !has_data {
+            summary.skipped += 1;
+        } else {
+            todo.push(part);
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    let summary = Mutex::new(summary);
+    std::thread::scope(|s| {
+        for _ in 0..opts.jobs.max(1) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(part) = todo.get(i) else { break };
+                let dst = out.join(part.rel_dir());
+                match convert_partition(part, &dst, opts.row_group_rows) {
+                    Ok(m) => {
+                        info!(
+                            partition = %part.rel_dir().display(),
+                            rows = m.rows.values().sum::<u64>(),
+                            truncated = m.truncated,
+                            "converted"
+                        );
+                        summary.lock().unwrap().converted += 1;
+                    }
+                    Err(e) => {
+                        error!(partition = %part.rel_dir().display(), error = %e, "convert failed");
+                        summary.lock().unwrap().failed.push((part.dir.clone(), e.to_string()));
+                    }
+                }
+            });
+        }
+    });
+    Ok(summary.into_inner().unwrap())
+}
+
+pub fn convert_partition(part: &Partition, dst: &Path, row_group_rows: usize) -> Result<ConvertManifest> {
+    std::fs::create_dir_all(dst)?;
+    // leftovers of an interrupted run
+    for e in std::fs::read_dir(dst)? {
+        let p = e?.path();
+        let name = p.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        if name.ends_with(".parquet") || name.ends_with(".inprogress") {
+            std::fs::remove_file(&p)?;
+        }
+    }
+
+    let mut m = ConvertManifest::default();
+    let mut sink = ParquetSink::new(row_group_rows);
+
+    let raw_src = part.raw_path();
+    if raw_src.exists() {
+        m.sources.push(raw_src.display().to_string());
+        m.source_bytes += std::fs::metadata(&raw_src)?.len();
+        for f in read_raw_frames(&raw_src)? {
+            let Ok(f) = f else {
+                m.truncated = true;
+                break;
+            };
+            let ts_ms = f.recv_ts_ns.div_euclid(1_000_000);
+            let (kind, payload) = match decode_depth_pb(&f.data) {
+                Some(v) => ("depth_pb", RowPayload::Json(v)),
+                None => ("depth_pb_raw", RowPayload::Raw(f.data)),
+            };
+            m.note(kind, ts_ms);
+            let row = Row { ts_ms, ts_ns: Some(f.recv_ts_ns), recv_seq: None, seq: None, symbol: part.symbol.clone(), payload };
+            sink.append(dst, kind, row)?;
+        }
+    }
+
+    let src = part.events_path();
+    let events = if src.exists() {
+        m.sources.push(src.display().to_string());
+        m.source_bytes += std::fs::metadata(&src)?.len();
+        Some(read_events(&src)?)
+    } else {
+        None
+    };
+    for ev in events.into_iter().flatten() {
+        let ev = match ev {
+            Ok(ev) => ev,
+            Err(e) if e.downcast_ref::<std::io::Error>().is_some() => {
+                m.truncated = true;
+                break;
+            }
+            Err(_) => {
+                m.bad_lines += 1;
+                continue;
+            }
+        };
+        let (kind, payload) = match (ev.payload, &ev.payload_b64) {
+            (Some(v), _) => (ev.kind.clone(), RowPayload::Json(v)),
+            (None, Some(b64)) => {
+                use base64::Engine as _;
+                let Ok(raw) = base64::engine::general_purpose::STANDARD.decode(b64) else {
+                    m.bad_lines += 1;
+                    continue;
+                };
+                match decode_depth_pb(&raw) {
+                    Some(v) => ("depth_pb".to_string(), RowPayload::Json(v)),
+                    None => (ev.kind.clone(), RowPayload::Raw(raw)),
+                }
+            }
+            (None, None) => {
+                m.bad_lines += 1;
+                continue;
+            }
+        };
+        m.note(&kind, ev.ts_ms);
+        let row = Row { ts_ms: ev.ts_ms, ts_ns: ev.ts_ns, recv_seq: ev.recv_seq, seq: ev.seq, symbol: ev.symbol, payload };
+        sink.append(dst, &kind, row)?;
+    }
+
+    m.files = sink
+        .close_all()?
+        .iter()
+        .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .collect();
+    m.files.sort();
+    m.converted_at_ms = clock::stamp().ms();
+    let tmp = dst.join(format!("{MANIFEST_NAME}.tmp"));
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&m)?)?;
+    std::fs::rename(tmp, dst.join(MANIFEST_NAME))?;
+    Ok(m)
+}
+
+/// Aggregated depth frames as a JSON row for the `depth_pb` table; `None` for anything else.
+fn decode_depth_pb(raw: &[u8]) -> Option<serde_json::Value> {
+    let w = PushDataV3ApiWrapper::decode(raw).ok()?;
+    let Some(Body::PublicAggreDepths(d)) = w.body else { return None };
+    let levels = |items: &[crate::mexc_pb::PublicAggreDepthV3ApiItem]| -> Vec<[f64; 2]> {
+        items
+            .iter()
+            .map(|it| [it.price.parse().unwrap_or(f64::NAN), it.quantity.parse().unwrap_or(f64::NAN)])
+            .collect()
+    };
+    Some(serde_json::json!({
+        "channel": w.channel,
+        "send_time_ms": w.send_time,
+        "event_type": d.event_type,
+        "from_version": d.from_version.parse::<u64>().ok(),
+        "to_version": d.to_version.parse::<u64>().ok(),
+        "bids": levels(&d.bids),
+        "asks": levels(&d.asks),
+    }))
+}
@@ -0,0 +1,131 @@
+// dashboard.rs
+//
+// Terminal dashboard over the shared-memory top-of-book regions (`shm`), so it
+// runs as a separate process and costs the recorder nothing. Redraws the whole
+// screen with plain ANSI escapes at a human rate.
+use anyhow::{anyhow, Result};
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::clock;
+use crate::shm::{ShmReader, TopOfBook};
+
+const PREFIX: &str = "mexc-tob-";
+
+struct Row {
+    reader: ShmReader,
+    tob: TopOfBook,
+    last_seq: u64,
+    last_at: Instant,
+    rate: f64,
+}
+
+/// Symbols with a region in `dir`, sorted.
+pub fn discover<P: AsRef<Path>>(dir: P) -> Result<Vec<String>> {
+    let mut out = Vec::new();
+    for e in std::fs::read_dir(dir)? {
+        let name = e?.file_name();
+        if let Some(sym) = name.to_str().and_then(|n| n.strip_prefix(PREFIX)) {
+            out.push(sym.to_string());
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+fn render(rows: &[Row]) -> String {
+    let now_ms = clock::now_ns() / 1_000_000;
+    let mut s = String::from("\x1b[H\x1b[2J");
+    let _ = writeln!(
+        s,
+        "{:<12} {:>14} {:>14} {:>9} {:>8} {:>9} {:>9} {:>9} {:>6} {:>7} {:>8}",
+        "symbol", "bid", "ask", "sprd bps", "imbal", "upd/s", "lat ms", "lag ms", "gaps", "resync", "age ms"
+    );
+    for r in rows {
+        let t = &r.tob;
+        let (gaps, resyncs) = r.reader.counters();
+        let (Some(bid), Some(ask)) = (t.bids.first(), t.asks.first()) else {
+            let _ = writeln!(s, "{:<12} (no book yet)", r.reader.symbol());
+            continue;
+        };
+        let mid = (bid[0] + ask[0]) / 2.0;
+        let bps = (ask[0] - bid[0]) / mid * 1e4;
+        let bq: f64 = t.bids.iter().map(|l| l[1]).sum();
+        let aq: f64 = t.asks.iter().map(|l| l[1]).sum();
+        let imbalance = if bq + aq > 0.0 { (bq - aq) / (bq + aq) } else { 0.0 };
+        let latency = t.ts_exch_ms.map(|x| (t.ts_recv_ms - x).to_string()).unwrap_or_else(|| "-".into());
+        let lag = t.ts_write_ns as f64 / 1e6 - t.ts_recv_ms as f64;
+        let _ = writeln!(
+            s,
+            "{:<12} {:>14.6} {:>14.6} {:>9.2} {:>8.3} {:>9.1} {:>9} {:>9.3} {:>6} {:>7} {:>8}",
+            r.reader.symbol(),
+            bid[0],
+            ask[0],
+            bps,
+            imbalance,
+            r.rate,
+            latency,
+            lag,
+            gaps,
+            resyncs,
+            now_ms - t.ts_recv_ms,
+        );
+    }
+    s
+}
+
+/// The regions being watched and the update rate measured for each.
+pub struct Dashboard {
+    rows: Vec<Row>,
+}
+
+impl Dashboard {
+    /// Empty `symbols` means every region in `dir`.
+    pub fn open<P: AsRef<Path>>(dir: P, symbols: &[String]) -> Result<Self> {
+        let dir = dir.as_ref();
+        let symbols = if symbols.is_empty() { discover(dir)? } else { symbols.to_vec() };
+        if symbols.is_empty() {
+            return Err(anyhow!("no top-of-book regions in {} (is [shm] enabled in the recorder?)", dir.display()));
+        }
+        let rows = symbols
+            .iter()
+            .map(|sym| {
+                let reader = ShmReader::open_symbol(dir, sym)?;
+                Ok(Row { last_seq: reader.seq(), reader, tob: TopOfBook::default(), last_at: Instant::now(), rate: 0.0 })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { rows })
+    }
+
+    /// Rereads every region.
+    pub fn refresh(&mut self) {
+        for r in &mut self.rows {
+            r.reader.read_into(&mut r.tob);
+            let dt = r.last_at.elapsed().as_secs_f64();
+            if dt > 0.0 {
+                // seq advances by 2 per publish
+                r.rate = r.tob.seq.saturating_sub(r.last_seq) as f64 / 2.0 / dt;
+            }
+            r.last_seq = r.tob.seq;
+            r.last_at = Instant::now();
+        }
+    }
+
+    /// The whole screen, as of the last `refresh`.
+    pub fn render(&self) -> String {
+        render(&self.rows)
+    }
+}
+
+/// Runs until the process is interrupted. Empty `symbols` means every region in `dir`.
+pub fn run<P: AsRef<Path>>(dir: P, symbols: &[String], refresh: Duration) -> Result<()> {
+    let mut dash = Dashboard::open(dir, symbols)?;
+    loop {
+        dash.refresh();
+        print!("{}", dash.render());
+        let _ = std::io::stdout().flush();
+        std::thread::sleep(refresh);
+    }
+}
@@ -0,0 +1,75 @@
+// error.rs
+//
+// Typed errors for the live ingestion path (WS frames, book maintenance, REST
+// snapshots). Each maps to a `Recovery` so callers decide between skipping the
+// message, retrying later, resyncing the book or giving up on the connection
+// without inspecting message strings. Offline tools keep using `anyhow`.
+use thiserror::Error;
+
+/// What the caller should do about an [`IngestError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// Drop this message; the book is still consistent.
+    Skip,
+    /// Transient; try the same request again after a pause.
+    Retry,
+    /// The book can no longer be trusted; reload it from a snapshot.
+    Resync,
+    /// Give up on this connection or request: the depth loop reconnects and
+    /// resyncs, a rejected snapshot request ends the symbol's recorder.
+    Abort,
+}
+
+#[derive(Debug, Error)]
+pub enum IngestError {
+    #[error("protobuf decode failed: {0}")]
+    Decode(#[from] prost::DecodeError),
+    #[error("bad {field} {value:?}")]
+    Parse { field: &'static str, value: String },
+    #[error("unexpected message body: {0}")]
+    UnexpectedBody(String),
+    #[error("sequence gap: need {needed}, got {from}..{to}")]
+    SequenceGap { needed: u64, from: u64, to: u64 },
+    #[error("stale update {from}..{to}, book at {version}")]
+    Stale { from: u64, to: u64, version: u64 },
+    #[error("crossed book: bid {bid} >= ask {ask}")]
+    CrossedBook { bid: f64, ask: f64 },
+    /// Boxed: the tungstenite error would triple the size of every `Result`.
+    #[error("transport: {0}")]
+    Transport(Box<tokio_tungstenite::tungstenite::Error>),
+    /// `status` is `None` when the request failed before a response arrived.
+    #[error("REST {}: {body}", status.map_or("error".to_string(), |s| s.to_string()))]
+    Rest { status: Option<u16>, body: String },
+}
+
+impl IngestError {
+    pub fn recovery(&self) -> Recovery {
+        match self {
+            Self::Decode(_) | Self::UnexpectedBody(_) | Self::Stale { .. } => Recovery::Skip,
+            // a bad level leaves the book untouched, but without the frame's updates,
+            // and later frames would apply on top of the hole
+            Self::Parse { .. } | Self::SequenceGap { .. } | Self::CrossedBook { .. } => Recovery::Resync,
+            Self::Transport(_) => Recovery::Abort,
+            Self::Rest { status: None, .. } => Recovery::Retry,
+            Self::Rest { status: Some(s), .. } if *s == 429 || *s == 418 || *s >= 500 => Recovery::Retry,
+            Self::Rest { .. } => Recovery::Abort,
+        }
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for IngestError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        Self::Transport(Box::new(e))
+    }
+}
+
+impl From<reqwest::Error> for IngestError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Rest { status: e.status().map(|s| s.as_u16()), body: e.to_string() }
+    }
+}
+
+/// Parses a numeric field of a WS or REST message.
+pub fn parse_num<T: std::str::FromStr>(field: &'static str, value: &str) -> Result<T, IngestError> {
+    value.parse().map_err(|_| IngestError::Parse { field, value: value.to_string() })
+}
@@ -0,0 +1,171 @@
+// exchange_info.rs
+//
+// Symbol metadata cache filled from `/api/v3/exchangeInfo`: status, assets and
+// price/quantity precision. The recorder loads it before anything else,
+// refuses symbols that are unknown or not tradable, stores an `exchange_info`
+// event per recorded symbol and refreshes it every `[exchange]
+// info_refresh_secs`. The depth loop checks live levels against the cached
+// tick and lot size; offline readers (`audit`, `manifest`) get them from the
+// stored event.
+//
+// `watch_task` diffs consecutive fetches into `SymbolChange`s (listings,
+// status changes, delistings) and hands them to the recorder, which stops and
+// starts symbols accordingly and stores the changes of the symbols it records,
+// waits for or would auto-record.
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::clock;
+use crate::error::IngestError;
+use crate::rest::{MexcRestClient, RestSymbol};
+use crate::store::DataStore;
+use crate::types::{ExchangeInfoEvent, Precision, SymbolChange, SymbolInfo, SymbolStatus};
+
+/// Decimal places of a decimal step string (`"0.001"` -> 3); `None` for zero or junk.
+fn step_decimals(step: &str) -> Option<u32> {
+    let v: f64 = step.trim().parse().ok()?;
+    if !(v.is_finite() && v > 0.0) {
+        return None;
+    }
+    Some(step.trim().split_once('.').map_or(0, |(_, frac)| frac.trim_end_matches('0').len() as u32))
+}
+
+impl From<&RestSymbol> for SymbolInfo {
+    fn from(s: &RestSymbol) -> Self {
+        let qty_dp = s.base_size_precision.as_deref().and_then(step_decimals).unwrap_or(s.base_asset_precision);
+        SymbolInfo {
+            symbol: s.symbol.clone(),
+            status: SymbolStatus::from_code(&s.status),
+            base_asset: s.base_asset.clone(),
+            quote_asset: s.quote_asset.clone(),
+            spot_trading: s.is_spot_trading_allowed,
+            precision: Precision { price_dp: s.quote_precision.unwrap_or(s.quote_asset_precision), qty_dp },
+        }
+    }
+}
+
+struct Cache {
+    symbols: HashMap<String, SymbolInfo>,
+    server_time_ms: Option<i64>,
+    loaded: bool,
+}
+
+/// What changed from `old` to `new`; status and spot trading only, precision
+/// changes are in the next `exchange_info` event.
+fn diff(old: &HashMap<String, SymbolInfo>, new: &HashMap<String, SymbolInfo>, ts_recv_ms: i64) -> Vec<SymbolChange> {
+    let mut out: Vec<SymbolChange> = Vec::new();
+    for (s, n) in new {
+        match old.get(s) {
+            Some(o) if o.status == n.status && o.spot_trading == n.spot_trading => {}
+            o => out.push(SymbolChange { symbol: s.clone(), ts_recv_ms, old: o.cloned(), new: Some(n.clone()) }),
+        }
+    }
+    for (s, o) in old.iter().filter(|(s, _)| !new.contains_key(*s)) {
+        out.push(SymbolChange { symbol: s.clone(), ts_recv_ms, old: Some(o.clone()), new: None });
+    }
+    out.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    out
+}
+
+pub struct ExchangeInfo {
+    rest: Arc<MexcRestClient>,
+    cache: RwLock<Cache>,
+}
+
+impl ExchangeInfo {
+    /// Fetches every symbol once.
+    pub async fn load(rest: Arc<MexcRestClient>) -> Result<Self, IngestError> {
+        let info = Self { rest, cache: RwLock::new(Cache { symbols: HashMap::new(), server_time_ms: None, loaded: false }) };
+        info.refresh().await?;
+        Ok(info)
+    }
+
+    /// Replaces the cache with a fresh `/api/v3/exchangeInfo` and returns what
+    /// changed since the previous fetch (nothing on the first).
+    pub async fn refresh(&self) -> Result<Vec<SymbolChange>, IngestError> {
+        let resp = self.rest.exchange_info(None).await?;
+        let symbols: HashMap<_, _> = resp.symbols.iter().map(|s| (s.symbol.clone(), SymbolInfo::from(s))).collect();
+        let mut cache = self.cache.write().unwrap();
+        let changes = if cache.loaded { diff(&cache.symbols, &symbols, clock::stamp().ms()) } else { Vec::new() };
+        *cache = Cache { symbols, server_time_ms: resp.server_time, loaded: true };
+        Ok(changes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.read().unwrap().symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<SymbolInfo> {
+        self.cache.read().unwrap().symbols.get(symbol).cloned()
+    }
+
+    pub fn precision(&self, symbol: &str) -> Option<Precision> {
+        self.cache.read().unwrap().symbols.get(symbol).map(|s| s.precision)
+    }
+
+    /// The cached info of every symbol in `symbols`, or an error naming each one
+    /// that is unknown or not tradable.
+    pub fn check(&self, symbols: &[String]) -> Result<Vec<SymbolInfo>> {
+        let mut ok = Vec::new();
+        let mut problems = Vec::new();
+        for s in symbols {
+            match self.get(s) {
+                None => problems.push(format!("{s}: unknown symbol")),
+                Some(i) if i.status != SymbolStatus::Online => problems.push(format!("{s}: status is {:?}", i.status)),
+                Some(i) if !i.spot_trading => problems.push(format!("{s}: spot trading not allowed")),
+                Some(i) => ok.push(i),
+            }
+        }
+        if !problems.is_empty() {
+            return Err(anyhow!("exchangeInfo rejects {}", problems.join("; ")));
+        }
+        Ok(ok)
+    }
+
+    /// Stores the cached info of each of `symbols` as an `exchange_info` event.
+    pub fn persist(&self, store: &DataStore, symbols: &[String]) -> Result<()> {
+        let server_time_ms = self.cache.read().unwrap().server_time_ms;
+        for s in symbols {
+            let Some(info) = self.get(s) else { continue };
+            let recv = clock::stamp();
+            store.append_event_json(s, recv, "exchange_info", &ExchangeInfoEvent { ts_recv_ms: recv.ms(), server_time_ms, info })?;
+        }
+        Ok(())
+    }
+}
+
+/// Refreshes `info` every `interval` and sends the changes of each successful
+/// refresh (possibly none) to `tx`.
+pub async fn watch_task(info: Arc<ExchangeInfo>, interval: Duration, tx: mpsc::Sender<Vec<SymbolChange>>) {
+    let mut tick = tokio::time::interval(interval);
+    tick.tick().await;
+    loop {
+        tick.tick().await;
+        let changes = match info.refresh().await {
+            Ok(c) => c,
+            Err(e) => {
+                warn!(error = %e, "exchangeInfo refresh failed");
+                continue;
+            }
+        };
+        debug!(symbols = info.len(), changes = changes.len(), "exchangeInfo refreshed");
+        for c in &changes {
+            debug!(
+                symbol = %c.symbol, change = c.kind(),
+                from = ?c.old.as_ref().map(|i| i.status), to = ?c.new.as_ref().map(|i| i.status),
+                "exchangeInfo symbol change"
+            );
+        }
+        if tx.send(changes).await.is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,290 @@
+// features.rs
+//
+// Order flow and microstructure features from the book and trade streams,
+// live (`record` with `[features] enabled`) or offline (`features`, or
+// `replay_features` from code).
+//
+// Book features describe the book right after an update:
+// - mid and spread in bps;
+// - microprice: the level-1 prices weighted by the opposite level-1 quantity;
+// - weighted mid: the same over `levels` levels, with each side's VWAP;
+// - depth imbalance over `levels` levels;
+// - slope per side: cumulative quantity per bp between mid and the last level.
+//
+// Flow features are totals over each rolling window ending at the row:
+// - order flow imbalance: the level-1 e_n of Cont, Kukanov & Stoikov summed
+//   over book updates;
+// - trade-signed volume and VWAP;
+// - realized volatility: the root of the summed squared log mid returns
+//   between book updates.
+//
+// Windows run on receive time, so REST-polled trades count from when they
+// arrived rather than when they printed.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+
+use crate::config::FeaturesConfig;
+use crate::replay::{replay_events, BookReplay};
+use crate::types::{BookSide, RevSide, TradeEvent};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowFeatures {
+    pub window_ms: u64,
+    /// Positive when bids grow or asks shrink.
+    pub ofi: f64,
+    /// Buy minus sell volume; trades without a side count in neither.
+    pub signed_volume: f64,
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+    pub trades: u64,
+    /// `None` without trades in the window.
+    pub vwap: Option<f64>,
+    /// Not annualised.
+    pub realized_vol: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureRow {
+    pub symbol: String,
+    pub ts_ms: i64,
+    /// Book version the row was computed on.
+    pub version: u64,
+    pub mid: f64,
+    pub microprice: f64,
+    pub weighted_mid: f64,
+    pub spread_bps: f64,
+    /// (bid qty - ask qty) / (bid qty + ask qty), in -1..=1.
+    pub depth_imbalance: f64,
+    /// `None` when the side has a single level at mid.
+    pub bid_slope: Option<f64>,
+    pub ask_slope: Option<f64>,
+    pub windows: Vec<WindowFeatures>,
+}
+
+/// Values of the last `span_ms` with their running sums.
+struct Rolling<const N: usize> {
+    span_ms: i64,
+    items: VecDeque<(i64, [f64; N])>,
+    sums: [f64; N],
+}
+
+impl<const N: usize> Rolling<N> {
+    fn new(span_ms: u64) -> Self {
+        Self { span_ms: span_ms as i64, items: VecDeque::new(), sums: [0.0; N] }
+    }
+
+    fn push(&mut self, ts_ms: i64, v: [f64; N]) {
+        for (s, x) in self.sums.iter_mut().zip(v) {
+            *s += x;
+        }
+        self.items.push_back((ts_ms, v));
+    }
+
+    /// Drops what is older than `span_ms` before `now_ms`.
+    fn evict(&mut self, now_ms: i64) {
+        while let Some((ts, v)) = self.items.front() {
+            if *ts > now_ms - self.span_ms {
+                break;
+            }
+            for (s, x) in self.sums.iter_mut().zip(v) {
+                *s -= x;
+            }
+            self.items.pop_front();
+        }
+        // running sums drift; an empty window is exactly zero
+        if self.items.is_empty() {
+            self.sums = [0.0; N];
+        }
+    }
+}
+
+struct Window {
+    ms: u64,
+    ofi: Rolling<1>,
+    /// buy, sell, all volume, notional
+    trades: Rolling<4>,
+    /// squared log mid returns
+    returns: Rolling<1>,
+}
+
+impl Window {
+    fn new(ms: u64) -> Self {
+        Self { ms, ofi: Rolling::new(ms), trades: Rolling::new(ms), returns: Rolling::new(ms) }
+    }
+
+    fn features(&mut self, now_ms: i64) -> WindowFeatures {
+        self.ofi.evict(now_ms);
+        self.trades.evict(now_ms);
+        self.returns.evict(now_ms);
+        let [buy, sell, volume, notional] = self.trades.sums;
+        WindowFeatures {
+            window_ms: self.ms,
+            ofi: self.ofi.sums[0],
+            signed_volume: buy - sell,
+            buy_volume: buy,
+            sell_volume: sell,
+            trades: self.trades.items.len() as u64,
+            vwap: (volume > 0.0).then(|| notional / volume),
+            realized_vol: self.returns.sums[0].max(0.0).sqrt(),
+        }
+    }
+}
+
+/// Level-1 price and quantity of each side.
+#[derive(Clone, Copy)]
+struct Top {
+    bid: (f64, f64),
+    ask: (f64, f64),
+}
+
+/// Turns one symbol's book updates and trades into `FeatureRow`s.
+pub struct FeatureEngine {
+    symbol: String,
+    levels: usize,
+    interval_ms: i64,
+    windows: Vec<Window>,
+    prev: Option<Top>,
+    last_row_ms: Option<i64>,
+}
+
+impl FeatureEngine {
+    pub fn new(symbol: &str, cfg: &FeaturesConfig) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            levels: cfg.levels.max(1),
+            interval_ms: cfg.interval_ms as i64,
+            windows: cfg.windows_ms.iter().map(|&ms| Window::new(ms)).collect(),
+            prev: None,
+            last_row_ms: None,
+        }
+    }
+
+    /// Forgets the previous book, so that the jump across a resync counts as
+    /// neither order flow nor a return.
+    pub fn reset_book(&mut self) {
+        self.prev = None;
+    }
+
+    pub fn on_trade(&mut self, t: &TradeEvent) {
+        let (buy, sell) = match t.side.as_deref() {
+            Some("BUY") => (t.qty, 0.0),
+            Some("SELL") => (0.0, t.qty),
+            _ => (0.0, 0.0),
+        };
+        for w in &mut self.windows {
+            w.trades.push(t.ts_recv_ms, [buy, sell, t.qty, t.price * t.qty]);
+        }
+    }
+
+    /// Feeds the book as of `ts_ms`; returns a row unless one was emitted less
+    /// than `interval_ms` ago or a side is empty.
+    pub fn on_book(&mut self, ts_ms: i64, version: u64, asks: &BookSide, bids: &RevSide) -> Option<FeatureRow> {
+        let (Some((ap, aq)), Some((bp, bq))) = (asks.iter().next(), bids.iter().next()) else {
+            self.reset_book();
+            return None;
+        };
+        let top = Top { bid: ((bp.0).0, *bq), ask: (ap.0, *aq) };
+        let mid = (top.bid.0 + top.ask.0) / 2.0;
+
+        if let Some(prev) = self.prev {
+            let e = ofi(&prev, &top);
+            let prev_mid = (prev.bid.0 + prev.ask.0) / 2.0;
+            let r = if mid > 0.0 && prev_mid > 0.0 { (mid / prev_mid).ln() } else { 0.0 };
+            for w in &mut self.windows {
+                if e != 0.0 {
+                    w.ofi.push(ts_ms, [e]);
+                }
+                if r != 0.0 {
+                    w.returns.push(ts_ms, [r * r]);
+                }
+            }
+        }
+        self.prev = Some(top);
+
+        if self.last_row_ms.is_some_and(|t| ts_ms < t + self.interval_ms) {
+            return None;
+        }
+        self.last_row_ms = Some(ts_ms);
+
+        let (bid_qty, bid_notional, bid_last) = depth(bids.iter().map(|(p, q)| ((p.0).0, *q)), self.levels);
+        let (ask_qty, ask_notional, ask_last) = depth(asks.iter().map(|(p, q)| (p.0, *q)), self.levels);
+        let total = bid_qty + ask_qty;
+        let (bid_vwap, ask_vwap) = (bid_notional / bid_qty, ask_notional / ask_qty);
+        let slope = |qty: f64, last: f64| {
+            let bps = (last - mid).abs() / mid * 1e4;
+            (bps > 0.0).then(|| qty / bps)
+        };
+        Some(FeatureRow {
+            symbol: self.symbol.clone(),
+            ts_ms,
+            version,
+            mid,
+            microprice: (top.ask.0 * top.bid.1 + top.bid.0 * top.ask.1) / (top.bid.1 + top.ask.1),
+            weighted_mid: (ask_vwap * bid_qty + bid_vwap * ask_qty) / total,
+            spread_bps: (top.ask.0 - top.bid.0) / mid * 1e4,
+            depth_imbalance: (bid_qty - ask_qty) / total,
+            bid_slope: slope(bid_qty, bid_last),
+            ask_slope: slope(ask_qty, ask_last),
+            windows: self.windows.iter_mut().map(|w| w.features(ts_ms)).collect(),
+        })
+    }
+}
+
+/// Level-1 order flow imbalance from `prev` to `cur`.
+fn ofi(prev: &Top, cur: &Top) -> f64 {
+    let mut e = 0.0;
+    if cur.bid.0 >= prev.bid.0 {
+        e += cur.bid.1;
+    }
+    if cur.bid.0 <= prev.bid.0 {
+        e -= prev.bid.1;
+    }
+    if cur.ask.0 <= prev.ask.0 {
+        e -= cur.ask.1;
+    }
+    if cur.ask.0 >= prev.ask.0 {
+        e += prev.ask.1;
+    }
+    e
+}
+
+/// Quantity, notional and last price of the first `levels` levels.
+fn depth(side: impl Iterator<Item = (f64, f64)>, levels: usize) -> (f64, f64, f64) {
+    side.take(levels).fold((0.0, 0.0, 0.0), |(q, n, _), (p, lq)| (q + lq, n + p * lq, p))
+}
+
+/// Replays `symbol` through a `FeatureEngine` and calls `on_row` for every row
+/// at or after `from_ms`; the data before it, back to the last snapshot, warms
+/// the windows up. Returns the book as of `to_ms`.
+pub fn replay_features<P: AsRef<Path>>(
+    root: P,
+    symbol: &str,
+    from_ms: i64,
+    to_ms: i64,
+    cfg: &FeaturesConfig,
+    mut on_row: impl FnMut(&FeatureRow) -> Result<()>,
+) -> Result<BookReplay> {
+    let mut engine = FeatureEngine::new(symbol, cfg);
+    replay_events(root, symbol, from_ms, to_ms, |book, ev, changed| {
+        match ev.kind.as_str() {
+            "trade" => {
+                if let Ok(t) = ev.payload_as::<TradeEvent>() {
+                    engine.on_trade(&t);
+                }
+                return Ok(());
+            }
+            "depth_snapshot" => engine.reset_book(),
+            _ if !book.depth.valid => engine.reset_book(),
+            _ => {}
+        }
+        if !changed {
+            return Ok(());
+        }
+        match engine.on_book(ev.ts_ms, book.depth.version, &book.depth.asks, &book.depth.bids) {
+            Some(row) if ev.ts_ms >= from_ms => on_row(&row),
+            _ => Ok(()),
+        }
+    })
+}
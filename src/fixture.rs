@@ -0,0 +1,216 @@
+// fixture.rs
+//
+// Golden-file fixtures for the book builder. A fixture is a directory with
+//
+//   fixture.json   symbol, crossed-book policy and the REST snapshots used:
+//                  the first seeds the book, the rest answer resyncs in order
+//   raw.pb.zst     aggregated-depth WS frames (`rawlog` format)
+//   golden.json    expected `replay` output
+//
+// `replay` runs the frames through `DepthBook::apply_frame`, the recorder's
+// depth-loop step, and collects the events it would store, the final book and
+// the telemetry counters. `record_fixture` cuts a fixture
+// out of a recorded partition.
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::book::{CrossedGuard, CrossedPolicy, DepthBook};
+use crate::error::{IngestError, Recovery};
+use crate::rawlog::{read_raw_frames, RawChannel, RawFrame, RawWriter, RAW_FILE_NAME};
+use crate::store::{read_partition, Partition};
+use crate::types::{BookSide, CrossedBook, DepthDelta, DepthSnapshot, RevSide};
+
+pub const FIXTURE_FILE: &str = "fixture.json";
+pub const GOLDEN_FILE: &str = "golden.json";
+/// Levels per side in emitted events, as stored by the recorder.
+const EVENT_LEVELS: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureMeta {
+    pub symbol: String,
+    #[serde(default)]
+    pub crossed_policy: CrossedPolicy,
+    #[serde(default = "default_tolerate_updates")]
+    pub crossed_tolerate_updates: u32,
+    pub snapshots: Vec<DepthSnapshot>,
+}
+
+fn default_tolerate_updates() -> u32 { 10 }
+
+pub struct Fixture {
+    pub meta: FixtureMeta,
+    pub frames: Vec<RawFrame>,
+}
+
+impl Fixture {
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+        let meta: FixtureMeta = serde_json::from_slice(&std::fs::read(dir.join(FIXTURE_FILE))?)?;
+        if meta.snapshots.is_empty() {
+            return Err(anyhow!("{}: no snapshots", dir.display()));
+        }
+        let frames = read_raw_frames(dir.join(RAW_FILE_NAME))?.collect::<Result<Vec<_>>>()?;
+        Ok(Self { meta, frames })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counters {
+    pub frames: u64,
+    pub applied: u64,
+    pub skipped: u64,
+    pub gaps: u64,
+    pub resyncs: u64,
+    pub crossed: u64,
+    /// Resyncs still pending at the end because the fixture ran out of snapshots.
+    pub unresolved: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GoldenEvent {
+    DepthSnapshot(DepthSnapshot),
+    DepthDelta(DepthDelta),
+    CrossedBook(CrossedBook),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Golden {
+    pub counters: Counters,
+    pub events: Vec<GoldenEvent>,
+    /// Full book after the last frame.
+    pub book: DepthSnapshot,
+}
+
+impl Golden {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut text = serde_json::to_string_pretty(self)?;
+        text.push('\n');
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+fn levels(asks: &BookSide, bids: &RevSide, n: usize) -> (Vec<[f64; 2]>, Vec<[f64; 2]>) {
+    (
+        asks.iter().take(n).map(|(p, q)| [p.0, *q]).collect(),
+        bids.iter().take(n).map(|(p, q)| [(p.0).0, *q]).collect(),
+    )
+}
+
+/// Replays `fx` the way the recorder would have processed it live.
+pub fn replay(fx: &Fixture) -> Golden {
+    let symbol = &fx.meta.symbol;
+    let mut snapshots = fx.meta.snapshots.iter();
+    let mut book = DepthBook::new(Some(CrossedGuard::new(fx.meta.crossed_policy, fx.meta.crossed_tolerate_updates)));
+    let mut c = Counters::default();
+    let mut events = Vec::new();
+
+    let snapshot_event = |s: &DepthSnapshot, book: &DepthBook| {
+        let (a, b) = levels(&book.asks, &book.bids, EVENT_LEVELS);
+        GoldenEvent::DepthSnapshot(DepthSnapshot { asks: a, bids: b, ..s.clone() })
+    };
+
+    let first = snapshots.next().expect("checked in Fixture::load");
+    book.load_snapshot(first);
+    let mut last_ts_ms = first.ts_recv_ms;
+    events.push(snapshot_event(first, &book));
+
+    for f in fx.frames.iter().filter(|f| f.channel == RawChannel::AggreDepth) {
+        c.frames += 1;
+        let recv_ms = f.recv_ts_ns.div_euclid(1_000_000);
+        last_ts_ms = recv_ms;
+        let out = book.apply_frame(symbol, f.data.clone().into(), recv_ms);
+        if out.crossed.started {
+            c.crossed += 1;
+        }
+        if let Some(ev) = out.crossed.event {
+            events.push(GoldenEvent::CrossedBook(ev));
+        }
+        if let Some(d) = out.delta {
+            c.applied += 1;
+            let (a, b) = levels(&book.asks, &book.bids, EVENT_LEVELS);
+            events.push(GoldenEvent::DepthDelta(DepthDelta {
+                symbol: symbol.clone(),
+                ts_recv_ms: recv_ms,
+                from_version: d.from_version,
+                to_version: book.version,
+                bids: b,
+                asks: a,
+            }));
+        }
+        match &out.error {
+            Some(e) if e.recovery() == Recovery::Skip => c.skipped += 1,
+            Some(IngestError::SequenceGap { .. }) if out.broke => c.gaps += 1,
+            _ => {}
+        }
+
+        if !book.valid {
+            if let Some(s) = snapshots.next() {
+                book.load_snapshot(s);
+                c.resyncs += 1;
+                events.push(snapshot_event(s, &book));
+            }
+        }
+    }
+    c.unresolved = !book.valid as u64;
+
+    let (a, b) = levels(&book.asks, &book.bids, usize::MAX);
+    Golden {
+        counters: c,
+        events,
+        book: DepthSnapshot { symbol: symbol.clone(), ts_recv_ms: last_ts_ms, last_update_id: book.version, bids: b, asks: a },
+    }
+}
+
+/// Cuts a fixture out of the recorded partition `part_dir`: its first
+/// `depth_snapshot`, up to `max_frames` depth frames after it and the
+/// snapshots recorded between them. Writes the golden output of the result.
+pub fn record_fixture<P: AsRef<Path>, Q: AsRef<Path>>(
+    part_dir: P,
+    out_dir: Q,
+    policy: CrossedPolicy,
+    max_frames: usize,
+) -> Result<Golden> {
+    let part = Partition::from_dir(part_dir)?;
+    let out_dir = out_dir.as_ref();
+    let mut snapshots = Vec::new();
+    let mut frames = Vec::new();
+    for ev in read_partition(&part)? {
+        let ev = ev?;
+        match ev.kind.as_str() {
+            "depth_snapshot" => snapshots.push(ev.payload_as::<DepthSnapshot>()?),
+            "depth_pb_raw" if !snapshots.is_empty() => {
+                if frames.len() == max_frames {
+                    break;
+                }
+                let data = ev.raw().ok_or_else(|| anyhow!("depth frame without bytes"))??;
+                frames.push((ev.ts_ns.unwrap_or(ev.ts_ms * 1_000_000), data));
+            }
+            _ => {}
+        }
+    }
+    if snapshots.is_empty() {
+        return Err(anyhow!("{}: no depth_snapshot", part.dir.display()));
+    }
+
+    std::fs::create_dir_all(out_dir)?;
+    let meta = FixtureMeta { symbol: part.symbol.clone(), crossed_policy: policy, crossed_tolerate_updates: default_tolerate_updates(), snapshots };
+    std::fs::write(out_dir.join(FIXTURE_FILE), serde_json::to_string_pretty(&meta)? + "\n")?;
+    let raw_path = out_dir.join(RAW_FILE_NAME);
+    let _ = std::fs::remove_file(&raw_path);
+    let mut w = RawWriter::open(&raw_path)?;
+    for (ts_ns, data) in &frames {
+        w.write(*ts_ns, RawChannel::AggreDepth, data)?;
+    }
+    w.finish()?;
+
+    let golden = replay(&Fixture::load(out_dir)?);
+    golden.write(out_dir.join(GOLDEN_FILE))?;
+    Ok(golden)
+}
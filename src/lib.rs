@@ -0,0 +1,36 @@
+// lib.rs
+//! MEXC spot public market data: L2 book reconstruction, recording and local
+//! distribution. The `mexc-spot-public` binary is the recorder; consumers link
+//! this crate for the shared types and readers (e.g. [`shm::ShmReader`]).
+
+pub mod mexc_pb {
+    #![allow(clippy::large_enum_variant)]
+    include!(concat!(env!("OUT_DIR"), "/mexc.pb.rs"));
+}
+
+pub mod clock;
+pub mod types;
+pub mod error;
+pub mod book;
+pub mod telemetry;
+pub mod store;
+pub mod rawlog;
+pub mod columnar;
+pub mod convert;
+pub mod manifest;
+pub mod audit;
+pub mod replay;
+pub mod features;
+pub mod sampler;
+pub mod align;
+pub mod fixture;
+pub mod validate;
+pub mod config;
+pub mod rest;
+pub mod exchange_info;
+pub mod logging;
+pub mod server;
+pub mod shm;
+pub mod dashboard;
+pub mod mcast;
+pub mod mock;
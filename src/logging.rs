@@ -0,0 +1,57 @@
+// logging.rs
+//
+// `tracing` setup from the `[logging]` section: leveled events to stderr
+// (pretty or JSON) and, with `save_logs`, to `log_file_path`. The file either
+// rotates (`rotation`, rolled files get a date suffix) or, with
+// `rewrite_last_logs`, is truncated at every start. `RUST_LOG` overrides
+// `level` when set.
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt as _;
+use tracing_subscriber::util::SubscriberInitExt as _;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+use crate::config::{LogFormat, LogRotation, LoggingConfig};
+
+fn layer<S>(format: LogFormat, writer: BoxMakeWriter, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    match format {
+        LogFormat::Pretty => fmt::layer().with_writer(writer).with_ansi(ansi).with_target(false).boxed(),
+        LogFormat::Json => fmt::layer().json().with_writer(writer).with_current_span(true).boxed(),
+    }
+}
+
+/// Installs the global subscriber. Keep the returned guard alive for the life
+/// of the process; dropping it flushes the file writer.
+pub fn init(cfg: &LoggingConfig) -> Result<Option<WorkerGuard>> {
+    let filter = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new(cfg.level.to_lowercase()))?;
+    let stderr = layer(cfg.format, BoxMakeWriter::new(std::io::stderr), true);
+
+    let (file, guard) = if cfg.save_logs {
+        let path = Path::new(&cfg.log_file_path);
+        let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let name = path.file_name().ok_or_else(|| anyhow!("log_file_path {} has no file name", path.display()))?;
+        std::fs::create_dir_all(dir)?;
+        let (writer, guard) = if cfg.rewrite_last_logs {
+            tracing_appender::non_blocking(std::fs::File::create(path)?)
+        } else {
+            let rotation = match cfg.rotation {
+                LogRotation::Hourly => Rotation::HOURLY,
+                LogRotation::Daily => Rotation::DAILY,
+                LogRotation::Never => Rotation::NEVER,
+            };
+            tracing_appender::non_blocking(RollingFileAppender::new(rotation, dir, name))
+        };
+        (Some(layer(cfg.format, BoxMakeWriter::new(writer), false)), Some(guard))
+    } else {
+        (None, None)
+    };
+
+    tracing_subscriber::registry().with(filter).with(stderr).with(file).try_init()?;
+    Ok(guard)
+}
@@ -1,122 +1,1179 @@
 // main.rs
 use anyhow::{anyhow, Result};
-use bytes::Bytes;
+use base64::Engine;
+use clap::{Parser, Subcommand};
 use futures::{SinkExt, StreamExt};
 use ordered_float::OrderedFloat;
-use prost::Message;
-use reqwest;
-use serde::Deserialize;
 use std::{
     cmp::Reverse,
-    collections::{BTreeMap, HashSet, VecDeque},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
     hash::{Hash, Hasher},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
     time::{Duration, Instant},
 };
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message as WsMsg};
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message as WsMsg, MaybeTlsStream, WebSocketStream};
 use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::{AbortHandle, JoinSet};
 
-type BookSide = BTreeMap<OrderedFloat<f64>, f64>;
-type RevSide = BTreeMap<Reverse<OrderedFloat<f64>>, f64>;
+use mexc_spot_public::{clock, config, dashboard, logging, server};
+use mexc_spot_public::error::{parse_num, IngestError, Recovery};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use mexc_spot_public::book::{CrossedGuard, DepthBook};
+use mexc_spot_public::telemetry::Telemetry;
+use mexc_spot_public::types::{AppliedDelta, BookSide, BookValidation, RevSide, DepthSnapshot, DepthDelta, SymbolChange, TradeEvent, ClockSkewSample};
+use mexc_spot_public::validate::{validate, DeltaBuffer};
+use mexc_spot_public::store::{list_partitions, read_partition, DataStore, Partition};
+use mexc_spot_public::audit::{audit_symbol, AuditOptions};
+use mexc_spot_public::manifest::{read_manifest, scan_partition, verify_partition, write_manifest, PartitionStats, VerifyReport};
+use mexc_spot_public::replay::{book_at, replay_range};
+use mexc_spot_public::features::{replay_features, FeatureEngine};
+use mexc_spot_public::align::{replay_aligned, Aligner};
+use mexc_spot_public::sampler::{replay_samples, SampleFiles, SampleFormat, SampleRow, SampleWriter, Sampler};
+use mexc_spot_public::rawlog::{self, RawChannel};
+use mexc_spot_public::shm::ShmWriter;
+use mexc_spot_public::mcast::McastPublisher;
+use mexc_spot_public::convert::{convert_all, ConvertOptions};
+use mexc_spot_public::fixture::record_fixture;
+use mexc_spot_public::rest::{MexcRestClient, RestTrade};
+use mexc_spot_public::exchange_info::{self, ExchangeInfo};
+use config::{Config, ListingsConfig, WebsocketConfig};
+use server::BookHub;
 
-pub mod mexc_pb { include!(concat!(env!("OUT_DIR"), "/mexc.pb.rs")); }
-use mexc_pb::PushDataV3ApiWrapper;
+/// The shared REST client and the `[websocket]` settings.
+#[derive(Clone)]
+struct Endpoints {
+    rest: Arc<MexcRestClient>,
+    ws: WebsocketConfig,
+}
+
+/// Exit status besides 0 and 1: clap exits with 2 on bad usage, and 3 means
+/// the command ran but the data has problems (`verify` found damage, `book-at`
+/// has no valid book).
+const EXIT_DATA: u8 = 3;
 
-mod types;
-mod telemetry;
-mod store;
-use telemetry::Telemetry;
-use types::{DepthSnapshot, DepthDelta, TradeEvent, ClockSkewSample};
-use store::DataStore;
+#[derive(Parser)]
+#[command(name = "mexc-spot-public", version, about = "MEXC spot public market data: recorder and offline tools")]
+struct Cli {
+    /// Config file; a missing file means defaults
+    #[arg(long, global = true, env = "MEXC_CONFIG", default_value = "config.toml")]
+    config: PathBuf,
+    /// Data root [default: `[storage] data_dir`]
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+    /// Comma separated [default: `[exchange] symbols` for record, every recorded symbol otherwise]
+    #[arg(long, global = true, value_delimiter = ',')]
+    symbols: Vec<String>,
+    #[command(subcommand)]
+    cmd: Cmd,
+}
 
-#[derive(Deserialize)]
-struct Snapshot {
-    #[serde(rename = "lastUpdateId")]
-    last_update_id: u64,
-    bids: Vec<[String; 2]>,
-    asks: Vec<[String; 2]>,
+#[derive(Subcommand)]
+enum Cmd {
+    /// Record books and trades from the exchange until stopped
+    Record,
+    /// Rebuild books from stored data and print the top levels as NDJSON after every change
+    Replay {
+        /// Unix ms, RFC 3339 or YYYY-MM-DD
+        #[arg(long)]
+        from: Option<String>,
+        #[arg(long)]
+        to: Option<String>,
+        /// Levels per side
+        #[arg(long, default_value_t = 1)]
+        levels: usize,
+    },
+    /// Compute order flow and microstructure features from stored data, as NDJSON rows
+    Features {
+        /// Unix ms, RFC 3339 or YYYY-MM-DD
+        #[arg(long)]
+        from: Option<String>,
+        #[arg(long)]
+        to: Option<String>,
+        /// [default: `[features] interval_ms`]
+        #[arg(long)]
+        interval_ms: Option<u64>,
+        /// Comma separated [default: `[features] windows_ms`]
+        #[arg(long, value_delimiter = ',')]
+        windows_ms: Vec<u64>,
+    },
+    /// Sample stored books on a fixed time grid, with trade aggregates, to CSV or Parquet
+    Sample {
+        /// Unix ms, RFC 3339 or YYYY-MM-DD
+        #[arg(long)]
+        from: Option<String>,
+        #[arg(long)]
+        to: Option<String>,
+        /// [default: `[sampler] interval_ms`]
+        #[arg(long)]
+        interval_ms: Option<u64>,
+        /// Levels per side [default: `[sampler] levels`]
+        #[arg(long)]
+        levels: Option<usize>,
+        /// csv or parquet [default: `[sampler] format`]
+        #[arg(long)]
+        format: Option<SampleFormat>,
+        /// One `<SYMBOL>_<INTERVAL>ms.<ext>` per symbol in here [default: `[sampler] dir`]
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Match stored trades to the book just before them and print them enriched, as NDJSON
+    Align {
+        /// Unix ms, RFC 3339 or YYYY-MM-DD
+        #[arg(long)]
+        from: Option<String>,
+        #[arg(long)]
+        to: Option<String>,
+        /// Levels per side kept for walking [default: `[align] levels`]
+        #[arg(long)]
+        levels: Option<usize>,
+    },
+    /// Print stored events as NDJSON
+    Cat {
+        /// Only this kind, e.g. `trade`; repeatable
+        #[arg(long = "kind")]
+        kinds: Vec<String>,
+        /// Unix ms, RFC 3339 or YYYY-MM-DD
+        #[arg(long)]
+        from: Option<String>,
+        #[arg(long)]
+        to: Option<String>,
+        /// Stop after this many events
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Print the book as of a point in time, rebuilt from stored data
+    BookAt {
+        /// Unix ms, RFC 3339 or YYYY-MM-DD
+        time: String,
+        /// Levels per side
+        #[arg(long, default_value_t = 20)]
+        levels: usize,
+    },
+    /// Replay stored data through the book logic and report data quality per hour
+    Audit {
+        /// First date, YYYY-MM-DD [default: all]
+        #[arg(long)]
+        from: Option<String>,
+        /// Last date, inclusive [default: --from, or all]
+        #[arg(long)]
+        to: Option<String>,
+        /// JSON report [default: audit-<SYMBOL>.json]
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Convert finished partitions to Parquet
+    Convert {
+        /// [default: <DATA_DIR>-parquet]
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// [default: number of CPUs]
+        #[arg(long)]
+        jobs: Option<usize>,
+    },
+    /// Per-partition event counts, versions, gaps and resyncs
+    Stats {
+        /// Unix ms, RFC 3339 or YYYY-MM-DD
+        #[arg(long)]
+        from: Option<String>,
+        #[arg(long)]
+        to: Option<String>,
+        /// One JSON document instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Recheck finished partitions against their manifest.json
+    Verify {
+        /// Write manifests for finished partitions without one
+        #[arg(long)]
+        write_missing: bool,
+    },
+    /// Cut a golden-file fixture out of a recorded partition
+    Fixture {
+        partition: PathBuf,
+        out: PathBuf,
+        #[arg(long, default_value_t = 500)]
+        max_frames: usize,
+    },
+    /// Live terminal view of the recorder's shared-memory books
+    Dashboard,
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let symbol = std::env::args().nth(1).unwrap_or_else(|| "BTCUSDT".to_string());
-    let outdir = std::env::args().nth(2).unwrap_or_else(|| "data".to_string());
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(code) => code,
+        // `cat ... | head`
+        Err(e) if e.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<ExitCode> {
+    let cfg = Config::load(&cli.config)?;
+    let data_dir = cli.data_dir.clone().unwrap_or_else(|| PathBuf::from(&cfg.storage.data_dir));
+
+    if let Cmd::Dashboard = cli.cmd {
+        dashboard::run(&cfg.shm.dir, &cli.symbols, Duration::from_millis(500))?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let _log_guard = logging::init(&cfg.logging)?;
+
+    let symbols = || -> Result<Vec<String>> {
+        if !cli.symbols.is_empty() {
+            return Ok(cli.symbols.clone());
+        }
+        let mut all: Vec<String> = list_partitions(&data_dir)?.into_iter().map(|p| p.symbol).collect();
+        all.dedup();
+        Ok(all)
+    };
+    let from_to = |from: &Option<String>, to: &Option<String>| -> Result<(i64, i64)> {
+        Ok((
+            from.as_deref().map(clock::parse_time_ms).transpose()?.unwrap_or(i64::MIN),
+            to.as_deref().map(clock::parse_time_ms).transpose()?.unwrap_or(i64::MAX),
+        ))
+    };
+
+    match &cli.cmd {
+        Cmd::Record => {
+            let symbols = if cli.symbols.is_empty() { cfg.exchange.symbols.clone() } else { cli.symbols.clone() };
+            record_cmd(&cfg, &data_dir, symbols).await?;
+        }
+        Cmd::Replay { from, to, levels } => {
+            let (from, to) = from_to(from, to)?;
+            replay_cmd(&data_dir, &symbols()?, from, to, *levels)?;
+        }
+        Cmd::Features { from, to, interval_ms, windows_ms } => {
+            let (from, to) = from_to(from, to)?;
+            let mut fc = cfg.features.clone();
+            fc.interval_ms = interval_ms.unwrap_or(fc.interval_ms);
+            if !windows_ms.is_empty() {
+                fc.windows_ms = windows_ms.clone();
+            }
+            features_cmd(&data_dir, &symbols()?, from, to, &fc)?;
+        }
+        Cmd::Sample { from, to, interval_ms, levels, format, out } => {
+            let (from, to) = from_to(from, to)?;
+            let mut sc = cfg.sampler.clone();
+            sc.interval_ms = interval_ms.unwrap_or(sc.interval_ms);
+            sc.levels = levels.unwrap_or(sc.levels);
+            sc.format = format.unwrap_or(sc.format);
+            let out = out.clone().unwrap_or_else(|| PathBuf::from(&sc.dir));
+            sample_cmd(&data_dir, &symbols()?, from, to, &sc, &out)?;
+        }
+        Cmd::Align { from, to, levels } => {
+            let (from, to) = from_to(from, to)?;
+            let mut ac = cfg.align.clone();
+            ac.levels = levels.unwrap_or(ac.levels);
+            align_cmd(&data_dir, &symbols()?, from, to, &ac)?;
+        }
+        Cmd::Cat { kinds, from, to, limit } => {
+            let (from, to) = from_to(from, to)?;
+            cat_cmd(&data_dir, &symbols()?, kinds, from, to, limit.unwrap_or(usize::MAX))?;
+        }
+        Cmd::BookAt { time, levels } => return book_at_cmd(&data_dir, &symbols()?, clock::parse_time_ms(time)?, *levels),
+        Cmd::Audit { from, to, out } => audit_cmd(&data_dir, &symbols()?, from.as_deref(), to.as_deref(), out.as_deref())?,
+        Cmd::Convert { out, jobs } => convert_cmd(&data_dir, out.clone(), *jobs, &cfg)?,
+        Cmd::Stats { from, to, json } => {
+            let (from, to) = from_to(from, to)?;
+            stats_cmd(&data_dir, &symbols()?, from, to, *json)?;
+        }
+        Cmd::Verify { write_missing } => return verify_cmd(&data_dir, *write_missing),
+        Cmd::Fixture { partition, out, max_frames } => fixture_cmd(partition, out, *max_frames, &cfg)?,
+        Cmd::Dashboard => unreachable!("handled above"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
 
-    let store = DataStore::new(&outdir)?;
+/// `record`: every symbol in `symbols`, plus listings if `[listings] auto_record`.
+async fn record_cmd(cfg: &Config, outdir: &Path, symbols: Vec<String>) -> Result<()> {
+    let store = Arc::new(
+        DataStore::with_format(outdir, cfg.storage.format, cfg.storage.parquet_row_group_rows)?
+            .with_raw_capture(cfg.storage.raw_capture),
+    );
     let telem = Arc::new(Telemetry::new());
 
-    let mut asks: BookSide = BTreeMap::new();
-    let mut bids: RevSide = BTreeMap::new();
+    let api_key = cfg.exchange.api_key.clone().or_else(|| std::env::var("MEXC_API_KEY").ok());
+    let rest = MexcRestClient::new(&cfg.exchange.rest_url, &cfg.rest, api_key)?.with_telemetry(telem.clone());
+    let endpoints = Endpoints { rest: Arc::new(rest), ws: cfg.websocket.clone() };
+
+    let info = Arc::new(ExchangeInfo::load(endpoints.rest.clone()).await?);
+    for i in info.check(&symbols)? {
+        info!(symbol = %i.symbol, tick = i.precision.tick_size(), lot = i.precision.lot_size(), "symbol info loaded");
+    }
+    let (changes_tx, mut changes_rx) = mpsc::channel(4);
+    tokio::spawn(exchange_info::watch_task(
+        info.clone(),
+        Duration::from_secs(cfg.exchange.info_refresh_secs.max(1)),
+        changes_tx,
+    ));
+
+    let hub = cfg.server.enabled.then(|| Arc::new(BookHub::new()));
+    if let Some(hub) = &hub {
+        let hub = hub.clone();
+        let bind = cfg.server.bind.clone();
+        tokio::spawn(async move {
+            if let Err(e) = server::serve(hub, &bind).await {
+                error!(error = %e, "book server stopped");
+            }
+        });
+    }
+
+    let mcast = if cfg.multicast.enabled {
+        let mc = Arc::new(McastPublisher::new(&cfg.multicast)?);
+        tokio::spawn(mc.clone().snapshot_loop(Duration::from_millis(cfg.multicast.snapshot_interval_ms)));
+        let mc_rt = mc.clone();
+        let bind = cfg.multicast.retransmit_bind.clone();
+        tokio::spawn(async move {
+            if let Err(e) = mc_rt.serve_retransmit(&bind).await {
+                error!(error = %e, "multicast retransmit service stopped");
+            }
+        });
+        Some(mc)
+    } else {
+        None
+    };
+    let fanout = Fanout { hub, mcast };
+
+    let rec = Arc::new(Recorder {
+        cfg: cfg.clone(),
+        store: store.clone(),
+        telem: telem.clone(),
+        endpoints: endpoints.clone(),
+        info,
+        fanout,
+        recording: Default::default(),
+    });
+    tokio::spawn(clock_skew_task(telem, endpoints.rest.clone()));
+    tokio::spawn(clock_anchor_task(rec.clone()));
+    let raw_store = store.clone();
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(rawlog::FLUSH_EVERY);
+        loop {
+            tick.tick().await;
+            raw_store.flush_raw();
+        }
+    });
+
+    let mut sup = Supervisor::new(rec);
+    for s in &symbols {
+        sup.start(s);
+    }
+    // one listener for the whole loop, so a SIGINT between polls is not lost
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let res = loop {
+        // with auto_record on, keep waiting for listings even with nothing to record
+        if sup.idle() && !cfg.listings.auto_record {
+            break sup.failed.take().map_or(Ok(()), Err);
+        }
+        tokio::select! {
+            Some(done) = sup.tasks.join_next_with_id() => sup.finished(done),
+            Some(changes) = changes_rx.recv() => {
+                for c in &changes {
+                    sup.on_change(c, &cfg.listings);
+                }
+                sup.persist_info();
+            }
+            _ = &mut ctrl_c => break Ok(()),
+        }
+    };
+    store.close()?;
+    res
+}
+
+/// Per-process state shared by the recorders of all symbols.
+struct Recorder {
+    cfg: Config,
+    store: Arc<DataStore>,
+    telem: Arc<Telemetry>,
+    endpoints: Endpoints,
+    info: Arc<ExchangeInfo>,
+    fanout: Fanout,
+    /// Symbols with a running recorder.
+    recording: std::sync::Mutex<BTreeSet<String>>,
+}
+
+/// First delay before restarting a recorder that ended, doubled per restart
+/// up to `RESTART_BACKOFF_MAX`.
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// Whether a recorder that ended with `e` is worth restarting. REST rejecting
+/// the symbol and local failures (storage, shared memory) will not go away.
+fn recoverable(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<IngestError>()
+        .is_some_and(|e| matches!(e, IngestError::Transport(_)) || e.recovery() != Recovery::Abort)
+}
+
+/// Starts and stops symbol recorders as `exchangeInfo` changes, and restarts
+/// the ones that end while their symbol is still tradable.
+struct Supervisor {
+    rec: Arc<Recorder>,
+    tasks: JoinSet<(String, Result<()>)>,
+    running: HashMap<String, AbortHandle>,
+    /// Stopped while paused; restarted when back online.
+    waiting: BTreeSet<String>,
+    /// Restarts in a row per symbol and when the last one was due.
+    restarts: HashMap<String, (u32, Instant)>,
+    /// Last unrecoverable recorder error, returned once nothing is left to record.
+    failed: Option<anyhow::Error>,
+}
+
+impl Supervisor {
+    fn new(rec: Arc<Recorder>) -> Self {
+        Self {
+            rec,
+            tasks: JoinSet::new(),
+            running: HashMap::new(),
+            waiting: BTreeSet::new(),
+            restarts: HashMap::new(),
+            failed: None,
+        }
+    }
+
+    fn idle(&self) -> bool {
+        self.running.is_empty() && self.waiting.is_empty()
+    }
+
+    fn start(&mut self, symbol: &str) {
+        if self.start_after(symbol, Duration::ZERO) {
+            info!(symbol, "recording started");
+        }
+    }
+
+    /// Counts as running (a stop aborts it) while it waits out `delay`. False
+    /// if `symbol` was already running.
+    fn start_after(&mut self, symbol: &str, delay: Duration) -> bool {
+        self.waiting.remove(symbol);
+        if self.running.contains_key(symbol) {
+            return false;
+        }
+        let (rec, s) = (self.rec.clone(), symbol.to_string());
+        let handle = self.tasks.spawn(async move {
+            tokio::time::sleep(delay).await;
+            if let Err(e) = rec.info.persist(&rec.store, std::slice::from_ref(&s)) {
+                warn!(symbol = %s, error = %e, "failed to store exchange_info");
+            }
+            let r = record_symbol(rec, s.clone()).await;
+            (s, r)
+        });
+        self.running.insert(symbol.to_string(), handle);
+        self.rec.recording.lock().unwrap().insert(symbol.to_string());
+        true
+    }
+
+    /// Doubles per restart in a row; a recorder that ran for
+    /// `RESTART_BACKOFF_MAX` starts over at `RESTART_BACKOFF`.
+    fn restart_delay(&mut self, symbol: &str) -> Duration {
+        let now = Instant::now();
+        let (n, due) = self.restarts.entry(symbol.to_string()).or_insert((0, now));
+        if now.saturating_duration_since(*due) > RESTART_BACKOFF_MAX {
+            *n = 0;
+        }
+        let delay = RESTART_BACKOFF.saturating_mul(1 << (*n).min(16)).min(RESTART_BACKOFF_MAX);
+        *n += 1;
+        *due = now + delay;
+        delay
+    }
 
-    let t0 = Instant::now();
-    let mut snap_ver = reload_snapshot(&symbol, &mut asks, &mut bids).await?;
-    let rtt_ms = t0.elapsed().as_millis() as u64;
-    telem.record_rest_rtt_ms(rtt_ms).await;
+    /// False if `symbol` was not being recorded.
+    fn stop(&mut self, symbol: &str, reason: &str) -> bool {
+        let Some(h) = self.running.remove(symbol) else { return false };
+        h.abort();
+        self.rec.recording.lock().unwrap().remove(symbol);
+        warn!(symbol, reason, "recording stopped");
+        true
+    }
 
-    let ts_now = epoch_ms();
+    fn finished(&mut self, done: Result<(tokio::task::Id, (String, Result<()>)), tokio::task::JoinError>) {
+        let (id, outcome) = match done {
+            Ok((id, (_, r))) => (id, r),
+            Err(e) if e.is_cancelled() => return,
+            Err(e) => (e.id(), Err(anyhow!("recorder panicked: {e}"))),
+        };
+        // a recorder stopped and restarted since has a new id
+        let Some(symbol) = self.running.iter().find(|(_, h)| h.id() == id).map(|(s, _)| s.clone()) else { return };
+        self.running.remove(&symbol);
+        self.rec.recording.lock().unwrap().remove(&symbol);
+        let reason = match outcome {
+            Err(e) if !recoverable(&e) => {
+                error!(symbol = %symbol, error = %e, "recording failed");
+                self.failed = Some(e);
+                return;
+            }
+            Err(e) => e.to_string(),
+            Ok(()) => "depth stream ended".to_string(),
+        };
+        match self.rec.info.get(&symbol) {
+            None => info!(symbol = %symbol, "recording ended, symbol no longer listed"),
+            Some(i) if !i.tradable() => {
+                info!(symbol = %symbol, status = ?i.status, "recording ended, waiting for trading to resume");
+                self.waiting.insert(symbol);
+            }
+            Some(_) => {
+                let delay = self.restart_delay(&symbol);
+                warn!(symbol = %symbol, reason = %reason, retry_in_ms = delay.as_millis() as u64, "recording ended, restarting");
+                self.start_after(&symbol, delay);
+            }
+        }
+    }
+
+    fn on_change(&mut self, c: &SymbolChange, listings: &ListingsConfig) {
+        let symbol = c.symbol.as_str();
+        let candidate = c.old.is_none()
+            && c.new.as_ref().is_some_and(|n| listings.auto_record && listings.quote_assets.contains(&n.quote_asset));
+        // the rest of the exchange changes too; keep what concerns this recording
+        if candidate || self.running.contains_key(symbol) || self.waiting.contains(symbol) {
+            info!(
+                symbol, change = c.kind(),
+                from = ?c.old.as_ref().map(|i| i.status), to = ?c.new.as_ref().map(|i| i.status),
+                "exchangeInfo symbol change"
+            );
+            if let Err(e) = self.rec.store.append_event_json(symbol, clock::stamp(), c.kind(), c) {
+                warn!(symbol, error = %e, "failed to store symbol change");
+            }
+        }
+        match (&c.old, &c.new) {
+            (_, None) => {
+                self.waiting.remove(symbol);
+                self.stop(symbol, "delisted");
+            }
+            (Some(_), Some(new)) if new.tradable() => {
+                if self.waiting.contains(symbol) {
+                    self.start(symbol);
+                }
+            }
+            (Some(_), Some(new)) => {
+                if self.stop(symbol, &format!("status {:?}", new.status)) {
+                    self.waiting.insert(symbol.to_string());
+                }
+            }
+            (None, Some(new)) => {
+                let wanted = candidate && self.running.len() + self.waiting.len() < listings.max_symbols;
+                if !wanted {
+                    return;
+                }
+                info!(symbol, status = ?new.status, "new listing, recording");
+                if new.tradable() {
+                    self.start(symbol);
+                } else {
+                    self.waiting.insert(symbol.to_string());
+                }
+            }
+        }
+    }
+
+    /// Dated `exchange_info` for every recorded symbol after a refresh.
+    fn persist_info(&self) {
+        let symbols: Vec<String> = self.running.keys().cloned().collect();
+        if let Err(e) = self.rec.info.persist(&self.rec.store, &symbols) {
+            warn!(error = %e, "failed to store exchange_info");
+        }
+    }
+}
+
+/// Records one symbol until the task is aborted or the book can no longer be loaded.
+async fn record_symbol(rec: Arc<Recorder>, symbol: String) -> Result<()> {
+    let cfg = &rec.cfg;
+    let store = &rec.store;
+    let shm = if cfg.shm.enabled {
+        Some(ShmWriter::create(&cfg.shm.dir, &symbol, cfg.shm.levels)?)
+    } else {
+        None
+    };
+    if rec.fanout.mcast.is_some() {
+        McastPublisher::check_symbol(&symbol)?;
+    }
+
+    let mut book = DepthBook::new(Some(CrossedGuard::new(cfg.book.crossed_policy, cfg.book.crossed_tolerate_updates)));
+    book.precision = rec.info.precision(&symbol);
+    let snap_ver = reload_snapshot(&rec.endpoints.rest, &symbol, &mut book).await?;
+
+    let recv = clock::stamp();
     store.append_event_json(
         &symbol,
-        ts_now,
+        recv,
         "depth_snapshot",
         &DepthSnapshot {
             symbol: symbol.clone(),
-            ts_recv_ms: ts_now,
+            ts_recv_ms: recv.ms(),
             last_update_id: snap_ver,
-            bids: bids.iter().take(50).map(|(k,q)| [ (k.0).0, *q ]).collect(),
-            asks: asks.iter().take(50).map(|(k,q)| [ k.0, *q ]).collect(),
+            bids: book.bids.iter().take(50).map(|(k,q)| [ (k.0).0, *q ]).collect(),
+            asks: book.asks.iter().take(50).map(|(k,q)| [ k.0, *q ]).collect(),
         },
     )?;
+    let features = cfg.features.enabled.then(|| FeatureTap {
+        engine: Arc::new(std::sync::Mutex::new(FeatureEngine::new(&symbol, &cfg.features))),
+        store: store.clone(),
+        hub: rec.fanout.hub.clone(),
+    });
+    let sampler = cfg.sampler.enabled.then(|| {
+        let s = Sampler::new(&symbol, cfg.sampler.interval_ms, cfg.sampler.levels);
+        SamplerTap(Arc::new(std::sync::Mutex::new((s, SampleFiles::new(&cfg.sampler)))))
+    });
+    let align = cfg.align.enabled.then(|| AlignTap {
+        aligner: Arc::new(std::sync::Mutex::new(Aligner::new(&cfg.align))),
+        store: store.clone(),
+    });
+    let taps = Taps { features, sampler: sampler.clone(), align };
+    let mut sinks = BookSinks { fanout: rec.fanout.clone(), shm, taps: taps.clone() };
+    sinks.snapshot(&symbol, snap_ver, recv, &book.asks, &book.bids);
 
-    println!("REST snapshot loaded (lastUpdateId={})", snap_ver);
-    println!("levels: asks={} bids={}", asks.len(), bids.len());
-
-    let telem_clone = telem.clone();
-    tokio::spawn(clock_skew_task(telem_clone));
+    info!(symbol = %symbol, version = snap_ver, asks = book.asks.len(), bids = book.bids.len(), "REST snapshot loaded");
 
-    let store_tr = DataStore::new(&outdir)?;
-    let symbol_tr = symbol.clone();
-    tokio::spawn(async move {
-        if let Err(e) = trades_poller_rest(symbol_tr, store_tr).await {
-            eprintln!("trades poller stopped: {e}");
+    let validation = cfg.validation.enabled.then(|| {
+        let (tx, rx) = mpsc::channel(4);
+        let every = Duration::from_secs(cfg.validation.interval_secs.max(1));
+        // ends with the depth loop, on its next send
+        tokio::spawn(
+            validation_task(rec.endpoints.rest.clone(), symbol.clone(), cfg.validation.depth, every, tx)
+                .instrument(info_span!("validation", symbol = %symbol)),
+        );
+        Validation {
+            rx,
+            buffer: DeltaBuffer::new(cfg.validation.buffer_deltas),
+            pending: None,
+            max_mismatch_ratio: cfg.validation.max_mismatch_ratio,
         }
     });
 
-    depth_ws_loop(symbol, asks, bids, snap_ver, &store, telem).await?;
+    // polled here rather than spawned so that stopping the symbol stops its trades too
+    let trades = async {
+        if let Err(e) = trades_poller_rest(rec.endpoints.rest.clone(), symbol.clone(), store.clone(), rec.fanout.clone(), taps).await {
+            error!(error = %e, "trades poller stopped");
+        }
+        std::future::pending::<()>().await
+    };
+    // closes intervals that saw no events
+    let sample_ticks = async {
+        let Some(tap) = &sampler else { return std::future::pending().await };
+        let mut tick = tokio::time::interval(Duration::from_millis(cfg.sampler.interval_ms.max(1)));
+        loop {
+            tick.tick().await;
+            tap.feed(|s, rows| s.advance(clock::stamp().ms() - SAMPLE_LAG_MS, rows));
+        }
+    };
+
+    tokio::select! {
+        r = depth_ws_loop(symbol.clone(), &rec, book, sinks, validation)
+            .instrument(info_span!("depth", symbol = %symbol)) => r,
+        _ = trades.instrument(info_span!("trades", symbol = %symbol)) => Ok(()),
+        _ = sample_ticks => Ok(()),
+    }
+}
+
+/// `convert`: finished partitions to `out` (default `<data_dir>-parquet`).
+fn convert_cmd(data_dir: &Path, out: Option<PathBuf>, jobs: Option<usize>, cfg: &Config) -> Result<()> {
+    let out = out.unwrap_or_else(|| PathBuf::from(format!("{}-parquet", data_dir.display().to_string().trim_end_matches('/'))));
+    let opts = ConvertOptions {
+        jobs: jobs.unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4)),
+        row_group_rows: cfg.storage.parquet_row_group_rows,
+        ..Default::default()
+    };
+    let sum = convert_all(data_dir, &out, &opts)?;
+    println!("converted {} partitions, skipped {}, failed {}", sum.converted, sum.skipped, sum.failed.len());
+    if !sum.failed.is_empty() {
+        return Err(anyhow!("{} partitions failed", sum.failed.len()));
+    }
     Ok(())
 }
 
-async fn clock_skew_task(_telem: Arc<Telemetry>) {
+/// `verify`: rechecks finished partitions against their `manifest.json`,
+/// optionally writing manifests for partitions without one. Those are not
+/// counted as verified; only a truncated one is reported, as damaged.
+fn verify_cmd(root: &Path, write_missing: bool) -> Result<ExitCode> {
+    let now = clock::now_ns() / 1_000_000;
+    let (mut ok, mut open, mut written) = (0, 0, 0);
+    let mut damaged = Vec::new();
+    for part in list_partitions(root)? {
+        if part.end_ms()? + 60_000 > now {
+            open += 1;
+            continue;
+        }
+        let r = if write_missing && read_manifest(&part)?.is_none() {
+            // nothing to check against: only what the data says about itself
+            let m = write_manifest(&part)?;
+            written += 1;
+            let problems = if m.stats.truncated { vec!["data does not decode to the end".to_string()] } else { Vec::new() };
+            if problems.is_empty() {
+                continue;
+            }
+            VerifyReport { part: part.clone(), problems }
+        } else {
+            verify_partition(&part)?
+        };
+        if r.ok() {
+            ok += 1;
+        } else {
+            println!("DAMAGED {}", part.rel_dir().display());
+            for p in &r.problems {
+                println!("  {p}");
+            }
+            damaged.push(r);
+        }
+    }
+    println!(
+        "verified {ok} partitions ok, {} damaged, {open} still open, {written} manifests written (not verified)",
+        damaged.len()
+    );
+    Ok(if damaged.is_empty() { ExitCode::SUCCESS } else { ExitCode::from(EXIT_DATA) })
+}
+
+/// `audit`: one line per hour and a JSON report per symbol (default
+/// `audit-<symbol>.json`); dates are `YYYY-MM-DD`, inclusive.
+fn audit_cmd(root: &Path, symbols: &[String], from: Option<&str>, to: Option<&str>, out: Option<&Path>) -> Result<()> {
+    if out.is_some() && symbols.len() > 1 {
+        return Err(anyhow!("--out needs a single symbol"));
+    }
+    let from = from.unwrap_or_default();
+    let to = to.unwrap_or(if from.is_empty() { "9999" } else { from });
+    for symbol in symbols {
+        let report = audit_symbol(root, symbol, from, to, &AuditOptions::default())?;
+        for h in &report.hours {
+            println!("{}", h.summary_line());
+        }
+        println!("{}", report.total.summary_line());
+        let out = out.map_or_else(|| PathBuf::from(format!("audit-{symbol}.json")), Path::to_path_buf);
+        std::fs::write(&out, serde_json::to_vec_pretty(&report)?)?;
+        println!("report written to {}", out.display());
+    }
+    Ok(())
+}
+
+/// `fixture`: cuts a golden-file fixture (see `fixture.rs`) out of a recorded
+/// partition, using the `[book]` crossed policy.
+fn fixture_cmd(part: &Path, out: &Path, max_frames: usize, cfg: &Config) -> Result<()> {
+    let golden = record_fixture(part, out, cfg.book.crossed_policy, max_frames)?;
+    let c = &golden.counters;
+    println!(
+        "fixture written to {}: {} frames, {} applied, {} skipped, {} gaps, {} resyncs, {} crossed",
+        out.display(), c.frames, c.applied, c.skipped, c.gaps, c.resyncs, c.crossed
+    );
+    Ok(())
+}
+
+/// Partitions of `symbols` overlapping `from..=to` (unix ms), in order.
+fn partitions_between(root: &Path, symbols: &[String], from: i64, to: i64) -> Result<Vec<Partition>> {
+    let mut out = Vec::new();
+    for p in list_partitions(root)? {
+        if symbols.contains(&p.symbol) && p.end_ms()? > from && p.start_ms()? <= to {
+            out.push(p);
+        }
+    }
+    Ok(out)
+}
+
+/// `cat`: stored events as NDJSON, symbol by symbol; raw frames get `payload_b64`.
+fn cat_cmd(root: &Path, symbols: &[String], kinds: &[String], from: i64, to: i64, limit: usize) -> Result<()> {
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    let mut n = 0;
+    for part in partitions_between(root, symbols, from, to)? {
+        for ev in read_partition(&part)? {
+            let mut ev = ev?;
+            if ev.ts_ms < from || ev.ts_ms > to || !(kinds.is_empty() || kinds.contains(&ev.kind)) {
+                continue;
+            }
+            if n == limit {
+                return Ok(out.flush()?);
+            }
+            if let Some(raw) = ev.raw_bytes.take() {
+                ev.payload_b64 = Some(base64::engine::general_purpose::STANDARD.encode(raw));
+            }
+            serde_json::to_writer(&mut out, &ev)?;
+            out.write_all(b"\n")?;
+            n += 1;
+        }
+    }
+    Ok(out.flush()?)
+}
+
+/// `replay`: the top `levels` after every book change as NDJSON, and a summary
+/// per symbol on stderr.
+fn replay_cmd(root: &Path, symbols: &[String], from: i64, to: i64, levels: usize) -> Result<()> {
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    for symbol in symbols {
+        let book = replay_range(root, symbol, from, to, |b| {
+            if b.depth.valid {
+                serde_json::to_writer(&mut out, &b.snapshot(levels))?;
+                out.write_all(b"\n")?;
+            }
+            Ok(())
+        })?;
+        let s = &book.stats;
+        eprintln!(
+            "{symbol}: {} events, {} snapshots, {} frames, {} applied, {} skipped, {} breaks, {} read errors",
+            s.events, s.snapshots, s.depth_frames, s.applied, s.skipped, s.breaks, s.read_errors
+        );
+    }
+    Ok(out.flush()?)
+}
+
+/// `features`: feature rows of each symbol as NDJSON.
+fn features_cmd(root: &Path, symbols: &[String], from: i64, to: i64, fc: &config::FeaturesConfig) -> Result<()> {
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    for symbol in symbols {
+        replay_features(root, symbol, from, to, fc, |row| {
+            serde_json::to_writer(&mut out, row)?;
+            Ok(out.write_all(b"\n")?)
+        })?;
+    }
+    Ok(out.flush()?)
+}
+
+/// `sample`: one file of grid rows per symbol, and a summary per symbol on stderr.
+fn sample_cmd(root: &Path, symbols: &[String], from: i64, to: i64, sc: &config::SamplerConfig, out: &Path) -> Result<()> {
+    for symbol in symbols {
+        let path = out.join(format!("{symbol}_{}ms.{}", sc.interval_ms, sc.format.extension()));
+        let mut w = SampleWriter::create(&path, sc.format, sc.levels)?;
+        let (mut rows, mut gaps) = (0u64, 0u64);
+        replay_samples(root, symbol, from, to, sc.interval_ms, sc.levels, |r| {
+            rows += 1;
+            gaps += r.gap as u64;
+            w.write(r)
+        })?;
+        w.finish()?;
+        eprintln!("{symbol}: {rows} rows, {gaps} in gaps -> {}", w.path().display());
+    }
+    Ok(())
+}
+
+/// `align`: aligned trades as NDJSON, and a summary per symbol on stderr.
+fn align_cmd(root: &Path, symbols: &[String], from: i64, to: i64, ac: &config::AlignConfig) -> Result<()> {
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    for symbol in symbols {
+        let (_, s) = replay_aligned(root, symbol, from, to, ac, |t| {
+            serde_json::to_writer(&mut out, t)?;
+            Ok(out.write_all(b"\n")?)
+        })?;
+        eprintln!(
+            "{symbol}: {} trades, {} aligned, side agrees {} / disagrees {}, {} inside spread, {} off level, {} exceed visible",
+            s.trades, s.aligned, s.side_agrees, s.side_disagrees, s.inside_spread, s.off_level, s.exceeds_visible
+        );
+    }
+    Ok(out.flush()?)
+}
+
+/// `book-at`: the book of each symbol as of `at`, as JSON.
+fn book_at_cmd(root: &Path, symbols: &[String], at: i64, levels: usize) -> Result<ExitCode> {
+    let mut code = ExitCode::SUCCESS;
+    for symbol in symbols {
+        match book_at(root, symbol, at, levels)? {
+            Some(book) => println!("{}", serde_json::to_string_pretty(&book)?),
+            None => {
+                eprintln!("{symbol}: no valid book at {at}");
+                code = ExitCode::from(EXIT_DATA);
+            }
+        }
+    }
+    Ok(code)
+}
+
+/// `stats`: the manifest stats of each partition, scanned where there is no manifest yet.
+fn stats_cmd(root: &Path, symbols: &[String], from: i64, to: i64, json: bool) -> Result<()> {
+    #[derive(serde::Serialize)]
+    struct Row {
+        partition: String,
+        manifest: bool,
+        stats: PartitionStats,
+    }
+    let mut rows = Vec::new();
+    for part in partitions_between(root, symbols, from, to)? {
+        let (manifest, stats) = match read_manifest(&part)? {
+            Some(m) => (true, m.stats),
+            None => (false, scan_partition(&part)?),
+        };
+        rows.push(Row { partition: part.rel_dir().display().to_string(), manifest, stats });
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    for r in &rows {
+        let s = &r.stats;
+        let kinds: Vec<String> = s.by_kind.iter().map(|(k, n)| format!("{k}={n}")).collect();
+        println!(
+            "{}: {} events ({}), {} raw frames, versions {}..{}, {} gaps, {} resyncs, {} bad lines{}{}",
+            r.partition,
+            s.events,
+            kinds.join(" "),
+            s.raw_frames,
+            s.min_version.map_or("-".to_string(), |v| v.to_string()),
+            s.max_version.map_or("-".to_string(), |v| v.to_string()),
+            s.gaps,
+            s.resyncs,
+            s.bad_lines,
+            if s.truncated { ", truncated" } else { "" },
+            if r.manifest { "" } else { ", open" },
+        );
+    }
+    println!(
+        "{} partitions, {} events, {} raw frames, {} gaps, {} resyncs",
+        rows.len(),
+        rows.iter().map(|r| r.stats.events).sum::<u64>(),
+        rows.iter().map(|r| r.stats.raw_frames).sum::<u64>(),
+        rows.iter().map(|r| r.stats.gaps).sum::<u64>(),
+        rows.iter().map(|r| r.stats.resyncs).sum::<u64>(),
+    );
+    Ok(())
+}
+
+/// Cross-task consumers of book and trade updates; cheap to clone.
+#[derive(Clone, Default)]
+struct Fanout {
+    hub: Option<Arc<BookHub>>,
+    mcast: Option<Arc<McastPublisher>>,
+}
+
+impl Fanout {
+    fn trade(&self, symbol: &str, t: &TradeEvent) {
+        if let Some(hub) = &self.hub {
+            hub.publish_trade(symbol, t);
+        }
+        if let Some(mc) = &self.mcast {
+            mc.publish_trade(symbol, t);
+        }
+    }
+}
+
+/// A symbol's `FeatureEngine`, fed by both its depth loop and its trades poller.
+#[derive(Clone)]
+struct FeatureTap {
+    engine: Arc<std::sync::Mutex<FeatureEngine>>,
+    store: Arc<DataStore>,
+    hub: Option<Arc<BookHub>>,
+}
+
+impl FeatureTap {
+    fn book(&self, symbol: &str, recv: clock::Stamp, version: u64, asks: &BookSide, bids: &RevSide) {
+        let Some(row) = self.engine.lock().unwrap().on_book(recv.ms(), version, asks, bids) else { return };
+        let _ = self.store.append_event_json(symbol, recv, "features", &row);
+        if let Some(hub) = &self.hub {
+            hub.publish_features(symbol, &row);
+        }
+    }
+
+    fn trade(&self, t: &TradeEvent) {
+        self.engine.lock().unwrap().on_trade(t);
+    }
+}
+
+/// The sampling timer closes a grid point this long after it, so that events
+/// stamped just before it but fed a little later still count.
+const SAMPLE_LAG_MS: i64 = 100;
+
+/// A symbol's `Sampler` and its files, fed by the depth loop, the trades
+/// poller and the sampling timer.
+#[derive(Clone)]
+struct SamplerTap(Arc<std::sync::Mutex<(Sampler, SampleFiles)>>);
+
+impl SamplerTap {
+    fn feed(&self, f: impl FnOnce(&mut Sampler, &mut Vec<SampleRow>)) {
+        let mut guard = self.0.lock().unwrap();
+        let (sampler, files) = &mut *guard;
+        let mut rows = Vec::new();
+        f(sampler, &mut rows);
+        for r in &rows {
+            if let Err(e) = files.write(r) {
+                warn!(error = %e, "failed to write sample");
+            }
+        }
+    }
+}
+
+/// A symbol's `Aligner`; aligned trades are stored as `trade_aligned` events.
+#[derive(Clone)]
+struct AlignTap {
+    aligner: Arc<std::sync::Mutex<Aligner>>,
+    store: Arc<DataStore>,
+}
+
+/// Per-symbol consumers of both the book and the trades, each optional.
+#[derive(Clone, Default)]
+struct Taps {
+    features: Option<FeatureTap>,
+    sampler: Option<SamplerTap>,
+    align: Option<AlignTap>,
+}
+
+impl Taps {
+    fn trade(&self, t: &TradeEvent) {
+        if let Some(f) = &self.features {
+            f.trade(t);
+        }
+        if let Some(tap) = &self.sampler {
+            tap.feed(|s, rows| s.on_trade(t, rows));
+        }
+        if let Some(a) = &self.align {
+            let aligned = a.aligner.lock().unwrap().on_trade(t);
+            let _ = a.store.append_event_json(&t.symbol, clock::stamp(), "trade_aligned", &aligned);
+        }
+    }
+}
+
+/// Live consumers of the reconstructed book besides the on-disk store.
+struct BookSinks {
+    fanout: Fanout,
+    shm: Option<ShmWriter>,
+    taps: Taps,
+}
+
+impl BookSinks {
+    fn snapshot(&mut self, symbol: &str, version: u64, recv: clock::Stamp, asks: &BookSide, bids: &RevSide) {
+        let ts_recv_ms = recv.ms();
+        if let Some(hub) = &self.fanout.hub {
+            hub.publish_snapshot(symbol, version, asks, bids);
+        }
+        if let Some(mc) = &self.fanout.mcast {
+            mc.publish_snapshot(symbol, version, ts_recv_ms, asks, bids);
+        }
+        if let Some(shm) = &mut self.shm {
+            shm.publish(version, ts_recv_ms, None, asks, bids);
+        }
+        if let Some(f) = &self.taps.features {
+            f.engine.lock().unwrap().reset_book();
+            f.book(symbol, recv, version, asks, bids);
+        }
+        if let Some(tap) = &self.taps.sampler {
+            tap.feed(|s, rows| s.on_snapshot(ts_recv_ms, version, asks, bids, rows));
+        }
+        if let Some(a) = &self.taps.align {
+            a.aligner.lock().unwrap().on_book(ts_recv_ms, version, asks, bids);
+        }
+    }
+
+    fn delta(&mut self, symbol: &str, d: &AppliedDelta, recv: clock::Stamp, asks: &BookSide, bids: &RevSide) {
+        let ts_recv_ms = recv.ms();
+        if let Some(hub) = &self.fanout.hub {
+            hub.publish_delta(symbol, d);
+        }
+        if let Some(mc) = &self.fanout.mcast {
+            mc.publish_delta(symbol, d, ts_recv_ms);
+        }
+        if let Some(shm) = &mut self.shm {
+            shm.publish(d.to_version, ts_recv_ms, d.ts_exch_ms, asks, bids);
+        }
+        if let Some(f) = &self.taps.features {
+            f.book(symbol, recv, d.to_version, asks, bids);
+        }
+        if let Some(tap) = &self.taps.sampler {
+            tap.feed(|s, rows| s.on_book(ts_recv_ms, d.to_version, asks, bids, rows));
+        }
+        if let Some(a) = &self.taps.align {
+            a.aligner.lock().unwrap().on_book(d.ts_exch_ms.unwrap_or(ts_recv_ms), d.to_version, asks, bids);
+        }
+    }
+
+    /// The book is unusable until the next snapshot.
+    fn broken(&mut self, ts_recv_ms: i64) {
+        if let Some(tap) = &self.taps.sampler {
+            tap.feed(|s, rows| s.on_break(ts_recv_ms, rows));
+        }
+        if let Some(a) = &self.taps.align {
+            a.aligner.lock().unwrap().on_break(ts_recv_ms);
+        }
+    }
+}
+
+async fn clock_skew_task(_telem: Arc<Telemetry>, rest: Arc<MexcRestClient>) {
     loop {
-        match reqwest::get("https://api.mexc.com/api/v3/time").await {
-            Ok(resp) => {
-                if let Ok(json) = resp.json::<serde_json::Value>().await {
-                    let ts_local = epoch_ms();
-                    if let Some(server) = json.get("serverTime").and_then(|v| v.as_i64()) {
-                        let _sample = ClockSkewSample {
-                            ts_local_ms: ts_local,
-                            server_time_ms: server,
-                            offset_ms: server - ts_local,
-                        };
-                     
-                    }
-                }
+        match rest.server_time().await {
+            Ok(server) => {
+                let ts_local = clock::now_ns() / 1_000_000;
+                let _sample = ClockSkewSample {
+                    ts_local_ms: ts_local,
+                    server_time_ms: server,
+                    offset_ms: server - ts_local,
+                };
             }
-            Err(e) => eprintln!("clock skew req error: {e}"),
+            Err(e) => warn!(error = %e, "clock skew request failed"),
         }
         tokio::time::sleep(Duration::from_secs(30)).await;
     }
 }
 
-async fn depth_ws_loop(
-    symbol: String,
-    mut asks: BookSide,
-    mut bids: RevSide,
-    mut snap_ver: u64,
-    store: &DataStore,
-    telem: Arc<Telemetry>,
-) -> Result<()> {
-    let url = "wss://wbs-api.mexc.com/ws";
+/// Re-reads the wall clock every minute and records how far it drifted from
+/// the monotonic anchor, under every recorded symbol.
+async fn clock_anchor_task(rec: Arc<Recorder>) {
+    let mut tick = tokio::time::interval(Duration::from_secs(60));
+    tick.tick().await;
+    loop {
+        tick.tick().await;
+        let sample = clock::clock().reanchor();
+        if sample.drift_ns.abs() > 5_000_000 {
+            warn!(drift_ms = sample.drift_ns as f64 / 1e6, "clock re-anchored");
+        }
+        let symbols: Vec<String> = rec.recording.lock().unwrap().iter().cloned().collect();
+        for symbol in &symbols {
+            let _ = rec.store.append_event_json(symbol, clock::stamp(), "clock_anchor", &sample);
+        }
+    }
+}
+
+/// Periodic REST comparison state owned by the depth loop.
+struct Validation {
+    rx: mpsc::Receiver<DepthSnapshot>,
+    buffer: DeltaBuffer,
+    /// REST snapshot newer than the live book, waiting for it to catch up.
+    pending: Option<DepthSnapshot>,
+    max_mismatch_ratio: f64,
+}
+
+impl Validation {
+    fn check(&mut self, asks: &BookSide, bids: &RevSide, snap_ver: u64) -> Option<BookValidation> {
+        let rest = self.pending.take()?;
+        match validate(&rest, &self.buffer, asks, bids, snap_ver) {
+            Some(mut v) => {
+                v.resync = v.aligned && v.mismatch_ratio > self.max_mismatch_ratio;
+                Some(v)
+            }
+            None => {
+                self.pending = Some(rest);
+                None
+            }
+        }
+    }
+}
+
+/// Fetches `/api/v3/depth` every `interval` for the depth loop to compare against.
+async fn validation_task(rest: Arc<MexcRestClient>, symbol: String, limit: usize, interval: Duration, tx: mpsc::Sender<DepthSnapshot>) {
+    let mut tick = tokio::time::interval(interval);
+    tick.tick().await;
+    loop {
+        tick.tick().await;
+        let snap = match rest.depth(&symbol, limit).await {
+            Ok(s) => s,
+            Err(e) => {
+                warn!(error = %e, "validation snapshot failed");
+                continue;
+            }
+        };
+        let levels = |side: &[[String; 2]]| -> Vec<[f64; 2]> {
+            side.iter().filter_map(|[p, q]| Some([p.parse().ok()?, q.parse().ok()?])).collect()
+        };
+        let rest = DepthSnapshot {
+            symbol: symbol.clone(),
+            ts_recv_ms: clock::stamp().ms(),
+            last_update_id: snap.last_update_id,
+            bids: levels(&snap.bids),
+            asks: levels(&snap.asks),
+        };
+        if tx.send(rest).await.is_err() {
+            return;
+        }
+    }
+}
+
+type WsStream = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn subscribe_depth(url: &str, chan: &str) -> Result<WsStream, IngestError> {
     let (mut ws, _) = connect_async(url).await?;
-    let chan = format!("spot@public.aggre.depth.v3.api.pb@10ms@{symbol}");
     ws.send(WsMsg::Text(
         serde_json::json!({
             "method": "SUBSCRIPTION",
@@ -124,64 +1181,92 @@ async fn depth_ws_loop(
         }).to_string(),
     ))
     .await?;
+    Ok(ws)
+}
+
+/// Waits, reconnects and resubscribes, doubling the wait per failed attempt.
+async fn reconnect_depth(cfg: &WebsocketConfig, chan: &str) -> WsStream {
+    let mut delay = Duration::from_millis(cfg.reconnect_backoff_ms);
+    loop {
+        tokio::time::sleep(delay).await;
+        match subscribe_depth(&cfg.url, chan).await {
+            Ok(ws) => return ws,
+            Err(e) => warn!(channel = %chan, error = %e, retry_in_ms = delay.as_millis() as u64 * 2, "reconnect failed"),
+        }
+        delay = (delay * 2).min(Duration::from_millis(cfg.max_reconnect_backoff_ms.max(cfg.reconnect_backoff_ms)));
+    }
+}
+
+/// Applies depth frames until the task is aborted. When the socket drops it
+/// reconnects and resyncs from REST, since frames were missed in between.
+async fn depth_ws_loop(
+    symbol: String,
+    rec: &Recorder,
+    mut book: DepthBook,
+    mut sinks: BookSinks,
+    mut validation: Option<Validation>,
+) -> Result<()> {
+    let (endpoints, store, telem) = (&rec.endpoints, &rec.store, &rec.telem);
+    let chan = format!("spot@public.aggre.depth.v3.api.pb@10ms@{symbol}");
+    let mut ws = subscribe_depth(&endpoints.ws.url, &chan).await?;
 
     let mut ping_tick = tokio::time::interval(Duration::from_secs(30));
-    let mut last_to_ver: Option<u64> = None;
     let mut last_ping_sent: Option<Instant> = None;
+    let mut resync_not_before = Instant::now();
 
     loop {
+        let mut failure: Option<IngestError> = None;
+        let mut broke = false;
+        let mut checked: Option<BookValidation> = None;
+        let mut lost = false;
         tokio::select! {
             _ = ping_tick.tick() => {
                 last_ping_sent = Some(Instant::now());
                 let _ = ws.send(WsMsg::Ping(Vec::new())).await;
             }
+            Some(rest) = async { validation.as_mut()?.rx.recv().await }, if validation.is_some() => {
+                if let Some(val) = &mut validation {
+                    val.pending = Some(rest);
+                    checked = val.check(&book.asks, &book.bids, book.version);
+                }
+            }
             msg = ws.next() => {
                 match msg {
                     Some(Ok(WsMsg::Binary(buf))) => {
-                        let recv_ts = epoch_ms();
-
-                      
-                        let _ = store.append_event_raw_b64(&symbol, recv_ts, "depth_pb_raw", &buf);
-
-                        match handle_diff_update(buf.into(), &mut asks, &mut bids, &mut snap_ver, &mut last_to_ver) {
-                            Ok(()) => {
-                                let _ = store.append_event_json(&symbol, recv_ts, "depth_delta", &DepthDelta{
-                                    symbol: symbol.clone(),
-                                    ts_recv_ms: recv_ts,
-                                    from_version: last_to_ver.unwrap_or(snap_ver),
-                                    to_version: snap_ver,
-                                    bids: bids.iter().take(50).map(|(k,q)| [ (k.0).0, *q ]).collect(),
-                                    asks: asks.iter().take(50).map(|(k,q)| [ k.0, *q ]).collect(),
-                                });
-                            }
-                            Err(e) => {
-                                {
-                                    let mut gaps = telem.gap_counter.lock().await;
-                                    *gaps += 1;
-                                }
-                                eprintln!("delta error ({e}), trying resync");
-                                if let Ok(new_ver) = reload_snapshot(&symbol, &mut asks, &mut bids).await {
-                                    snap_ver = new_ver;
-                                    last_to_ver = None;
-                                    {
-                                        let mut r = telem.resync_counter.lock().await;
-                                        *r += 1;
-                                    }
-                                    eprintln!("resynced via REST (lastUpdateId={})", snap_ver);
-                                    let ts_now = epoch_ms();
-                                    let _ = store.append_event_json(
-                                        &symbol, ts_now, "depth_snapshot",
-                                        &DepthSnapshot {
-                                            symbol: symbol.clone(),
-                                            ts_recv_ms: ts_now,
-                                            last_update_id: snap_ver,
-                                            bids: bids.iter().take(50).map(|(k,q)| [ (k.0).0, *q ]).collect(),
-                                            asks: asks.iter().take(50).map(|(k,q)| [ k.0, *q ]).collect(),
-                                        }
-                                    );
-                                }
+                        let recv = clock::stamp();
+                        let _ = store.capture_raw(&symbol, recv, RawChannel::AggreDepth, &buf);
+
+                        let out = book.apply_frame(&symbol, buf.into(), recv.ms());
+                        if out.crossed.started {
+                            *telem.crossed_counter.lock().await += 1;
+                        }
+                        if let Some(ev) = &out.crossed.event {
+                            warn!(
+                                channel = %chan, version = ev.version, best_bid = ev.best_bid, best_ask = ev.best_ask,
+                                action = %ev.action, updates = ev.updates, "crossed book"
+                            );
+                            let _ = store.append_event_json(&symbol, recv, "crossed_book", ev);
+                        }
+                        if out.off_grid > 0 {
+                            warn!(channel = %chan, version = book.version, levels = out.off_grid, precision = ?book.precision, "levels off the tick/lot grid");
+                        }
+                        if let Some(d) = out.delta {
+                            sinks.delta(&symbol, &d, recv, &book.asks, &book.bids);
+                            let _ = store.append_event_json(&symbol, recv, "depth_delta", &DepthDelta{
+                                symbol: symbol.clone(),
+                                ts_recv_ms: recv.ms(),
+                                from_version: d.from_version,
+                                to_version: book.version,
+                                bids: book.bids.iter().take(50).map(|(k,q)| [ (k.0).0, *q ]).collect(),
+                                asks: book.asks.iter().take(50).map(|(k,q)| [ k.0, *q ]).collect(),
+                            });
+                            if let Some(val) = &mut validation {
+                                val.buffer.push(d);
+                                checked = val.check(&book.asks, &book.bids, book.version);
                             }
                         }
+                        failure = out.error;
+                        broke = out.broke;
                     }
                     Some(Ok(WsMsg::Pong(_))) => {
                         if let Some(t0) = last_ping_sent.take() {
@@ -192,117 +1277,123 @@ async fn depth_ws_loop(
                     Some(Ok(WsMsg::Ping(p))) => {
                         let _ = ws.send(WsMsg::Pong(p)).await;
                     }
-                    Some(Ok(WsMsg::Close(_))) | None => break,
-                    Some(Ok(_)) => {}
-                    Some(Err(e)) => {
-                        eprintln!("ws error: {e}");
-                        break;
+                    Some(Ok(WsMsg::Close(_))) | None => {
+                        warn!(channel = %chan, version = book.version, "depth socket closed");
+                        lost = true;
                     }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => failure = Some(e.into()),
                 }
             }
         }
-    }
-    Ok(())
-}
-
-async fn reload_snapshot(symbol: &str, asks: &mut BookSide, bids: &mut RevSide) -> Result<u64> {
-    let snap: Snapshot = reqwest::get(format!(
-        "https://api.mexc.com/api/v3/depth?symbol={symbol}&limit=1000"
-    ))
-    .await?
-    .json()
-    .await?;
-
-    asks.clear();
-    bids.clear();
-    for [p, q] in snap.asks.iter() {
-        asks.insert(OrderedFloat(p.parse::<f64>()?), q.parse()?);
-    }
-    for [p, q] in snap.bids.iter() {
-        bids.insert(Reverse(OrderedFloat(p.parse::<f64>()?)), q.parse()?);
-    }
-    Ok(snap.last_update_id)
-}
-
-fn handle_diff_update(
-    buf: Bytes,
-    asks: &mut BookSide,
-    bids: &mut RevSide,
-    snap_ver: &mut u64,
-    last_to_ver: &mut Option<u64>,
-) -> Result<()> {
-    use mexc_pb::push_data_v3_api_wrapper::Body;
-
-    let wrapper = PushDataV3ApiWrapper::decode(buf)?;
-    let Some(body) = wrapper.body else { return Ok(()); };
-    let Body::PublicAggreDepths(delta) = body else { return Ok(()); };
 
-    let from_v: u64 = delta.from_version.parse()?;
-    let to_v: u64 = delta.to_version.parse()?;
-
-    if to_v <= *snap_ver {
-        return Ok(());
-    }
-
-    let needed = *snap_ver + 1;
-    if !(from_v <= needed && needed <= to_v) {
-        if from_v > needed {
-            let gap = from_v.saturating_sub(needed);
-            if last_to_ver.is_none() || gap <= 1000 {
-                *snap_ver = from_v - 1;
-            } else {
-                return Err(anyhow!("sequence gap: need {}, got {}..{}", needed, from_v, to_v));
+        if let Some(e) = failure {
+            match e.recovery() {
+                Recovery::Skip => debug!(channel = %chan, version = book.version, error = %e, "skipped message"),
+                Recovery::Resync | Recovery::Retry => {
+                    // a broken book waiting for its snapshot keeps gapping; count it once
+                    if broke {
+                        if matches!(e, IngestError::SequenceGap { .. }) {
+                            *telem.gap_counter.lock().await += 1;
+                        }
+                        sinks.broken(clock::stamp().ms());
+                    }
+                    warn!(channel = %chan, version = book.version, error = %e, "resyncing");
+                }
+                Recovery::Abort => {
+                    warn!(channel = %chan, version = book.version, error = %e, "depth socket failed");
+                    lost = true;
+                }
             }
-        } else {
-            return Ok(());
         }
-    }
 
-    for it in delta.asks {
-        let p = OrderedFloat(it.price.parse::<f64>()?);
-        let q: f64 = it.quantity.parse()?;
-        if q == 0.0 { asks.remove(&p); } else { asks.insert(p, q); }
-    }
-    for it in delta.bids {
-        let p = Reverse(OrderedFloat(it.price.parse::<f64>()?));
-        let q: f64 = it.quantity.parse()?;
-        if q == 0.0 { bids.remove(&p); } else { bids.insert(p, q); }
-    }
+        if let Some(v) = checked {
+            if v.resync {
+                warn!(
+                    channel = %chan, version = v.local_version, rest_version = v.rest_version,
+                    mismatched = v.mismatched_levels, compared = v.compared_levels,
+                    "book diverged from REST, resyncing"
+                );
+                if book.break_book() {
+                    sinks.broken(clock::stamp().ms());
+                }
+            }
+            let _ = store.append_event_json(&symbol, clock::stamp(), "book_validation", &v);
+        }
 
-    *snap_ver = to_v;
-    *last_to_ver = Some(to_v);
+        if lost {
+            // whatever was pushed while disconnected is gone
+            if book.break_book() {
+                sinks.broken(clock::stamp().ms());
+            }
+            resync_not_before = Instant::now();
+            ws = reconnect_depth(&endpoints.ws, &chan).await;
+            last_ping_sent = None;
+            info!(channel = %chan, "depth socket reconnected");
+        }
 
-    if let (Some((ask, _)), Some((bid, _))) = (asks.first_key_value(), bids.first_key_value()) {
-        if bid.0 .0 <= ask.0 {
-            let spread = ask.0 - bid.0 .0;
-            println!(
-                "bid {:>12.6} | ask {:>12.6} | spread {:>8.5} %",
-                bid.0 .0, ask.0, spread / ask.0 * 100.0
-            );
-        } else {
-            eprintln!("crossed book");
+        if !book.valid && Instant::now() >= resync_not_before {
+            let gaps = *telem.gap_counter.lock().await;
+            match reload_snapshot(&endpoints.rest, &symbol, &mut book).await {
+                Ok(_) => {
+                    // picks up tick or lot size changes from the last exchangeInfo refresh
+                    book.precision = rec.info.precision(&symbol);
+                    if let Some(val) = &mut validation {
+                        val.buffer.clear();
+                        val.pending = None;
+                    }
+                    let resyncs = {
+                        let mut r = telem.resync_counter.lock().await;
+                        *r += 1;
+                        *r
+                    };
+                    if let Some(shm) = &mut sinks.shm {
+                        shm.set_counters(gaps, resyncs);
+                    }
+                    info!(channel = %chan, version = book.version, gaps, resyncs, "resynced via REST");
+                    let snap_recv = clock::stamp();
+                    let _ = store.append_event_json(
+                        &symbol, snap_recv, "depth_snapshot",
+                        &DepthSnapshot {
+                            symbol: symbol.clone(),
+                            ts_recv_ms: snap_recv.ms(),
+                            last_update_id: book.version,
+                            bids: book.bids.iter().take(50).map(|(k,q)| [ (k.0).0, *q ]).collect(),
+                            asks: book.asks.iter().take(50).map(|(k,q)| [ k.0, *q ]).collect(),
+                        }
+                    );
+                    sinks.snapshot(&symbol, book.version, snap_recv, &book.asks, &book.bids);
+                }
+                Err(e) if e.recovery() == Recovery::Abort => {
+                    error!(channel = %chan, version = book.version, error = %e, "resync failed");
+                    return Err(e.into());
+                }
+                Err(e) => {
+                    warn!(channel = %chan, version = book.version, error = %e, "resync failed, retrying");
+                    resync_not_before = Instant::now() + Duration::from_secs(1);
+                }
+            }
         }
     }
-    Ok(())
 }
 
-async fn trades_poller_rest(symbol: String, store: DataStore) -> Result<()> {
-    #[derive(Deserialize)]
-    struct RespTrade {
-        id: Option<u64>,
-        price: String,
-        qty: String,
-        #[serde(default)]
-        quoteQty: Option<String>,
-        time: Option<i64>,
-        #[serde(default)]
-        isBuyerMaker: Option<bool>,
-        #[serde(default)]
-        isBestMatch: Option<bool>,
-        #[serde(default)]
-        tradeType: Option<String>,
+/// Replaces `book` with the REST snapshot; returns its version.
+async fn reload_snapshot(rest: &MexcRestClient, symbol: &str, book: &mut DepthBook) -> Result<u64, IngestError> {
+    let snap = rest.depth(symbol, 1000).await?;
+
+    book.asks.clear();
+    book.bids.clear();
+    for [p, q] in snap.asks.iter() {
+        book.asks.insert(OrderedFloat(parse_num::<f64>("price", p)?), parse_num("quantity", q)?);
+    }
+    for [p, q] in snap.bids.iter() {
+        book.bids.insert(Reverse(OrderedFloat(parse_num::<f64>("price", p)?)), parse_num("quantity", q)?);
     }
+    book.resynced(snap.last_update_id);
+    Ok(snap.last_update_id)
+}
 
+async fn trades_poller_rest(rest: Arc<MexcRestClient>, symbol: String, store: Arc<DataStore>, fanout: Fanout, taps: Taps) -> Result<()> {
     struct Dedup {
         set: HashSet<u64>,
         q: VecDeque<u64>,
@@ -320,7 +1411,7 @@ async fn trades_poller_rest(symbol: String, store: DataStore) -> Result<()> {
             } else { false }
         }
     }
-    fn key_hash(t: &RespTrade) -> u64 {
+    fn key_hash(t: &RestTrade) -> u64 {
         use std::collections::hash_map::DefaultHasher;
         let mut h = DefaultHasher::new();
         let ts = t.time.unwrap_or(0);
@@ -328,38 +1419,18 @@ async fn trades_poller_rest(symbol: String, store: DataStore) -> Result<()> {
         let p = t.price.parse::<f64>().unwrap_or(0.0).to_bits();
         let q = t.qty.parse::<f64>().unwrap_or(0.0).to_bits();
         p.hash(&mut h); q.hash(&mut h);
-        let side = t.isBuyerMaker.unwrap_or(false);
+        let side = t.is_buyer_maker.unwrap_or(false);
         side.hash(&mut h);
         h.finish()
     }
 
-    let client = reqwest::Client::new();
     let mut last_ts: i64 = 0;
     let mut dedup = Dedup::new(10_000);
 
     loop {
-        let url = format!("https://api.mexc.com/api/v3/trades?symbol={}&limit=1000", symbol);
-        let mut req = client.get(&url);
-        if let Ok(k) = std::env::var("MEXC_API_KEY") { req = req.header("X-MEXC-APIKEY", k); }
-
-        let resp = match req.send().await {
-            Ok(r) => r,
-            Err(e) => { eprintln!("trades http err: {e}"); tokio::time::sleep(Duration::from_millis(500)).await; continue; }
-        };
-        let status = resp.status();
-        let body = match resp.text().await {
-            Ok(b) => b,
-            Err(e) => { eprintln!("trades body err: {e}"); tokio::time::sleep(Duration::from_millis(400)).await; continue; }
-        };
-        if !status.is_success() {
-            eprintln!("trades http {} body: {}", status, &body.chars().take(200).collect::<String>());
-            tokio::time::sleep(Duration::from_millis(500)).await;
-            continue;
-        }
-
-        let mut v: Vec<RespTrade> = match serde_json::from_str(&body) {
-            Ok(x) => x,
-            Err(e) => { eprintln!("trades json err: {e} body: {}", &body.chars().take(200).collect::<String>()); tokio::time::sleep(Duration::from_millis(400)).await; continue; }
+        let mut v = match rest.trades(&symbol, 1000).await {
+            Ok(v) => v,
+            Err(e) => { warn!(error = %e, "trades request failed"); tokio::time::sleep(Duration::from_millis(500)).await; continue; }
         };
 
         v.sort_by(|a, b| {
@@ -375,26 +1446,20 @@ async fn trades_poller_rest(symbol: String, store: DataStore) -> Result<()> {
             if !dedup.insert(k) { continue; }
             if tts > last_ts { last_ts = tts; }
 
-            let ts_recv = epoch_ms();
+            let recv = clock::stamp();
             let evt = TradeEvent{
                 symbol: symbol.clone(),
-                ts_recv_ms: ts_recv,
+                ts_recv_ms: recv.ms(),
                 id: t.id,
                 price: t.price.parse().unwrap_or(0.0),
                 qty: t.qty.parse().unwrap_or(0.0),
-                side: t.isBuyerMaker.map(|b| if b {"SELL".into()} else {"BUY".into()}),
+                side: t.is_buyer_maker.map(|b| if b {"SELL".into()} else {"BUY".into()}),
                 ts_exch_ms: t.time,
             };
-            let _ = store.append_event_json(&symbol, ts_recv, "trade", &evt);
+            let _ = store.append_event_json(&symbol, recv, "trade", &evt);
+            fanout.trade(&symbol, &evt);
+            taps.trade(&evt);
         }
         tokio::time::sleep(Duration::from_millis(250)).await;
     }
 }
-
-#[inline]
-fn epoch_ms() -> i64 {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let now = SystemTime::now();
-    let ms = now.duration_since(UNIX_EPOCH).unwrap();
-    (ms.as_secs() as i64) * 1000 + (ms.subsec_millis() as i64)
-}
@@ -0,0 +1,276 @@
+// manifest.rs
+//
+// `manifest.json` next to the data of every finished `hour=` partition,
+// written by the recorder once it has moved on to the next hour (and on
+// shutdown). A recorder that writes to a partition again removes its manifest
+// first, so a crash before the next one is written leaves no stale manifest.
+// It lists each file with its size and sha256, and what `events.ndjson.zst` /
+// `raw.pb.zst` contain. Stats are computed by reading the files back rather
+// than counted while appending, so they also cover data from earlier runs in
+// the same hour.
+//
+// Not to be confused with the converter's `_manifest.json` in its output
+// tree. `verify` recomputes everything and compares.
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::rawlog::read_raw_frames;
+use crate::store::{read_events, Partition};
+use crate::types::{ExchangeInfoEvent, Precision};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+const MANIFEST_FORMAT: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub name: String,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PartitionStats {
+    pub events: u64,
+    pub by_kind: BTreeMap<String, u64>,
+    pub min_ts_ms: Option<i64>,
+    pub max_ts_ms: Option<i64>,
+    /// Book versions seen in depth deltas and snapshots.
+    pub min_version: Option<u64>,
+    pub max_version: Option<u64>,
+    /// Stored depth deltas whose `from_version` skips past the previous `to_version`.
+    pub gaps: u64,
+    /// REST snapshots taken after the first one of the partition.
+    pub resyncs: u64,
+    pub bad_lines: u64,
+    pub raw_frames: u64,
+    /// A file ended in the middle of a zstd frame, line or raw record.
+    pub truncated: bool,
+    /// From the partition's last `exchange_info` event.
+    #[serde(default)]
+    pub precision: Option<Precision>,
+}
+
+impl PartitionStats {
+    fn note_ts(&mut self, ts_ms: i64) {
+        self.min_ts_ms = Some(self.min_ts_ms.map_or(ts_ms, |t| t.min(ts_ms)));
+        self.max_ts_ms = Some(self.max_ts_ms.map_or(ts_ms, |t| t.max(ts_ms)));
+    }
+
+    fn note_version(&mut self, v: u64) {
+        self.min_version = Some(self.min_version.map_or(v, |x| x.min(v)));
+        self.max_version = Some(self.max_version.map_or(v, |x| x.max(v)));
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartitionManifest {
+    pub format: u32,
+    pub symbol: String,
+    pub date: String,
+    pub hour: u8,
+    pub written_at_ms: i64,
+    pub files: Vec<FileEntry>,
+    pub stats: PartitionStats,
+}
+
+fn sha256_file(path: &Path) -> Result<(u64, String)> {
+    let mut f = File::open(path)?;
+    let mut h = Sha256::new();
+    let mut buf = vec![0u8; 1 << 16];
+    let mut n = 0u64;
+    loop {
+        let k = f.read(&mut buf)?;
+        if k == 0 {
+            break;
+        }
+        h.update(&buf[..k]);
+        n += k as u64;
+    }
+    let hex = h.finalize().iter().map(|b| format!("{b:02x}")).collect();
+    Ok((n, hex))
+}
+
+/// Data files of a partition: everything except manifests and unfinished files.
+fn data_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut out = Vec::new();
+    for e in std::fs::read_dir(dir)? {
+        let p = e?.path();
+        let Some(name) = p.file_name().and_then(|n| n.to_str()) else { continue };
+        if !p.is_file() || name == MANIFEST_FILE || name.ends_with(".tmp") || name.ends_with(".inprogress") {
+            continue;
+        }
+        out.push(p);
+    }
+    out.sort();
+    Ok(out)
+}
+
+pub fn scan_partition(part: &Partition) -> Result<PartitionStats> {
+    let mut st = PartitionStats::default();
+
+    let events = part.events_path();
+    if events.exists() {
+        let mut prev_to: Option<u64> = None;
+        let mut snapshots = 0u64;
+        for ev in read_events(&events)? {
+            let ev = match ev {
+                Ok(ev) => ev,
+                Err(e) if e.downcast_ref::<std::io::Error>().is_some() => {
+                    st.truncated = true;
+                    break;
+                }
+                Err(_) => {
+                    st.bad_lines += 1;
+                    continue;
+                }
+            };
+            st.events += 1;
+            st.note_ts(ev.ts_ms);
+            let field = |k: &str| ev.payload.as_ref().and_then(|p| p.get(k)).and_then(|v| v.as_u64());
+            match ev.kind.as_str() {
+                "depth_snapshot" => {
+                    snapshots += 1;
+                    if snapshots > 1 {
+                        st.resyncs += 1;
+                    }
+                    prev_to = field("last_update_id");
+                }
+                "depth_delta" => {
+                    if let (Some(from), Some(prev)) = (field("from_version"), prev_to) {
+                        if from > prev + 1 {
+                            st.gaps += 1;
+                        }
+                    }
+                    if let Some(to) = field("to_version") {
+                        prev_to = Some(to);
+                    }
+                }
+                "exchange_info" => {
+                    if let Ok(e) = ev.payload_as::<ExchangeInfoEvent>() {
+                        st.precision = Some(e.info.precision);
+                    }
+                }
+                _ => {}
+            }
+            for k in ["last_update_id", "from_version", "to_version"] {
+                if let Some(v) = field(k) {
+                    st.note_version(v);
+                }
+            }
+            *st.by_kind.entry(ev.kind).or_default() += 1;
+        }
+    }
+
+    let raw = part.raw_path();
+    if raw.exists() {
+        for f in read_raw_frames(&raw)? {
+            match f {
+                Ok(f) => {
+                    st.raw_frames += 1;
+                    st.note_ts(f.recv_ts_ns.div_euclid(1_000_000));
+                }
+                Err(_) => st.truncated = true,
+            }
+        }
+    }
+    Ok(st)
+}
+
+pub fn build_manifest(part: &Partition) -> Result<PartitionManifest> {
+    let files = data_files(&part.dir)?
+        .iter()
+        .map(|p| {
+            let (bytes, sha256) = sha256_file(p)?;
+            let name = p.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            Ok(FileEntry { name, bytes, sha256 })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(PartitionManifest {
+        format: MANIFEST_FORMAT,
+        symbol: part.symbol.clone(),
+        date: part.date.clone(),
+        hour: part.hour,
+        written_at_ms: crate::clock::now_ns().div_euclid(1_000_000),
+        files,
+        stats: scan_partition(part)?,
+    })
+}
+
+/// Builds and atomically (tmp + rename) writes the partition's manifest.
+pub fn write_manifest(part: &Partition) -> Result<PartitionManifest> {
+    let m = build_manifest(part)?;
+    let tmp = part.dir.join(format!("{MANIFEST_FILE}.tmp"));
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&m)?)?;
+    std::fs::rename(tmp, part.dir.join(MANIFEST_FILE))?;
+    Ok(m)
+}
+
+/// Removes `dir`'s manifest, if any, before more data goes into the partition.
+pub fn invalidate_manifest(dir: &Path) -> Result<()> {
+    match std::fs::remove_file(dir.join(MANIFEST_FILE)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+pub fn read_manifest(part: &Partition) -> Result<Option<PartitionManifest>> {
+    let p = part.dir.join(MANIFEST_FILE);
+    if !p.exists() {
+        return Ok(None);
+    }
+    let m = serde_json::from_slice(&std::fs::read(&p)?).map_err(|e| anyhow!("{}: {e}", p.display()))?;
+    Ok(Some(m))
+}
+
+#[derive(Debug, Clone)]
+pub struct VerifyReport {
+    pub part: Partition,
+    /// Empty when the partition matches its manifest.
+    pub problems: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Rechecks a partition against its manifest: file set, sizes, hashes, and
+/// that the files still decode to the recorded stats.
+pub fn verify_partition(part: &Partition) -> Result<VerifyReport> {
+    let mut problems = Vec::new();
+    let Some(m) = read_manifest(part)? else {
+        problems.push("no manifest".to_string());
+        return Ok(VerifyReport { part: part.clone(), problems });
+    };
+    let actual = build_manifest(part)?;
+
+    let want: BTreeMap<_, _> = m.files.iter().map(|f| (f.name.as_str(), f)).collect();
+    let have: BTreeMap<_, _> = actual.files.iter().map(|f| (f.name.as_str(), f)).collect();
+    for (name, w) in &want {
+        match have.get(name) {
+            None => problems.push(format!("{name}: missing")),
+            Some(h) if h.bytes != w.bytes => problems.push(format!("{name}: size {} != {}", h.bytes, w.bytes)),
+            Some(h) if h.sha256 != w.sha256 => problems.push(format!("{name}: sha256 mismatch")),
+            Some(_) => {}
+        }
+    }
+    for name in have.keys().filter(|n| !want.contains_key(*n)) {
+        problems.push(format!("{name}: not in manifest"));
+    }
+    if actual.stats.truncated && !m.stats.truncated {
+        problems.push("data no longer decodes to the end".to_string());
+    }
+    if actual.stats.events != m.stats.events || actual.stats.raw_frames != m.stats.raw_frames {
+        problems.push(format!(
+            "record count {}+{} raw, manifest says {}+{} raw",
+            actual.stats.events, actual.stats.raw_frames, m.stats.events, m.stats.raw_frames
+        ));
+    }
+    Ok(VerifyReport { part: part.clone(), problems })
+}
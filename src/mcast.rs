@@ -0,0 +1,549 @@
+// mcast.rs
+//
+// LAN fan-out of book deltas and trades over UDP multicast, plus a TCP side
+// service for gap recovery and full snapshots.
+//
+// Every datagram is one packet (little endian):
+//
+//   0   magic "MXMD" (u32)
+//   4   protocol version (u8)
+//   5   kind (u8)        1 delta, 2 trade, 3 snapshot
+//   6   reserved (u16)
+//   8   symbol, ASCII, zero padded (16 bytes)
+//   24  seq (u64)        per symbol, shared by deltas and trades
+//   32  ts_recv_ms (i64)
+//   40  body
+//
+//   delta:    from_version u64, to_version u64, n_bids u16, n_asks u16, levels
+//   trade:    id u64 (u64::MAX = none), price f64, qty f64, side u8 (0 ?, 1 buy, 2 sell), ts_exch_ms i64 (i64::MIN = none)
+//   snapshot: version u64, n_bids u16, n_asks u16, levels
+//
+// levels are (price f64, qty f64) pairs, bids first; qty 0 removes a level.
+// A snapshot carries the seq of the last packet folded into it and does not
+// consume a seq of its own. Multicast snapshots are truncated to the top
+// `snapshot_levels`; the TCP service serves the book up to 65535 levels a
+// side, the most a packet can hold.
+//
+// TCP requests are single lines, answered with u32-length-prefixed packets and
+// a zero length terminator:
+//
+//   RETRANS <SYMBOL> <FROM_SEQ> <TO_SEQ>
+//   SNAPSHOT <SYMBOL>
+//
+// A request that cannot be parsed is answered with the length 0xffffffff and
+// one `ERR <reason>` line instead; the connection stays usable.
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, BytesMut};
+use ordered_float::OrderedFloat;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+    io::{BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream, UdpSocket},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tracing::Instrument as _;
+
+use crate::config::MulticastConfig;
+use crate::types::{AppliedDelta, BookSide, RevSide, TradeEvent};
+
+const MAGIC: u32 = u32::from_le_bytes(*b"MXMD");
+const PROTO_VERSION: u8 = 1;
+const HEADER_LEN: usize = 40;
+/// Keeps every datagram under a 1500 byte Ethernet MTU.
+const MAX_DATAGRAM: usize = 1472;
+const MAX_UDP_LEVELS: usize = (MAX_DATAGRAM - HEADER_LEN - 12) / 16;
+/// Level counts are u16 on the wire.
+const MAX_PACKET_LEVELS: usize = u16::MAX as usize;
+/// Length prefix of an `ERR` line on the TCP service.
+const ERR_MARKER: u32 = u32::MAX;
+
+const KIND_DELTA: u8 = 1;
+const KIND_TRADE: u8 = 2;
+const KIND_SNAPSHOT: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Body {
+    Delta { from_version: u64, to_version: u64, bids: Vec<[f64; 2]>, asks: Vec<[f64; 2]> },
+    Trade { id: Option<u64>, price: f64, qty: f64, side: Option<String>, ts_exch_ms: Option<i64> },
+    Snapshot { version: u64, bids: Vec<[f64; 2]>, asks: Vec<[f64; 2]> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub symbol: String,
+    pub seq: u64,
+    pub ts_recv_ms: i64,
+    pub body: Body,
+}
+
+fn symbol_field(symbol: &str) -> Result<[u8; 16]> {
+    if symbol.len() > 16 {
+        return Err(anyhow!("symbol {symbol} longer than 16 bytes"));
+    }
+    let mut sym = [0u8; 16];
+    sym[..symbol.len()].copy_from_slice(symbol.as_bytes());
+    Ok(sym)
+}
+
+fn put_header(buf: &mut BytesMut, kind: u8, symbol: &str, seq: u64, ts_recv_ms: i64) -> Result<()> {
+    buf.put_u32_le(MAGIC);
+    buf.put_u8(PROTO_VERSION);
+    buf.put_u8(kind);
+    buf.put_u16_le(0);
+    buf.put_slice(&symbol_field(symbol)?);
+    buf.put_u64_le(seq);
+    buf.put_i64_le(ts_recv_ms);
+    Ok(())
+}
+
+fn put_levels<'a>(buf: &mut BytesMut, levels: impl Iterator<Item = &'a [f64; 2]>) {
+    for [p, q] in levels {
+        buf.put_f64_le(*p);
+        buf.put_f64_le(*q);
+    }
+}
+
+fn put_counts(buf: &mut BytesMut, bids: &[[f64; 2]], asks: &[[f64; 2]]) -> Result<()> {
+    if bids.len() > MAX_PACKET_LEVELS || asks.len() > MAX_PACKET_LEVELS {
+        return Err(anyhow!("{} bids / {} asks do not fit in a packet", bids.len(), asks.len()));
+    }
+    buf.put_u16_le(bids.len() as u16);
+    buf.put_u16_le(asks.len() as u16);
+    Ok(())
+}
+
+/// Fails if the symbol is longer than 16 bytes or a side has more than 65535 levels.
+pub fn encode(p: &Packet) -> Result<BytesMut> {
+    let mut buf = BytesMut::with_capacity(256);
+    match &p.body {
+        Body::Delta { from_version, to_version, bids, asks } => {
+            put_header(&mut buf, KIND_DELTA, &p.symbol, p.seq, p.ts_recv_ms)?;
+            buf.put_u64_le(*from_version);
+            buf.put_u64_le(*to_version);
+            put_counts(&mut buf, bids, asks)?;
+            put_levels(&mut buf, bids.iter());
+            put_levels(&mut buf, asks.iter());
+        }
+        Body::Trade { id, price, qty, side, ts_exch_ms } => {
+            put_header(&mut buf, KIND_TRADE, &p.symbol, p.seq, p.ts_recv_ms)?;
+            buf.put_u64_le(id.unwrap_or(u64::MAX));
+            buf.put_f64_le(*price);
+            buf.put_f64_le(*qty);
+            buf.put_u8(match side.as_deref() {
+                Some("BUY") => 1,
+                Some("SELL") => 2,
+                _ => 0,
+            });
+            buf.put_i64_le(ts_exch_ms.unwrap_or(i64::MIN));
+        }
+        Body::Snapshot { version, bids, asks } => {
+            put_header(&mut buf, KIND_SNAPSHOT, &p.symbol, p.seq, p.ts_recv_ms)?;
+            buf.put_u64_le(*version);
+            put_counts(&mut buf, bids, asks)?;
+            put_levels(&mut buf, bids.iter());
+            put_levels(&mut buf, asks.iter());
+        }
+    }
+    Ok(buf)
+}
+
+fn need(b: &[u8], n: usize) -> Result<()> {
+    if b.remaining() < n {
+        return Err(anyhow!("truncated packet"));
+    }
+    Ok(())
+}
+
+fn get_levels(b: &mut &[u8], n: usize) -> Result<Vec<[f64; 2]>> {
+    need(b, n * 16)?;
+    Ok((0..n).map(|_| [b.get_f64_le(), b.get_f64_le()]).collect())
+}
+
+pub fn decode(mut b: &[u8]) -> Result<Packet> {
+    need(b, HEADER_LEN)?;
+    if b.get_u32_le() != MAGIC {
+        return Err(anyhow!("bad magic"));
+    }
+    let ver = b.get_u8();
+    if ver != PROTO_VERSION {
+        return Err(anyhow!("unsupported protocol version {ver}"));
+    }
+    let kind = b.get_u8();
+    b.advance(2);
+    let symbol = String::from_utf8_lossy(&b[..16]).trim_end_matches('\0').to_string();
+    b.advance(16);
+    let seq = b.get_u64_le();
+    let ts_recv_ms = b.get_i64_le();
+    let body = match kind {
+        KIND_DELTA => {
+            need(b, 20)?;
+            let from_version = b.get_u64_le();
+            let to_version = b.get_u64_le();
+            let nb = b.get_u16_le() as usize;
+            let na = b.get_u16_le() as usize;
+            Body::Delta { from_version, to_version, bids: get_levels(&mut b, nb)?, asks: get_levels(&mut b, na)? }
+        }
+        KIND_TRADE => {
+            need(b, 33)?;
+            let id = b.get_u64_le();
+            let price = b.get_f64_le();
+            let qty = b.get_f64_le();
+            let side = match b.get_u8() {
+                1 => Some("BUY".to_string()),
+                2 => Some("SELL".to_string()),
+                _ => None,
+            };
+            let ts = b.get_i64_le();
+            Body::Trade {
+                id: (id != u64::MAX).then_some(id),
+                price,
+                qty,
+                side,
+                ts_exch_ms: (ts != i64::MIN).then_some(ts),
+            }
+        }
+        KIND_SNAPSHOT => {
+            need(b, 12)?;
+            let version = b.get_u64_le();
+            let nb = b.get_u16_le() as usize;
+            let na = b.get_u16_le() as usize;
+            Body::Snapshot { version, bids: get_levels(&mut b, nb)?, asks: get_levels(&mut b, na)? }
+        }
+        k => return Err(anyhow!("unknown packet kind {k}")),
+    };
+    Ok(Packet { symbol, seq, ts_recv_ms, body })
+}
+
+#[derive(Default)]
+struct SymbolState {
+    seq: u64,
+    version: u64,
+    ts_recv_ms: i64,
+    asks: BookSide,
+    bids: RevSide,
+    recent: VecDeque<(u64, bytes::Bytes)>,
+}
+
+impl SymbolState {
+    fn snapshot(&self, symbol: &str, levels: usize) -> Packet {
+        Packet {
+            symbol: symbol.to_string(),
+            seq: self.seq,
+            ts_recv_ms: self.ts_recv_ms,
+            body: Body::Snapshot {
+                version: self.version,
+                bids: self.bids.iter().take(levels).map(|(k, q)| [(k.0).0, *q]).collect(),
+                asks: self.asks.iter().take(levels).map(|(k, q)| [k.0, *q]).collect(),
+            },
+        }
+    }
+}
+
+pub struct McastPublisher {
+    sock: UdpSocket,
+    group: SocketAddr,
+    snapshot_levels: usize,
+    retransmit_buffer: usize,
+    state: Mutex<HashMap<String, SymbolState>>,
+}
+
+impl McastPublisher {
+    pub fn new(cfg: &MulticastConfig) -> Result<Self> {
+        let group: SocketAddrV4 = cfg.group.parse()?;
+        if !group.ip().is_multicast() {
+            return Err(anyhow!("{} is not a multicast address", group.ip()));
+        }
+        let iface: Ipv4Addr = cfg.interface.parse()?;
+        let sock = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        sock.set_multicast_if_v4(&iface)?;
+        sock.set_multicast_ttl_v4(cfg.ttl)?;
+        sock.set_multicast_loop_v4(true)?;
+        sock.bind(&SocketAddr::from((iface, 0)).into())?;
+        sock.set_nonblocking(true)?;
+        Ok(Self {
+            sock: sock.into(),
+            group: group.into(),
+            snapshot_levels: cfg.snapshot_levels.min(MAX_UDP_LEVELS / 2),
+            retransmit_buffer: cfg.retransmit_buffer,
+            state: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Fails for a symbol that does not fit the packet header; the recorder
+    /// checks each symbol before publishing it.
+    pub fn check_symbol(symbol: &str) -> Result<()> {
+        symbol_field(symbol).map(|_| ())
+    }
+
+    /// Assigns the next seq, keeps the packet for retransmission and sends it.
+    /// Datagrams are dropped rather than blocking the book loop; receivers
+    /// recover them through the TCP service.
+    fn emit(&self, st: &mut SymbolState, mut p: Packet) {
+        st.seq += 1;
+        p.seq = st.seq;
+        // deltas are split to fit a datagram and symbols checked up front, so this cannot fail
+        let buf = encode(&p).expect("packet fits").freeze();
+        let _ = self.sock.send_to(&buf, self.group);
+        st.recent.push_back((p.seq, buf));
+        while st.recent.len() > self.retransmit_buffer {
+            st.recent.pop_front();
+        }
+    }
+
+    pub fn publish_snapshot(&self, symbol: &str, version: u64, ts_recv_ms: i64, asks: &BookSide, bids: &RevSide) {
+        let mut map = self.state.lock().unwrap();
+        let st = map.entry(symbol.to_string()).or_default();
+        st.version = version;
+        st.ts_recv_ms = ts_recv_ms;
+        st.asks = asks.clone();
+        st.bids = bids.clone();
+        if let Ok(buf) = encode(&st.snapshot(symbol, self.snapshot_levels)) {
+            let _ = self.sock.send_to(&buf, self.group);
+        }
+    }
+
+    pub fn publish_delta(&self, symbol: &str, d: &AppliedDelta, ts_recv_ms: i64) {
+        let mut map = self.state.lock().unwrap();
+        let st = map.entry(symbol.to_string()).or_default();
+        st.version = d.to_version;
+        st.ts_recv_ms = ts_recv_ms;
+        for [p, q] in &d.asks {
+            if *q == 0.0 { st.asks.remove(&OrderedFloat(*p)); } else { st.asks.insert(OrderedFloat(*p), *q); }
+        }
+        for [p, q] in &d.bids {
+            let k = Reverse(OrderedFloat(*p));
+            if *q == 0.0 { st.bids.remove(&k); } else { st.bids.insert(k, *q); }
+        }
+        // a delta wider than one datagram goes out as consecutive packets with the same versions
+        let per_packet = MAX_UDP_LEVELS;
+        let mut bids = d.bids.as_slice();
+        let mut asks = d.asks.as_slice();
+        loop {
+            let nb = bids.len().min(per_packet);
+            let na = asks.len().min(per_packet - nb);
+            self.emit(st, Packet {
+                symbol: symbol.to_string(),
+                seq: 0,
+                ts_recv_ms,
+                body: Body::Delta {
+                    from_version: d.from_version,
+                    to_version: d.to_version,
+                    bids: bids[..nb].to_vec(),
+                    asks: asks[..na].to_vec(),
+                },
+            });
+            bids = &bids[nb..];
+            asks = &asks[na..];
+            if bids.is_empty() && asks.is_empty() {
+                break;
+            }
+        }
+    }
+
+    pub fn publish_trade(&self, symbol: &str, t: &TradeEvent) {
+        let mut map = self.state.lock().unwrap();
+        let st = map.entry(symbol.to_string()).or_default();
+        self.emit(st, Packet {
+            symbol: symbol.to_string(),
+            seq: 0,
+            ts_recv_ms: t.ts_recv_ms,
+            body: Body::Trade {
+                id: t.id,
+                price: t.price,
+                qty: t.qty,
+                side: t.side.clone(),
+                ts_exch_ms: t.ts_exch_ms,
+            },
+        });
+    }
+
+    /// Periodic top-N snapshot per symbol; doubles as a heartbeat for receivers.
+    pub async fn snapshot_loop(self: Arc<Self>, every: Duration) {
+        let mut tick = tokio::time::interval(every);
+        loop {
+            tick.tick().await;
+            let pkts: Vec<_> = {
+                let map = self.state.lock().unwrap();
+                map.iter().filter_map(|(s, st)| encode(&st.snapshot(s, self.snapshot_levels)).ok()).collect()
+            };
+            for buf in pkts {
+                let _ = self.sock.send_to(&buf, self.group);
+            }
+        }
+    }
+
+    pub async fn serve_retransmit(self: Arc<Self>, bind: &str) -> Result<()> {
+        let listener = tokio::net::TcpListener::bind(bind).await?;
+        tracing::info!(bind, "multicast retransmit service listening");
+        self.serve_retransmit_listener(listener).await
+    }
+
+    /// `serve_retransmit` on an already bound listener.
+    pub async fn serve_retransmit_listener(self: Arc<Self>, listener: tokio::net::TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(c) => c,
+                Err(e) => {
+                    // e.g. out of file descriptors; the service outlives it
+                    tracing::warn!(error = %e, "retransmit accept failed");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let this = self.clone();
+            let span = tracing::info_span!("retransmit_client", %peer);
+            tokio::spawn(
+                async move {
+                    if let Err(e) = this.handle_retransmit(stream).await {
+                        tracing::warn!(error = %e, "retransmit client failed");
+                    }
+                }
+                .instrument(span),
+            );
+        }
+    }
+
+    async fn handle_retransmit(&self, stream: tokio::net::TcpStream) -> Result<()> {
+        let (rd, mut wr) = stream.into_split();
+        let mut lines = tokio::io::BufReader::new(rd).lines();
+        while let Some(line) = lines.next_line().await? {
+            match self.answer(&line) {
+                Ok(frames) => {
+                    for f in frames {
+                        wr.write_u32_le(f.len() as u32).await?;
+                        wr.write_all(&f).await?;
+                    }
+                    wr.write_u32_le(0).await?;
+                }
+                Err(e) => {
+                    tracing::debug!(request = %line, error = %e, "bad retransmit request");
+                    wr.write_u32_le(ERR_MARKER).await?;
+                    wr.write_all(format!("ERR {}\n", e.to_string().replace('\n', " ")).as_bytes()).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The packets answering one TCP request line.
+    fn answer(&self, line: &str) -> Result<Vec<bytes::Bytes>> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let map = self.state.lock().unwrap();
+        match parts.as_slice() {
+            ["RETRANS", sym, from, to] => {
+                let from: u64 = from.parse().map_err(|_| anyhow!("bad seq {from}"))?;
+                let to: u64 = to.parse().map_err(|_| anyhow!("bad seq {to}"))?;
+                Ok(map
+                    .get(*sym)
+                    .map(|st| st.recent.iter().filter(|(s, _)| *s >= from && *s <= to).map(|(_, b)| b.clone()).collect())
+                    .unwrap_or_default())
+            }
+            ["SNAPSHOT", sym] => match map.get(*sym) {
+                Some(st) => Ok(vec![encode(&st.snapshot(sym, MAX_PACKET_LEVELS))?.freeze()]),
+                None => Ok(Vec::new()),
+            },
+            _ => Err(anyhow!("bad request: {line}")),
+        }
+    }
+}
+
+/// What a [`McastReceiver`] hands back for each datagram.
+#[derive(Debug)]
+pub enum Received {
+    Packet(Packet),
+    /// Packets with `from..=to` for `symbol` were not seen; the packet that
+    /// revealed the gap follows as a separate `Packet`.
+    Gap { symbol: String, from: u64, to: u64 },
+}
+
+/// Blocking receiver with per-symbol sequence tracking, for consumers that link the crate.
+pub struct McastReceiver {
+    sock: UdpSocket,
+    retransmit: Option<SocketAddr>,
+    expected: HashMap<String, u64>,
+    pending: Option<Packet>,
+    buf: Vec<u8>,
+}
+
+impl McastReceiver {
+    pub fn join(group: SocketAddrV4, interface: Ipv4Addr, retransmit: Option<SocketAddr>) -> Result<Self> {
+        let sock = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        sock.set_reuse_address(true)?;
+        sock.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, group.port())).into())?;
+        sock.join_multicast_v4(group.ip(), &interface)?;
+        Ok(Self { sock: sock.into(), retransmit, expected: HashMap::new(), pending: None, buf: vec![0u8; 65536] })
+    }
+
+    pub fn set_read_timeout(&self, t: Option<Duration>) -> Result<()> {
+        Ok(self.sock.set_read_timeout(t)?)
+    }
+
+    pub fn recv(&mut self) -> Result<Received> {
+        if let Some(p) = self.pending.take() {
+            return Ok(Received::Packet(p));
+        }
+        loop {
+            let n = self.sock.recv(&mut self.buf)?;
+            let p = match decode(&self.buf[..n]) {
+                Ok(p) => p,
+                Err(_) => continue,
+            };
+            if matches!(p.body, Body::Snapshot { .. }) {
+                return Ok(Received::Packet(p));
+            }
+            let exp = self.expected.entry(p.symbol.clone()).or_insert(p.seq);
+            if p.seq < *exp {
+                continue; // duplicate
+            }
+            if p.seq > *exp {
+                let gap = Received::Gap { symbol: p.symbol.clone(), from: *exp, to: p.seq - 1 };
+                *exp = p.seq + 1;
+                self.pending = Some(p);
+                return Ok(gap);
+            }
+            *exp = p.seq + 1;
+            return Ok(Received::Packet(p));
+        }
+    }
+
+    /// Fetches missed packets from the publisher's TCP service.
+    pub fn recover(&self, symbol: &str, from: u64, to: u64) -> Result<Vec<Packet>> {
+        self.request(&format!("RETRANS {symbol} {from} {to}\n"))
+    }
+
+    /// Full book from the TCP service; its seq tells where the multicast stream continues.
+    pub fn snapshot(&self, symbol: &str) -> Result<Option<Packet>> {
+        Ok(self.request(&format!("SNAPSHOT {symbol}\n"))?.into_iter().next())
+    }
+
+    fn request(&self, line: &str) -> Result<Vec<Packet>> {
+        let addr = self.retransmit.ok_or_else(|| anyhow!("no retransmit address configured"))?;
+        let mut s = TcpStream::connect_timeout(&addr, Duration::from_secs(2))?;
+        s.set_read_timeout(Some(Duration::from_secs(5)))?;
+        s.write_all(line.as_bytes())?;
+        let mut rd = BufReader::new(s);
+        let mut out = Vec::new();
+        loop {
+            let mut len = [0u8; 4];
+            rd.read_exact(&mut len)?;
+            let len = u32::from_le_bytes(len);
+            if len == ERR_MARKER {
+                let mut msg = String::new();
+                rd.read_line(&mut msg)?;
+                return Err(anyhow!("retransmit service: {}", msg.trim_end()));
+            }
+            let len = len as usize;
+            if len == 0 {
+                break;
+            }
+            let mut frame = vec![0u8; len];
+            rd.read_exact(&mut frame)?;
+            out.push(decode(&frame)?);
+        }
+        Ok(out)
+    }
+}
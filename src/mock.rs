@@ -0,0 +1,534 @@
+// mock.rs
+//
+// Local stand-in for the MEXC public API so the recorder can run end to end
+// without the network: a WS server speaking the SUBSCRIPTION protocol that
+// pushes `PushDataV3ApiWrapper` protobuf frames, and a REST stub for
+// `/api/v3/depth`, `/trades`, `/time` and `/exchangeInfo`.
+//
+// The mock keeps its own book per symbol and moves it through scripted
+// `Step`s, so REST snapshots always agree with what was pushed (or deliberately
+// withheld). Point `[exchange] rest_url` and `[websocket] url` at
+// `MockExchange::rest_url` / `ws_url`.
+use anyhow::{anyhow, Result};
+use futures::{SinkExt, StreamExt};
+use ordered_float::OrderedFloat;
+use prost::Message;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, Notify},
+    task::JoinHandle,
+};
+use tokio_tungstenite::{accept_async, tungstenite::protocol::Message as WsMsg};
+
+use crate::clock;
+use crate::mexc_pb::{push_data_v3_api_wrapper::Body, PublicAggreDepthV3ApiItem, PublicAggreDepthsV3Api, PushDataV3ApiWrapper};
+use crate::types::{BookSide, DepthSnapshot, RevSide};
+
+const PUSH_CHAN_CAP: usize = 4096;
+const SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One aggregated-depth update; `[price, qty]` levels, qty 0 removes.
+#[derive(Debug, Clone, Default)]
+pub struct Delta {
+    pub asks: Vec<[f64; 2]>,
+    pub bids: Vec<[f64; 2]>,
+    /// Versions covered, i.e. `to - from + 1`; 0 counts as 1.
+    pub span: u64,
+}
+
+impl Delta {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ask(mut self, price: f64, qty: f64) -> Self {
+        self.asks.push([price, qty]);
+        self
+    }
+
+    pub fn bid(mut self, price: f64, qty: f64) -> Self {
+        self.bids.push([price, qty]);
+        self
+    }
+
+    pub fn span(mut self, span: u64) -> Self {
+        self.span = span;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// Replace the exchange book without pushing anything.
+    Snapshot { version: u64, asks: Vec<[f64; 2]>, bids: Vec<[f64; 2]> },
+    /// Apply to the exchange book and push it.
+    Depth(Delta),
+    /// Apply to the exchange book but never push it (a lost frame).
+    Drop(Delta),
+    /// Push the previous depth frame again.
+    Duplicate,
+    /// Push arbitrary bytes on the depth channel.
+    Raw(Vec<u8>),
+    /// Append to `/api/v3/trades`.
+    Trade { price: f64, qty: f64, buyer_maker: bool },
+    /// `status` field in `/api/v3/exchangeInfo` (`"1"` is online).
+    Status(String),
+    /// Add the symbol, online with an empty book, if it is not listed yet.
+    List,
+    /// Remove the symbol: gone from `/api/v3/exchangeInfo`, REST answers "Invalid symbol".
+    Delist,
+    /// Answer the next `times` requests to `path` with `status`, and a
+    /// `Retry-After` header if `retry_after_secs` is set.
+    FailRest { path: String, status: u16, times: u32, retry_after_secs: Option<u64> },
+    Sleep(Duration),
+    /// Wait until some client is subscribed to the depth channel.
+    WaitSubscribed,
+    /// Wait until no client is subscribed to the depth channel.
+    WaitUnsubscribed,
+    /// Close every WS connection.
+    Disconnect,
+}
+
+/// Steps for one symbol, run in order by [`MockExchange::run`].
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub symbol: String,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Default)]
+struct MockBook {
+    asks: BookSide,
+    bids: RevSide,
+    version: u64,
+    last_push: Option<PublicAggreDepthsV3Api>,
+    trades: Vec<serde_json::Value>,
+    status: String,
+}
+
+impl MockBook {
+    fn apply(&mut self, d: &Delta) -> PublicAggreDepthsV3Api {
+        let from = self.version + 1;
+        self.version += d.span.max(1);
+        let item = |[p, q]: [f64; 2]| PublicAggreDepthV3ApiItem { price: p.to_string(), quantity: q.to_string() };
+        for &[p, q] in &d.asks {
+            if q == 0.0 { self.asks.remove(&OrderedFloat(p)); } else { self.asks.insert(OrderedFloat(p), q); }
+        }
+        for &[p, q] in &d.bids {
+            if q == 0.0 { self.bids.remove(&Reverse(OrderedFloat(p))); } else { self.bids.insert(Reverse(OrderedFloat(p)), q); }
+        }
+        PublicAggreDepthsV3Api {
+            asks: d.asks.iter().copied().map(item).collect(),
+            bids: d.bids.iter().copied().map(item).collect(),
+            event_type: String::new(),
+            from_version: from.to_string(),
+            to_version: self.version.to_string(),
+        }
+    }
+
+    fn levels(&self, limit: usize) -> (Vec<[f64; 2]>, Vec<[f64; 2]>) {
+        (
+            self.asks.iter().take(limit).map(|(p, q)| [p.0, *q]).collect(),
+            self.bids.iter().take(limit).map(|(p, q)| [(p.0).0, *q]).collect(),
+        )
+    }
+}
+
+struct Failure {
+    status: u16,
+    times: u32,
+    retry_after_secs: Option<u64>,
+}
+
+#[derive(Default)]
+struct State {
+    books: HashMap<String, MockBook>,
+    failures: HashMap<String, Failure>,
+    requests: HashMap<String, u64>,
+    /// Live depth subscriptions per symbol.
+    subscribed: HashMap<String, usize>,
+}
+
+#[derive(Clone)]
+enum Push {
+    Depth { symbol: String, body: PublicAggreDepthsV3Api },
+    Raw { symbol: String, data: Vec<u8> },
+    Close,
+}
+
+struct Inner {
+    state: Mutex<State>,
+    push: broadcast::Sender<Push>,
+    subscribed: Notify,
+}
+
+pub struct MockExchange {
+    inner: Arc<Inner>,
+    rest_addr: SocketAddr,
+    ws_addr: SocketAddr,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl MockExchange {
+    /// Binds both servers on ephemeral loopback ports; `symbols` start listed with empty books.
+    pub async fn start(symbols: &[&str]) -> Result<Self> {
+        let mut state = State::default();
+        for s in symbols {
+            state.books.insert(s.to_string(), MockBook { status: "1".to_string(), ..Default::default() });
+        }
+        let inner = Arc::new(Inner {
+            state: Mutex::new(state),
+            push: broadcast::channel(PUSH_CHAN_CAP).0,
+            subscribed: Notify::new(),
+        });
+        let rest = TcpListener::bind("127.0.0.1:0").await?;
+        let ws = TcpListener::bind("127.0.0.1:0").await?;
+        let (rest_addr, ws_addr) = (rest.local_addr()?, ws.local_addr()?);
+        let tasks = vec![
+            tokio::spawn(accept_loop(rest, inner.clone(), |inner, s| tokio::spawn(handle_http(inner, s)))),
+            tokio::spawn(accept_loop(ws, inner.clone(), |inner, s| tokio::spawn(handle_ws(inner, s)))),
+        ];
+        Ok(Self { inner, rest_addr, ws_addr, tasks })
+    }
+
+    pub fn rest_url(&self) -> String {
+        format!("http://{}", self.rest_addr)
+    }
+
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}/ws", self.ws_addr)
+    }
+
+    /// The exchange-side book, as `/api/v3/depth` would return it in full.
+    pub fn book(&self, symbol: &str) -> Option<DepthSnapshot> {
+        let st = self.inner.state.lock().unwrap();
+        let b = st.books.get(symbol)?;
+        let (asks, bids) = b.levels(usize::MAX);
+        Some(DepthSnapshot { symbol: symbol.to_string(), ts_recv_ms: 0, last_update_id: b.version, bids, asks })
+    }
+
+    /// REST requests served (or failed) for `path`, e.g. `/api/v3/depth`.
+    pub fn requests(&self, path: &str) -> u64 {
+        self.inner.state.lock().unwrap().requests.get(path).copied().unwrap_or(0)
+    }
+
+    pub async fn run(&self, scenario: &Scenario) -> Result<()> {
+        let symbol = &scenario.symbol;
+        for step in &scenario.steps {
+            match step {
+                Step::Sleep(d) => tokio::time::sleep(*d).await,
+                Step::WaitSubscribed => self.wait_subscribers(symbol, true).await?,
+                Step::WaitUnsubscribed => self.wait_subscribers(symbol, false).await?,
+                Step::Disconnect => {
+                    let _ = self.inner.push.send(Push::Close);
+                }
+                Step::Raw(data) => {
+                    let _ = self.inner.push.send(Push::Raw { symbol: symbol.clone(), data: data.clone() });
+                }
+                step => {
+                    let mut st = self.inner.state.lock().unwrap();
+                    if let Step::FailRest { path, status, times, retry_after_secs } = step {
+                        let f = Failure { status: *status, times: *times, retry_after_secs: *retry_after_secs };
+                        st.failures.insert(path.clone(), f);
+                        continue;
+                    }
+                    match step {
+                        Step::List => {
+                            st.books.entry(symbol.clone()).or_insert_with(|| MockBook { status: "1".to_string(), ..Default::default() });
+                            continue;
+                        }
+                        Step::Delist => {
+                            st.books.remove(symbol);
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    let book = st.books.get_mut(symbol).ok_or_else(|| anyhow!("unknown mock symbol {symbol}"))?;
+                    let push = match step {
+                        Step::Snapshot { version, asks, bids } => {
+                            let (v, status, trades) = (*version, std::mem::take(&mut book.status), std::mem::take(&mut book.trades));
+                            *book = MockBook { version: v, status, trades, ..Default::default() };
+                            book.apply(&Delta { asks: asks.clone(), bids: bids.clone(), span: 0 });
+                            book.version = v;
+                            None
+                        }
+                        Step::Depth(d) => {
+                            let body = book.apply(d);
+                            book.last_push = Some(body.clone());
+                            Some(body)
+                        }
+                        Step::Drop(d) => {
+                            book.apply(d);
+                            None
+                        }
+                        Step::Duplicate => book.last_push.clone(),
+                        Step::Trade { price, qty, buyer_maker } => {
+                            let id = book.trades.len() as u64 + 1;
+                            book.trades.push(serde_json::json!({
+                                "id": id,
+                                "price": price.to_string(),
+                                "qty": qty.to_string(),
+                                "quoteQty": (price * qty).to_string(),
+                                "time": clock::now_ns() / 1_000_000,
+                                "isBuyerMaker": buyer_maker,
+                                "isBestMatch": true,
+                                "tradeType": if *buyer_maker { "ASK" } else { "BID" },
+                            }));
+                            None
+                        }
+                        Step::Status(s) => {
+                            book.status = s.clone();
+                            None
+                        }
+                        _ => unreachable!("handled above"),
+                    };
+                    if let Some(body) = push {
+                        let _ = self.inner.push.send(Push::Depth { symbol: symbol.clone(), body });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn wait_subscribers(&self, symbol: &str, subscribed: bool) -> Result<()> {
+        let wait = async {
+            loop {
+                let notified = self.inner.subscribed.notified();
+                if self.inner.state.lock().unwrap().subscribed.get(symbol).is_some_and(|n| *n > 0) == subscribed {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        tokio::time::timeout(SUBSCRIBE_TIMEOUT, wait).await.map_err(|_| {
+            let what = if subscribed { "no depth subscription" } else { "depth still subscribed" };
+            anyhow!("{what} for {symbol} within {SUBSCRIBE_TIMEOUT:?}")
+        })
+    }
+}
+
+impl Drop for MockExchange {
+    fn drop(&mut self) {
+        for t in &self.tasks {
+            t.abort();
+        }
+    }
+}
+
+async fn accept_loop(listener: TcpListener, inner: Arc<Inner>, handle: fn(Arc<Inner>, TcpStream) -> JoinHandle<()>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        handle(inner.clone(), stream);
+    }
+}
+
+/// `spot@public.aggre.depth.v3.api.pb@10ms@BTCUSDT` -> `BTCUSDT`
+fn depth_channel_symbol(chan: &str) -> Option<&str> {
+    chan.contains("aggre.depth").then(|| chan.rsplit('@').next())?
+}
+
+async fn handle_ws(inner: Arc<Inner>, stream: TcpStream) {
+    let Ok(ws) = accept_async(stream).await else { return };
+    let (mut tx, mut rx) = ws.split();
+    let mut push_rx = inner.push.subscribe();
+    // symbol -> channel as the client spelled it
+    let mut subs: HashMap<String, String> = HashMap::new();
+    let ack = |msg: &str| WsMsg::Text(serde_json::json!({ "id": 0, "code": 0, "msg": msg }).to_string());
+
+    loop {
+        tokio::select! {
+            msg = rx.next() => {
+                let text = match msg {
+                    Some(Ok(WsMsg::Text(t))) => t,
+                    Some(Ok(WsMsg::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let Ok(req) = serde_json::from_str::<serde_json::Value>(&text) else { continue };
+                let params: Vec<String> = req["params"]
+                    .as_array()
+                    .map(|a| a.iter().filter_map(|p| p.as_str().map(str::to_string)).collect())
+                    .unwrap_or_default();
+                match req["method"].as_str() {
+                    Some("SUBSCRIPTION") => {
+                        for chan in params {
+                            if let Some(sym) = depth_channel_symbol(&chan) {
+                                if subs.insert(sym.to_string(), chan.clone()).is_none() {
+                                    *inner.state.lock().unwrap().subscribed.entry(sym.to_string()).or_default() += 1;
+                                }
+                            }
+                            let _ = tx.send(ack(&chan)).await;
+                        }
+                        inner.subscribed.notify_waiters();
+                    }
+                    Some("UNSUBSCRIPTION") => {
+                        for chan in params {
+                            if let Some(sym) = depth_channel_symbol(&chan) {
+                                if subs.remove(sym).is_some() {
+                                    *inner.state.lock().unwrap().subscribed.entry(sym.to_string()).or_default() -= 1;
+                                }
+                            }
+                            let _ = tx.send(ack(&chan)).await;
+                        }
+                        inner.subscribed.notify_waiters();
+                    }
+                    Some("PING") => {
+                        let _ = tx.send(ack("PONG")).await;
+                    }
+                    _ => {}
+                }
+            }
+            push = push_rx.recv() => {
+                let out = match push {
+                    Ok(Push::Depth { symbol, body }) => {
+                        let Some(chan) = subs.get(&symbol) else { continue };
+                        let now_ms = clock::now_ns() / 1_000_000;
+                        let body = PublicAggreDepthsV3Api { event_type: chan.clone(), ..body };
+                        PushDataV3ApiWrapper {
+                            channel: chan.clone(),
+                            symbol: Some(symbol),
+                            symbol_id: None,
+                            create_time: Some(now_ms),
+                            send_time: Some(now_ms),
+                            body: Some(Body::PublicAggreDepths(body)),
+                        }
+                        .encode_to_vec()
+                    }
+                    Ok(Push::Raw { symbol, data }) => {
+                        if !subs.contains_key(&symbol) {
+                            continue;
+                        }
+                        data
+                    }
+                    Ok(Push::Close) | Err(broadcast::error::RecvError::Closed) => {
+                        let _ = tx.send(WsMsg::Close(None)).await;
+                        break;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                };
+                if tx.send(WsMsg::Binary(out)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    {
+        let mut st = inner.state.lock().unwrap();
+        for sym in subs.keys() {
+            if let Some(n) = st.subscribed.get_mut(sym) {
+                *n -= 1;
+            }
+        }
+    }
+    inner.subscribed.notify_waiters();
+}
+
+async fn handle_http(inner: Arc<Inner>, mut stream: TcpStream) {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") && buf.len() < 16 * 1024 {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+    let head = String::from_utf8_lossy(&buf);
+    let target = head.split_whitespace().nth(1).unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params: HashMap<&str, &str> = query.split('&').filter_map(|kv| kv.split_once('=')).collect();
+
+    let (status, retry_after, body) = rest_response(&inner, path, &params);
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        418 => "I'm a teapot",
+        429 => "Too Many Requests",
+        _ => "Error",
+    };
+    let retry_after = retry_after.map(|s| format!("Retry-After: {s}\r\n")).unwrap_or_default();
+    let resp = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{retry_after}Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = stream.write_all(resp.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Status, `Retry-After` seconds and body.
+fn rest_response(inner: &Inner, path: &str, params: &HashMap<&str, &str>) -> (u16, Option<u64>, String) {
+    let mut st = inner.state.lock().unwrap();
+    *st.requests.entry(path.to_string()).or_default() += 1;
+    if let Some(f) = st.failures.get_mut(path).filter(|f| f.times > 0) {
+        f.times -= 1;
+        let body = serde_json::json!({ "code": f.status, "msg": "mock failure" }).to_string();
+        return (f.status, f.retry_after_secs, body);
+    }
+    let (status, body) = route(&st, path, params);
+    (status, None, body)
+}
+
+fn route(st: &State, path: &str, params: &HashMap<&str, &str>) -> (u16, String) {
+    let limit = |default: usize| params.get("limit").and_then(|l| l.parse().ok()).unwrap_or(default);
+    let bad_symbol = || (400, serde_json::json!({ "code": -1121, "msg": "Invalid symbol." }).to_string());
+
+    match path {
+        "/api/v3/time" => (200, serde_json::json!({ "serverTime": clock::now_ns() / 1_000_000 }).to_string()),
+        "/api/v3/depth" => {
+            let Some(b) = params.get("symbol").and_then(|s| st.books.get(*s)) else { return bad_symbol() };
+            let (asks, bids) = b.levels(limit(100));
+            let side = |l: Vec<[f64; 2]>| l.into_iter().map(|[p, q]| [p.to_string(), q.to_string()]).collect::<Vec<_>>();
+            let body = serde_json::json!({ "lastUpdateId": b.version, "bids": side(bids), "asks": side(asks) });
+            (200, body.to_string())
+        }
+        "/api/v3/trades" => {
+            let Some(b) = params.get("symbol").and_then(|s| st.books.get(*s)) else { return bad_symbol() };
+            let skip = b.trades.len().saturating_sub(limit(500));
+            (200, serde_json::Value::from(b.trades[skip..].to_vec()).to_string())
+        }
+        "/api/v3/exchangeInfo" => {
+            let mut symbols: Vec<_> = st
+                .books
+                .iter()
+                .filter(|(s, _)| params.get("symbol").is_none_or(|want| want == s))
+                .map(|(s, b)| {
+                    let (base, quote) = split_pair(s);
+                    serde_json::json!({
+                        "symbol": s,
+                        "status": b.status,
+                        "baseAsset": base,
+                        "quoteAsset": quote,
+                        "baseAssetPrecision": 8,
+                        "quotePrecision": 2,
+                        "quoteAssetPrecision": 8,
+                        "baseSizePrecision": "0.000001",
+                        "isSpotTradingAllowed": true,
+                    })
+                })
+                .collect();
+            symbols.sort_by(|a, b| a["symbol"].as_str().cmp(&b["symbol"].as_str()));
+            let body = serde_json::json!({
+                "timezone": "CST",
+                "serverTime": clock::now_ns() / 1_000_000,
+                "symbols": symbols,
+            });
+            (200, body.to_string())
+        }
+        _ => (404, serde_json::json!({ "code": 404, "msg": "not found" }).to_string()),
+    }
+}
+
+fn split_pair(symbol: &str) -> (&str, &str) {
+    ["USDT", "USDC", "USD1", "BTC", "ETH"]
+        .iter()
+        .find_map(|q| symbol.strip_suffix(q).filter(|b| !b.is_empty()).map(|b| (b, *q)))
+        .unwrap_or((symbol, ""))
+}
@@ -0,0 +1,216 @@
+// rawlog.rs
+//
+// `raw.pb.zst`: WS frames exactly as received, one record each, inside a zstd
+// stream. Every record is a fixed 16 byte little-endian header followed by the
+// frame bytes:
+//
+//   0   recv_ts_ns (i64)  wall clock at receive
+//   8   channel (u16)     see `RawChannel`
+//   10  flags (u16)       reserved, 0
+//   12  len (u32)         payload length
+//
+// The file is a sequence of complete zstd frames. The writer ends its frame
+// every `FLUSH_EVERY` of writing, and the store ends idle writers' frames on a
+// timer, so a crash loses at most about that much. A frame cut short by a
+// crash can only be the last one; reopening the file truncates it, so that
+// what a restart appends stays readable.
+use anyhow::{anyhow, Result};
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const RAW_FILE_NAME: &str = "raw.pb.zst";
+const HEADER_LEN: usize = 16;
+pub const FLUSH_EVERY: Duration = Duration::from_secs(1);
+/// Anything larger is treated as corruption by the reader.
+const MAX_FRAME: usize = 16 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum RawChannel {
+    Unknown = 0,
+    AggreDepth = 1,
+    AggreDeals = 2,
+    AggreBookTicker = 3,
+    LimitDepth = 4,
+}
+
+impl RawChannel {
+    pub fn from_id(id: u16) -> Self {
+        match id {
+            1 => Self::AggreDepth,
+            2 => Self::AggreDeals,
+            3 => Self::AggreBookTicker,
+            4 => Self::LimitDepth,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Maps a MEXC subscription channel (`spot@public.aggre.depth.v3.api.pb@10ms@BTCUSDT`).
+    pub fn from_channel(chan: &str) -> Self {
+        if chan.contains(".aggre.depth.") {
+            Self::AggreDepth
+        } else if chan.contains(".aggre.deals.") {
+            Self::AggreDeals
+        } else if chan.contains(".aggre.bookTicker.") {
+            Self::AggreBookTicker
+        } else if chan.contains(".limit.depth.") {
+            Self::LimitDepth
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RawFrame {
+    pub recv_ts_ns: i64,
+    pub channel: RawChannel,
+    pub flags: u16,
+    pub data: Vec<u8>,
+}
+
+pub struct RawWriter {
+    path: PathBuf,
+    /// Between frames.
+    file: Option<File>,
+    /// Inside a frame, since `frame_start`.
+    enc: Option<zstd::stream::write::Encoder<'static, File>>,
+    frame_start: Instant,
+}
+
+impl RawWriter {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).read(true).append(true).open(&path)?;
+        let complete = complete_frames_len(&file)?;
+        let len = file.metadata()?.len();
+        if complete < len {
+            tracing::warn!(path = %path.display(), bytes = len - complete, "truncating unfinished zstd frame");
+            file.set_len(complete)?;
+        }
+        Ok(Self { path, file: Some(file), enc: None, frame_start: Instant::now() })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn encoder(&mut self) -> Result<&mut zstd::stream::write::Encoder<'static, File>> {
+        if self.enc.is_none() {
+            // after a failed frame end the file is gone; reopen it
+            let file = match self.file.take() {
+                Some(f) => f,
+                None => OpenOptions::new().append(true).open(&self.path)?,
+            };
+            self.enc = Some(zstd::stream::write::Encoder::new(file, 3)?);
+            self.frame_start = Instant::now();
+        }
+        Ok(self.enc.as_mut().unwrap())
+    }
+
+    pub fn write(&mut self, recv_ts_ns: i64, channel: RawChannel, data: &[u8]) -> Result<()> {
+        let mut hdr = [0u8; HEADER_LEN];
+        hdr[0..8].copy_from_slice(&recv_ts_ns.to_le_bytes());
+        hdr[8..10].copy_from_slice(&(channel as u16).to_le_bytes());
+        hdr[12..16].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        let enc = self.encoder()?;
+        enc.write_all(&hdr)?;
+        enc.write_all(data)?;
+        if self.frame_start.elapsed() >= FLUSH_EVERY {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Ends the current frame, if any, making everything written so far readable.
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(enc) = self.enc.take() {
+            self.file = Some(enc.finish()?);
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.flush()
+    }
+}
+
+/// Length of the leading run of complete zstd frames in `file`.
+fn complete_frames_len(file: &File) -> Result<u64> {
+    if file.metadata()?.len() == 0 {
+        return Ok(0);
+    }
+    let map = unsafe { memmap2::Mmap::map(file)? };
+    let mut pos = 0;
+    while pos < map.len() {
+        match zstd::zstd_safe::find_frame_compressed_size(&map[pos..]) {
+            Ok(n) if n > 0 => pos += n,
+            _ => break,
+        }
+    }
+    Ok(pos as u64)
+}
+
+pub struct RawReader {
+    dec: zstd::stream::read::Decoder<'static, BufReader<File>>,
+    done: bool,
+}
+
+pub fn read_raw_frames<P: AsRef<Path>>(path: P) -> Result<RawReader> {
+    let dec = zstd::stream::read::Decoder::new(File::open(path)?)?;
+    Ok(RawReader { dec, done: false })
+}
+
+impl RawReader {
+    fn next_frame(&mut self) -> Result<Option<RawFrame>> {
+        let mut hdr = [0u8; HEADER_LEN];
+        // a clean end is only possible on a record boundary
+        let mut got = 0;
+        while got < HEADER_LEN {
+            match self.dec.read(&mut hdr[got..]) {
+                Ok(0) if got == 0 => return Ok(None),
+                Ok(0) => return Err(anyhow!("truncated record header")),
+                Ok(n) => got += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let recv_ts_ns = i64::from_le_bytes(hdr[0..8].try_into().unwrap());
+        let channel = RawChannel::from_id(u16::from_le_bytes(hdr[8..10].try_into().unwrap()));
+        let flags = u16::from_le_bytes(hdr[10..12].try_into().unwrap());
+        let len = u32::from_le_bytes(hdr[12..16].try_into().unwrap()) as usize;
+        if len > MAX_FRAME {
+            return Err(anyhow!("record length {len} exceeds limit"));
+        }
+        let mut data = vec![0u8; len];
+        self.dec.read_exact(&mut data).map_err(|e| anyhow!("truncated record payload: {e}"))?;
+        Ok(Some(RawFrame { recv_ts_ns, channel, flags, data }))
+    }
+}
+
+impl Iterator for RawReader {
+    type Item = Result<RawFrame>;
+
+    /// Yields at most one error (truncation/corruption), then stops.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_frame() {
+            Ok(Some(f)) => Some(Ok(f)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+// replay.rs
+//
+// Offline book reconstruction for the `replay` and `book-at` commands: stored
+// `depth_snapshot`s seed the book and depth frames (`raw.pb.zst` or legacy
+// `depth_pb_raw` lines) go through `DepthBook::apply_frame`, as live.
+// A frame that breaks the book (gap, bad level) leaves it unusable until the
+// next stored snapshot, which is where the recorder resynced. Levels the
+// recorder pruned from a crossed book come from the `crossed_book` event
+// stored right after the frame.
+//
+// Stored snapshots keep only the top 50 levels, so levels deeper than that
+// are only as complete as the deltas since the snapshot made them.
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::book::{apply_pruned, DepthBook};
+use crate::manifest::read_manifest;
+use crate::store::{list_partitions, read_events, read_partition, Partition, StoredEvent};
+use crate::types::{CrossedBook, DepthSnapshot};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReplayStats {
+    pub events: u64,
+    pub read_errors: u64,
+    pub snapshots: u64,
+    pub depth_frames: u64,
+    pub applied: u64,
+    pub skipped: u64,
+    /// Frames that broke the book; it stays unusable until the next snapshot.
+    pub breaks: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BookReplay {
+    pub symbol: String,
+    /// Without a crossed-book guard: stored `crossed_book` events stand in for it.
+    pub depth: DepthBook,
+    /// Receive time of the last event that changed the book.
+    pub ts_ms: i64,
+    /// Exchange send time of the last frame applied; `None` after a snapshot.
+    pub ts_exch_ms: Option<i64>,
+    pub stats: ReplayStats,
+}
+
+impl BookReplay {
+    pub fn new(symbol: &str) -> Self {
+        Self { symbol: symbol.to_string(), ..Default::default() }
+    }
+
+    /// Feeds one stored event; true if it changed a valid book.
+    pub fn apply(&mut self, ev: &StoredEvent) -> bool {
+        self.stats.events += 1;
+        match ev.kind.as_str() {
+            "depth_snapshot" => {
+                let Ok(s) = ev.payload_as::<DepthSnapshot>() else {
+                    self.stats.read_errors += 1;
+                    return false;
+                };
+                self.stats.snapshots += 1;
+                self.depth.load_snapshot(&s);
+                self.ts_ms = ev.ts_ms;
+                self.ts_exch_ms = None;
+                true
+            }
+            "depth_pb_raw" => {
+                let Some(Ok(raw)) = ev.raw() else {
+                    self.stats.read_errors += 1;
+                    return false;
+                };
+                self.stats.depth_frames += 1;
+                if !self.depth.valid {
+                    return false;
+                }
+                let out = self.depth.apply_frame(&self.symbol, raw.into(), ev.ts_ms);
+                match out.delta {
+                    Some(d) => {
+                        self.stats.applied += 1;
+                        self.ts_ms = ev.ts_ms;
+                        self.ts_exch_ms = d.ts_exch_ms;
+                        true
+                    }
+                    None if out.broke => {
+                        self.stats.breaks += 1;
+                        false
+                    }
+                    None => {
+                        self.stats.skipped += 1;
+                        false
+                    }
+                }
+            }
+            "crossed_book" => {
+                let Ok(c) = ev.payload_as::<CrossedBook>() else {
+                    self.stats.read_errors += 1;
+                    return false;
+                };
+                if !self.depth.valid || c.pruned_asks.len() + c.pruned_bids.len() == 0 {
+                    return false;
+                }
+                apply_pruned(&mut self.depth.asks, &mut self.depth.bids, &c);
+                self.ts_ms = ev.ts_ms;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The top `levels` per side.
+    pub fn snapshot(&self, levels: usize) -> DepthSnapshot {
+        DepthSnapshot {
+            symbol: self.symbol.clone(),
+            ts_recv_ms: self.ts_ms,
+            last_update_id: self.depth.version,
+            bids: self.depth.bids.iter().take(levels).map(|(p, q)| [(p.0).0, *q]).collect(),
+            asks: self.depth.asks.iter().take(levels).map(|(p, q)| [p.0, *q]).collect(),
+        }
+    }
+}
+
+fn has_snapshot(part: &Partition) -> Result<bool> {
+    if let Some(m) = read_manifest(part)? {
+        return Ok(m.stats.by_kind.contains_key("depth_snapshot"));
+    }
+    if !part.events_path().exists() {
+        return Ok(false);
+    }
+    Ok(read_events(part.events_path())?.any(|ev| ev.is_ok_and(|ev| ev.kind == "depth_snapshot")))
+}
+
+/// `symbol`'s partitions needed to know the book from `from_ms` to `to_ms`,
+/// starting at the last one with a snapshot before the one holding `from_ms`.
+fn partitions_for(root: &Path, symbol: &str, from_ms: i64, to_ms: i64) -> Result<Vec<Partition>> {
+    let mut parts = Vec::new();
+    for p in list_partitions(root)? {
+        if p.symbol == symbol && p.start_ms()? <= to_ms {
+            parts.push(p);
+        }
+    }
+    let needed = parts.iter().position(|p| p.end_ms().is_ok_and(|e| e > from_ms)).unwrap_or(parts.len());
+    // a snapshot inside `needed` may come after `from_ms`, so look strictly before it
+    let mut first = needed;
+    for (i, p) in parts[..needed].iter().enumerate().rev() {
+        if has_snapshot(p)? {
+            first = i;
+            break;
+        }
+    }
+    Ok(parts.split_off(first))
+}
+
+/// Replays `symbol` up to `to_ms`, calling `on_event` with the book after
+/// every stored event read (trades and the like included, and events before
+/// `from_ms` that bring the book up to date) and whether it changed a valid
+/// book. Returns the book as of `to_ms`.
+pub fn replay_events<P: AsRef<Path>>(
+    root: P,
+    symbol: &str,
+    from_ms: i64,
+    to_ms: i64,
+    mut on_event: impl FnMut(&BookReplay, &StoredEvent, bool) -> Result<()>,
+) -> Result<BookReplay> {
+    let mut book = BookReplay::new(symbol);
+    for part in partitions_for(root.as_ref(), symbol, from_ms, to_ms)? {
+        for ev in read_partition(&part)? {
+            let ev = match ev {
+                Ok(ev) => ev,
+                Err(_) => {
+                    book.stats.read_errors += 1;
+                    continue;
+                }
+            };
+            if ev.ts_ms > to_ms {
+                return Ok(book);
+            }
+            let changed = book.apply(&ev);
+            on_event(&book, &ev, changed)?;
+        }
+    }
+    Ok(book)
+}
+
+/// Replays `symbol` up to `to_ms`, calling `on_change` for every book change
+/// at or after `from_ms`. Returns the book as of `to_ms`.
+pub fn replay_range<P: AsRef<Path>>(
+    root: P,
+    symbol: &str,
+    from_ms: i64,
+    to_ms: i64,
+    mut on_change: impl FnMut(&BookReplay) -> Result<()>,
+) -> Result<BookReplay> {
+    replay_events(root, symbol, from_ms, to_ms, |book, ev, changed| {
+        if changed && ev.ts_ms >= from_ms {
+            on_change(book)?;
+        }
+        Ok(())
+    })
+}
+
+/// The book of `symbol` as of `at_ms`, or `None` without a valid one.
+pub fn book_at<P: AsRef<Path>>(root: P, symbol: &str, at_ms: i64, levels: usize) -> Result<Option<DepthSnapshot>> {
+    let book = replay_range(root, symbol, at_ms, at_ms, |_| Ok(()))?;
+    Ok(book.depth.valid.then(|| book.snapshot(levels)))
+}
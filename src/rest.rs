@@ -0,0 +1,267 @@
+// rest.rs
+//
+// Shared MEXC REST client. Keep one per process: its token bucket is the
+// IP-wide request weight budget, so snapshot, trade and clock requests of
+// every symbol draw from the same limit, and a 429/418 pauses all of them for
+// `Retry-After`. Failures that `IngestError::recovery` calls retriable are
+// retried with exponential backoff; RTTs go to `Telemetry`.
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::clock;
+use crate::config::RestConfig;
+use crate::error::{IngestError, Recovery};
+use crate::telemetry::Telemetry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    Depth,
+    Trades,
+    Time,
+    ExchangeInfo,
+}
+
+impl Endpoint {
+    pub fn path(self) -> &'static str {
+        match self {
+            Self::Depth => "/api/v3/depth",
+            Self::Trades => "/api/v3/trades",
+            Self::Time => "/api/v3/time",
+            Self::ExchangeInfo => "/api/v3/exchangeInfo",
+        }
+    }
+
+    /// IP weight as documented by MEXC.
+    pub fn weight(self) -> u32 {
+        match self {
+            Self::Depth | Self::Time => 1,
+            Self::Trades => 5,
+            Self::ExchangeInfo => 10,
+        }
+    }
+}
+
+/// `/api/v3/depth`; levels are `[price, qty]` strings.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DepthResponse {
+    #[serde(rename = "lastUpdateId")]
+    pub last_update_id: u64,
+    pub bids: Vec<[String; 2]>,
+    pub asks: Vec<[String; 2]>,
+}
+
+/// One entry of `/api/v3/trades`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestTrade {
+    pub id: Option<u64>,
+    pub price: String,
+    pub qty: String,
+    #[serde(default)]
+    pub quote_qty: Option<String>,
+    pub time: Option<i64>,
+    #[serde(default)]
+    pub is_buyer_maker: Option<bool>,
+    #[serde(default)]
+    pub is_best_match: Option<bool>,
+    #[serde(default)]
+    pub trade_type: Option<String>,
+}
+
+/// `/api/v3/exchangeInfo`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeInfoResponse {
+    #[serde(default)]
+    pub server_time: Option<i64>,
+    pub symbols: Vec<RestSymbol>,
+}
+
+/// One entry of `/api/v3/exchangeInfo`; `status` is `"1"` online, `"2"` paused, `"3"` offline.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestSymbol {
+    pub symbol: String,
+    pub status: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    #[serde(default)]
+    pub base_asset_precision: u32,
+    /// Price decimals; older responses only have `quoteAssetPrecision`.
+    #[serde(default)]
+    pub quote_precision: Option<u32>,
+    #[serde(default)]
+    pub quote_asset_precision: u32,
+    /// Lot size as a decimal string, e.g. `"0.000001"`; `"0"` when unset.
+    #[serde(default)]
+    pub base_size_precision: Option<String>,
+    #[serde(default)]
+    pub is_spot_trading_allowed: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerTime {
+    server_time: i64,
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+    paused_until: Option<Instant>,
+}
+
+pub struct MexcRestClient {
+    http: reqwest::Client,
+    base: String,
+    api_key: Option<String>,
+    cfg: RestConfig,
+    bucket: Mutex<Bucket>,
+    telem: Option<Arc<Telemetry>>,
+}
+
+impl MexcRestClient {
+    /// `base` is e.g. `https://api.mexc.com`, without a trailing slash.
+    pub fn new(base: &str, cfg: &RestConfig, api_key: Option<String>) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(cfg.timeout_ms))
+            .connect_timeout(Duration::from_millis(cfg.timeout_ms))
+            .build()?;
+        Ok(Self {
+            http,
+            base: base.trim_end_matches('/').to_string(),
+            api_key,
+            cfg: cfg.clone(),
+            bucket: Mutex::new(Bucket { tokens: cfg.weight_limit as f64, last: Instant::now(), paused_until: None }),
+            telem: None,
+        })
+    }
+
+    pub fn with_telemetry(mut self, telem: Arc<Telemetry>) -> Self {
+        self.telem = Some(telem);
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base
+    }
+
+    /// Waits until `weight` fits the budget (and any pause is over), then takes it.
+    async fn acquire(&self, weight: u32) {
+        let cap = self.cfg.weight_limit.max(1) as f64;
+        let per_sec = cap / self.cfg.weight_window_secs.max(1) as f64;
+        let weight = (weight as f64).min(cap);
+        loop {
+            let wait = {
+                let mut b = self.bucket.lock().await;
+                let now = Instant::now();
+                match b.paused_until {
+                    Some(until) if until > now => until - now,
+                    _ => {
+                        b.tokens = (b.tokens + (now - b.last).as_secs_f64() * per_sec).min(cap);
+                        b.last = now;
+                        if b.tokens >= weight {
+                            b.tokens -= weight;
+                            return;
+                        }
+                        Duration::from_secs_f64((weight - b.tokens) / per_sec)
+                    }
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Holds every request of this client back for `d`.
+    async fn pause(&self, d: Duration) {
+        let until = Instant::now() + d;
+        let mut b = self.bucket.lock().await;
+        b.paused_until = Some(b.paused_until.map_or(until, |u| u.max(until)));
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ms = self.cfg.backoff_ms.saturating_mul(1 << attempt.min(16)).min(self.cfg.max_backoff_ms);
+        // up to +25% so clients that failed together do not retry together
+        let jitter = (clock::now_ns().unsigned_abs() % 1000) * ms / 4000;
+        Duration::from_millis(ms + jitter)
+    }
+
+    async fn send<T: DeserializeOwned>(&self, ep: Endpoint, query: &[(&str, &str)]) -> (Result<T, IngestError>, Option<Duration>) {
+        let mut req = self.http.get(format!("{}{}", self.base, ep.path())).query(query);
+        if let Some(k) = &self.api_key {
+            req = req.header("X-MEXC-APIKEY", k);
+        }
+        let t0 = Instant::now();
+        let resp = match req.send().await {
+            Ok(r) => r,
+            Err(e) => return (Err(e.into()), None),
+        };
+        if let Some(t) = &self.telem {
+            t.record_rest_rtt_ms(t0.elapsed().as_millis() as u64).await;
+        }
+        let status = resp.status();
+        let retry_after = resp
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok()?.trim().parse().ok())
+            .map(Duration::from_secs);
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default().chars().take(200).collect();
+            return (Err(IngestError::Rest { status: Some(status.as_u16()), body }), retry_after);
+        }
+        let res = match resp.bytes().await {
+            Ok(b) => serde_json::from_slice(&b)
+                .map_err(|e| IngestError::Rest { status: Some(status.as_u16()), body: format!("bad JSON: {e}") }),
+            Err(e) => Err(e.into()),
+        };
+        (res, None)
+    }
+
+    /// GET `ep` with rate limiting and retries.
+    pub async fn get<T: DeserializeOwned>(&self, ep: Endpoint, query: &[(&str, &str)]) -> Result<T, IngestError> {
+        let mut attempt = 0;
+        loop {
+            self.acquire(ep.weight()).await;
+            let (res, retry_after) = self.send(ep, query).await;
+            let e = match res {
+                Ok(v) => return Ok(v),
+                Err(e) if e.recovery() == Recovery::Retry && attempt < self.cfg.max_retries => e,
+                Err(e) => return Err(e),
+            };
+            let wait = retry_after.unwrap_or_else(|| self.backoff(attempt));
+            warn!(path = ep.path(), attempt, wait_ms = wait.as_millis() as u64, error = %e, "REST request failed, retrying");
+            attempt += 1;
+            if let IngestError::Rest { status: Some(429 | 418), .. } = e {
+                // rate limits are per IP: everyone waits
+                self.pause(wait).await;
+            } else {
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+
+    pub async fn depth(&self, symbol: &str, limit: usize) -> Result<DepthResponse, IngestError> {
+        self.get(Endpoint::Depth, &[("symbol", symbol), ("limit", &limit.to_string())]).await
+    }
+
+    pub async fn trades(&self, symbol: &str, limit: usize) -> Result<Vec<RestTrade>, IngestError> {
+        self.get(Endpoint::Trades, &[("symbol", symbol), ("limit", &limit.to_string())]).await
+    }
+
+    /// All symbols, or just `symbol`.
+    pub async fn exchange_info(&self, symbol: Option<&str>) -> Result<ExchangeInfoResponse, IngestError> {
+        match symbol {
+            Some(s) => self.get(Endpoint::ExchangeInfo, &[("symbol", s)]).await,
+            None => self.get(Endpoint::ExchangeInfo, &[]).await,
+        }
+    }
+
+    pub async fn server_time(&self) -> Result<i64, IngestError> {
+        Ok(self.get::<ServerTime>(Endpoint::Time, &[]).await?.server_time)
+    }
+}
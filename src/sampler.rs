@@ -0,0 +1,483 @@
+// sampler.rs
+//
+// Fixed-interval book samples for datasets, live (`record` with `[sampler]
+// enabled`) or offline (`sample`). Grid points are multiples of `interval_ms`
+// of receive time; the row at `t` is the top `levels` of the book as it was
+// just before `t`, with the trades and book updates of `[t - interval, t)`.
+//
+// A row is marked `gap` when its interval overlaps a resync gap: the book
+// broke or was reloaded from a snapshot in it, or it lies between the last
+// event before a reload and the reload itself (where a recorder restart
+// shows up in stored data). The first interval after the first snapshot is
+// partial and marked too. Rows while the book is broken have no levels.
+//
+// Rows are written as CSV or Parquet with one column per level price and
+// size (`bid_px_1`, `bid_qty_1`, ... `ask_qty_<levels>`).
+use anyhow::{anyhow, Result};
+use arrow::array::{ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use std::fs::{create_dir_all, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::config::SamplerConfig;
+use crate::replay::{replay_events, BookReplay};
+use crate::store::partition_dir;
+use crate::types::{BookSide, RevSide, TradeEvent};
+
+/// Rows are handed to the arrow writer in batches of this size.
+const BATCH_ROWS: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleFormat {
+    Csv,
+    #[default]
+    Parquet,
+}
+
+impl SampleFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+impl std::str::FromStr for SampleFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "parquet" => Ok(Self::Parquet),
+            _ => Err(anyhow!("unknown sample format {s} (csv|parquet)")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampleRow {
+    pub symbol: String,
+    /// Grid point; the row covers the interval before it.
+    pub ts_ms: i64,
+    /// Up to `levels` per side, best first; empty while the book is broken.
+    pub bids: Vec<[f64; 2]>,
+    pub asks: Vec<[f64; 2]>,
+    /// Book version at the start and at the end of the interval.
+    pub version_from: Option<u64>,
+    pub version_to: Option<u64>,
+    /// Book updates applied in the interval.
+    pub updates: u64,
+    /// The last trade at or before the end of the interval, in any interval.
+    pub last_trade_price: Option<f64>,
+    pub last_trade_qty: Option<f64>,
+    pub last_trade_ts_ms: Option<i64>,
+    pub trades: u64,
+    pub volume: f64,
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+    /// `None` without trades in the interval.
+    pub vwap: Option<f64>,
+    pub gap: bool,
+}
+
+/// Accumulates the interval before the next grid point.
+#[derive(Default)]
+struct Interval {
+    version_from: Option<u64>,
+    updates: u64,
+    trades: u64,
+    volume: f64,
+    buy_volume: f64,
+    sell_volume: f64,
+    notional: f64,
+    gap: bool,
+}
+
+/// Turns one symbol's book updates and trades into `SampleRow`s.
+pub struct Sampler {
+    symbol: String,
+    interval_ms: i64,
+    levels: usize,
+    /// `None` until the first snapshot.
+    next_ms: Option<i64>,
+    bids: Vec<[f64; 2]>,
+    asks: Vec<[f64; 2]>,
+    /// `None` while the book is broken.
+    version: Option<u64>,
+    last_trade: Option<(f64, f64, i64)>,
+    cur: Interval,
+}
+
+impl Sampler {
+    pub fn new(symbol: &str, interval_ms: u64, levels: usize) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            interval_ms: interval_ms.max(1) as i64,
+            levels,
+            next_ms: None,
+            bids: Vec::new(),
+            asks: Vec::new(),
+            version: None,
+            last_trade: None,
+            cur: Interval::default(),
+        }
+    }
+
+    fn row(&self, ts_ms: i64) -> SampleRow {
+        let c = &self.cur;
+        let book = self.version.is_some();
+        SampleRow {
+            symbol: self.symbol.clone(),
+            ts_ms,
+            bids: if book { self.bids.clone() } else { Vec::new() },
+            asks: if book { self.asks.clone() } else { Vec::new() },
+            version_from: c.version_from,
+            version_to: self.version,
+            updates: c.updates,
+            last_trade_price: self.last_trade.map(|t| t.0),
+            last_trade_qty: self.last_trade.map(|t| t.1),
+            last_trade_ts_ms: self.last_trade.map(|t| t.2),
+            trades: c.trades,
+            volume: c.volume,
+            buy_volume: c.buy_volume,
+            sell_volume: c.sell_volume,
+            vwap: (c.volume > 0.0).then(|| c.notional / c.volume),
+            gap: c.gap,
+        }
+    }
+
+    fn advance_marking(&mut self, now_ms: i64, gap: bool, out: &mut Vec<SampleRow>) {
+        while let Some(t) = self.next_ms.filter(|t| *t <= now_ms) {
+            self.cur.gap |= gap;
+            out.push(self.row(t));
+            self.next_ms = Some(t + self.interval_ms);
+            self.cur = Interval { version_from: self.version, gap: self.version.is_none(), ..Default::default() };
+        }
+    }
+
+    /// Emits the rows of every grid point up to and including `now_ms`.
+    pub fn advance(&mut self, now_ms: i64, out: &mut Vec<SampleRow>) {
+        self.advance_marking(now_ms, false, out);
+    }
+
+    fn copy_book(&mut self, asks: &BookSide, bids: &RevSide) {
+        self.asks.clear();
+        self.asks.extend(asks.iter().take(self.levels).map(|(p, q)| [p.0, *q]));
+        self.bids.clear();
+        self.bids.extend(bids.iter().take(self.levels).map(|(p, q)| [(p.0).0, *q]));
+    }
+
+    /// The book was (re)loaded from a snapshot at `ts_ms`.
+    pub fn on_snapshot(&mut self, ts_ms: i64, version: u64, asks: &BookSide, bids: &RevSide, out: &mut Vec<SampleRow>) {
+        // nothing was seen between the last event and the reload
+        self.advance_marking(ts_ms, true, out);
+        if self.next_ms.is_none() {
+            self.next_ms = Some(ts_ms.div_euclid(self.interval_ms) * self.interval_ms + self.interval_ms);
+            self.cur = Interval::default();
+        }
+        self.cur.gap = true;
+        self.copy_book(asks, bids);
+        self.version = Some(version);
+    }
+
+    /// An update at `ts_ms` brought the book to `version`.
+    pub fn on_book(&mut self, ts_ms: i64, version: u64, asks: &BookSide, bids: &RevSide, out: &mut Vec<SampleRow>) {
+        self.advance(ts_ms, out);
+        self.copy_book(asks, bids);
+        self.version = Some(version);
+        self.cur.updates += 1;
+    }
+
+    /// The book broke at `ts_ms` and is unusable until the next snapshot.
+    pub fn on_break(&mut self, ts_ms: i64, out: &mut Vec<SampleRow>) {
+        self.advance(ts_ms, out);
+        self.version = None;
+        self.cur.gap = true;
+    }
+
+    pub fn on_trade(&mut self, t: &TradeEvent, out: &mut Vec<SampleRow>) {
+        self.advance(t.ts_recv_ms, out);
+        let c = &mut self.cur;
+        c.trades += 1;
+        c.volume += t.qty;
+        c.notional += t.price * t.qty;
+        match t.side.as_deref() {
+            Some("BUY") => c.buy_volume += t.qty,
+            Some("SELL") => c.sell_volume += t.qty,
+            _ => {}
+        }
+        self.last_trade = Some((t.price, t.qty, t.ts_recv_ms));
+    }
+}
+
+/// Replays `symbol` through a `Sampler` and calls `on_row` for every row from
+/// `from_ms` up to the last event at or before `to_ms`.
+pub fn replay_samples<P: AsRef<Path>>(
+    root: P,
+    symbol: &str,
+    from_ms: i64,
+    to_ms: i64,
+    interval_ms: u64,
+    levels: usize,
+    mut on_row: impl FnMut(&SampleRow) -> Result<()>,
+) -> Result<BookReplay> {
+    let mut sampler = Sampler::new(symbol, interval_ms, levels);
+    let mut rows = Vec::new();
+    let book = replay_events(root, symbol, from_ms, to_ms, |book, ev, changed| {
+        match ev.kind.as_str() {
+            "trade" => {
+                if let Ok(t) = ev.payload_as::<TradeEvent>() {
+                    sampler.on_trade(&t, &mut rows);
+                }
+            }
+            "depth_snapshot" if changed => sampler.on_snapshot(ev.ts_ms, book.depth.version, &book.depth.asks, &book.depth.bids, &mut rows),
+            "depth_pb_raw" if changed => sampler.on_book(ev.ts_ms, book.depth.version, &book.depth.asks, &book.depth.bids, &mut rows),
+            "depth_pb_raw" if !book.depth.valid => sampler.on_break(ev.ts_ms, &mut rows),
+            _ => {}
+        }
+        for r in rows.drain(..).filter(|r| r.ts_ms >= from_ms) {
+            on_row(&r)?;
+        }
+        Ok(())
+    })?;
+    Ok(book)
+}
+
+enum Cell {
+    I64(Option<i64>),
+    U64(Option<u64>),
+    F64(Option<f64>),
+    Str(String),
+    Bool(bool),
+}
+
+fn columns(levels: usize) -> Vec<Field> {
+    let mut f = vec![Field::new("ts_ms", DataType::Int64, false), Field::new("symbol", DataType::Utf8, false)];
+    for side in ["bid", "ask"] {
+        for i in 1..=levels {
+            f.push(Field::new(format!("{side}_px_{i}"), DataType::Float64, true));
+            f.push(Field::new(format!("{side}_qty_{i}"), DataType::Float64, true));
+        }
+    }
+    f.extend([
+        Field::new("last_trade_price", DataType::Float64, true),
+        Field::new("last_trade_qty", DataType::Float64, true),
+        Field::new("last_trade_ts_ms", DataType::Int64, true),
+        Field::new("trades", DataType::UInt64, false),
+        Field::new("volume", DataType::Float64, false),
+        Field::new("buy_volume", DataType::Float64, false),
+        Field::new("sell_volume", DataType::Float64, false),
+        Field::new("vwap", DataType::Float64, true),
+        Field::new("version_from", DataType::UInt64, true),
+        Field::new("version_to", DataType::UInt64, true),
+        Field::new("updates", DataType::UInt64, false),
+        Field::new("gap", DataType::Boolean, false),
+    ]);
+    f
+}
+
+/// `r` in `columns(levels)` order.
+fn cells(r: &SampleRow, levels: usize) -> Vec<Cell> {
+    let mut c = vec![Cell::I64(Some(r.ts_ms)), Cell::Str(r.symbol.clone())];
+    for side in [&r.bids, &r.asks] {
+        for i in 0..levels {
+            let l = side.get(i);
+            c.push(Cell::F64(l.map(|l| l[0])));
+            c.push(Cell::F64(l.map(|l| l[1])));
+        }
+    }
+    c.extend([
+        Cell::F64(r.last_trade_price),
+        Cell::F64(r.last_trade_qty),
+        Cell::I64(r.last_trade_ts_ms),
+        Cell::U64(Some(r.trades)),
+        Cell::F64(Some(r.volume)),
+        Cell::F64(Some(r.buy_volume)),
+        Cell::F64(Some(r.sell_volume)),
+        Cell::F64(r.vwap),
+        Cell::U64(r.version_from),
+        Cell::U64(r.version_to),
+        Cell::U64(Some(r.updates)),
+        Cell::Bool(r.gap),
+    ]);
+    c
+}
+
+fn build_batch(schema: &SchemaRef, levels: usize, rows: &[SampleRow]) -> Result<RecordBatch> {
+    let cells: Vec<Vec<Cell>> = rows.iter().map(|r| cells(r, levels)).collect();
+    let mut arrays: Vec<ArrayRef> = Vec::with_capacity(schema.fields().len());
+    for (i, f) in schema.fields().iter().enumerate() {
+        let col = cells.iter().map(|c| &c[i]);
+        let a: ArrayRef = match f.data_type() {
+            DataType::Int64 => {
+                let mut b = Int64Builder::with_capacity(rows.len());
+                col.for_each(|c| b.append_option(if let Cell::I64(v) = c { *v } else { None }));
+                Arc::new(b.finish())
+            }
+            DataType::UInt64 => {
+                let mut b = UInt64Builder::with_capacity(rows.len());
+                col.for_each(|c| b.append_option(if let Cell::U64(v) = c { *v } else { None }));
+                Arc::new(b.finish())
+            }
+            DataType::Float64 => {
+                let mut b = Float64Builder::with_capacity(rows.len());
+                col.for_each(|c| b.append_option(if let Cell::F64(v) = c { *v } else { None }));
+                Arc::new(b.finish())
+            }
+            DataType::Boolean => {
+                let mut b = BooleanBuilder::with_capacity(rows.len());
+                col.for_each(|c| b.append_value(matches!(c, Cell::Bool(true))));
+                Arc::new(b.finish())
+            }
+            _ => {
+                let mut b = StringBuilder::new();
+                col.for_each(|c| if let Cell::Str(s) = c { b.append_value(s) } else { b.append_null() });
+                Arc::new(b.finish())
+            }
+        };
+        arrays.push(a);
+    }
+    Ok(RecordBatch::try_new(schema.clone(), arrays)?)
+}
+
+enum Out {
+    Csv(BufWriter<File>),
+    Parquet { writer: Box<ArrowWriter<File>>, schema: SchemaRef, rows: Vec<SampleRow> },
+}
+
+/// One CSV or Parquet file of rows, written as `<path>.inprogress` and renamed
+/// by `finish` (or on drop).
+pub struct SampleWriter {
+    tmp: PathBuf,
+    dst: PathBuf,
+    levels: usize,
+    out: Option<Out>,
+}
+
+impl SampleWriter {
+    /// Replaces whatever is at `path` once finished.
+    pub fn create(path: &Path, format: SampleFormat, levels: usize) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+        let tmp = PathBuf::from(format!("{}.inprogress", path.display()));
+        let file = File::create(&tmp)?;
+        let fields = columns(levels);
+        let out = match format {
+            SampleFormat::Csv => {
+                let mut w = BufWriter::new(file);
+                let header: Vec<&str> = fields.iter().map(|f| f.name().as_str()).collect();
+                writeln!(w, "{}", header.join(","))?;
+                Out::Csv(w)
+            }
+            SampleFormat::Parquet => {
+                let schema = Arc::new(Schema::new(fields));
+                let props = WriterProperties::builder().set_compression(Compression::ZSTD(ZstdLevel::try_new(3)?)).build();
+                let writer = Box::new(ArrowWriter::try_new(file, schema.clone(), Some(props))?);
+                Out::Parquet { writer, schema, rows: Vec::with_capacity(BATCH_ROWS) }
+            }
+        };
+        Ok(Self { tmp, dst: path.to_path_buf(), levels, out: Some(out) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.dst
+    }
+
+    pub fn write(&mut self, r: &SampleRow) -> Result<()> {
+        match self.out.as_mut() {
+            Some(Out::Csv(w)) => {
+                let line: Vec<String> = cells(r, self.levels)
+                    .into_iter()
+                    .map(|c| match c {
+                        Cell::I64(v) => v.map_or(String::new(), |v| v.to_string()),
+                        Cell::U64(v) => v.map_or(String::new(), |v| v.to_string()),
+                        Cell::F64(v) => v.map_or(String::new(), |v| v.to_string()),
+                        Cell::Str(s) => s,
+                        Cell::Bool(b) => b.to_string(),
+                    })
+                    .collect();
+                writeln!(w, "{}", line.join(","))?;
+            }
+            Some(Out::Parquet { writer, schema, rows }) => {
+                rows.push(r.clone());
+                if rows.len() >= BATCH_ROWS {
+                    writer.write(&build_batch(schema, self.levels, rows)?)?;
+                    rows.clear();
+                }
+            }
+            None => return Err(anyhow!("{} is already finished", self.dst.display())),
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        match self.out.take() {
+            Some(Out::Csv(mut w)) => w.flush()?,
+            Some(Out::Parquet { mut writer, schema, rows }) => {
+                if !rows.is_empty() {
+                    writer.write(&build_batch(&schema, self.levels, &rows)?)?;
+                }
+                writer.close()?;
+            }
+            None => return Ok(()),
+        }
+        std::fs::rename(&self.tmp, &self.dst)?;
+        Ok(())
+    }
+}
+
+impl Drop for SampleWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            tracing::error!(path = %self.dst.display(), error = %e, "sample file close failed");
+        }
+    }
+}
+
+/// Live rows of one symbol, one file per hour partition under `[sampler] dir`:
+/// `symbol=X/date=Y/hour=Z/samples_<interval>ms.<ext>`, with `.1`, `.2`...
+/// after a restart within the hour.
+pub struct SampleFiles {
+    base: PathBuf,
+    cfg: SamplerConfig,
+    open: Option<(PathBuf, SampleWriter)>,
+}
+
+impl SampleFiles {
+    pub fn new(cfg: &SamplerConfig) -> Self {
+        Self { base: PathBuf::from(&cfg.dir), cfg: cfg.clone(), open: None }
+    }
+
+    pub fn write(&mut self, r: &SampleRow) -> Result<()> {
+        let dir = partition_dir(&self.base, &r.symbol, r.ts_ms);
+        if self.open.as_ref().is_some_and(|(d, _)| *d != dir) {
+            if let Some((_, mut w)) = self.open.take() {
+                w.finish()?;
+            }
+        }
+        let w = match &mut self.open {
+            Some((_, w)) => w,
+            None => {
+                let (stem, ext) = (format!("samples_{}ms", self.cfg.interval_ms), self.cfg.format.extension());
+                let mut path = dir.join(format!("{stem}.{ext}"));
+                let mut n = 0;
+                while path.exists() || PathBuf::from(format!("{}.inprogress", path.display())).exists() {
+                    n += 1;
+                    path = dir.join(format!("{stem}.{n}.{ext}"));
+                }
+                let w = SampleWriter::create(&path, self.cfg.format, self.cfg.levels)?;
+                &mut self.open.insert((dir, w)).1
+            }
+        };
+        w.write(r)
+    }
+}
@@ -0,0 +1,392 @@
+// server.rs
+//
+// Embedded WebSocket fan-out so local consumers can share the single upstream
+// MEXC connection.
+//
+// client -> server:
+//   {"op":"subscribe","channel":"book.BTCUSDT"}             full diff stream
+//   {"op":"subscribe","channel":"book.BTCUSDT","depth":20}   top-N on every update
+//   {"op":"subscribe","channel":"trades.BTCUSDT"}
+//   {"op":"subscribe","channel":"features.BTCUSDT"}          `[features] enabled` rows
+//   {"op":"unsubscribe","channel":"book.BTCUSDT"}
+//
+// server -> client (book, full mode): a "snapshot" first, then "delta"s with a
+// per-symbol `seq` that increases by exactly one. A hole in `seq` means the
+// client missed something; the server re-sends a snapshot itself when a
+// subscriber falls too far behind.
+//
+// Only symbols the recorder has published can be subscribed to; anything else
+// gets an error reply.
+use anyhow::{anyhow, Result};
+use futures::{SinkExt, StreamExt};
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc},
+    task::JoinHandle,
+};
+use tokio_tungstenite::{accept_async, tungstenite::protocol::Message as WsMsg};
+use tracing::Instrument as _;
+
+use crate::features::FeatureRow;
+use crate::types::{AppliedDelta, BookSide, RevSide, TradeEvent};
+
+const BOOK_CHAN_CAP: usize = 4096;
+const TRADE_CHAN_CAP: usize = 4096;
+const FEATURE_CHAN_CAP: usize = 1024;
+const CLIENT_OUT_CAP: usize = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BookMsg {
+    Snapshot {
+        seq: u64,
+        version: u64,
+        bids: Vec<[f64; 2]>,
+        asks: Vec<[f64; 2]>,
+    },
+    Delta {
+        seq: u64,
+        from_version: u64,
+        to_version: u64,
+        bids: Vec<[f64; 2]>,
+        asks: Vec<[f64; 2]>,
+    },
+}
+
+#[derive(Default)]
+struct FeedState {
+    seq: u64,
+    version: u64,
+    asks: BookSide,
+    bids: RevSide,
+}
+
+impl FeedState {
+    fn snapshot_msg(&self) -> BookMsg {
+        BookMsg::Snapshot {
+            seq: self.seq,
+            version: self.version,
+            bids: self.bids.iter().map(|(k, q)| [(k.0).0, *q]).collect(),
+            asks: self.asks.iter().map(|(k, q)| [k.0, *q]).collect(),
+        }
+    }
+}
+
+struct SymbolFeed {
+    state: Mutex<FeedState>,
+    book_tx: broadcast::Sender<Arc<BookMsg>>,
+    trades_tx: broadcast::Sender<Arc<TradeEvent>>,
+    features_tx: broadcast::Sender<Arc<FeatureRow>>,
+}
+
+impl SymbolFeed {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(FeedState::default()),
+            book_tx: broadcast::channel(BOOK_CHAN_CAP).0,
+            trades_tx: broadcast::channel(TRADE_CHAN_CAP).0,
+            features_tx: broadcast::channel(FEATURE_CHAN_CAP).0,
+        }
+    }
+
+    /// Snapshot and receiver are taken under the same lock the publisher holds
+    /// while sending, so nothing falls between the two.
+    fn subscribe_book(&self) -> (BookMsg, broadcast::Receiver<Arc<BookMsg>>) {
+        let st = self.state.lock().unwrap();
+        (st.snapshot_msg(), self.book_tx.subscribe())
+    }
+}
+
+fn apply_levels(asks: &mut BookSide, bids: &mut RevSide, a: &[[f64; 2]], b: &[[f64; 2]]) {
+    for [p, q] in a {
+        if *q == 0.0 { asks.remove(&OrderedFloat(*p)); } else { asks.insert(OrderedFloat(*p), *q); }
+    }
+    for [p, q] in b {
+        let k = Reverse(OrderedFloat(*p));
+        if *q == 0.0 { bids.remove(&k); } else { bids.insert(k, *q); }
+    }
+}
+
+/// Mirror of every published book plus the broadcast channels clients attach to.
+#[derive(Default)]
+pub struct BookHub {
+    feeds: RwLock<HashMap<String, Arc<SymbolFeed>>>,
+}
+
+impl BookHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The feed of a published symbol; clients cannot create feeds.
+    fn get(&self, symbol: &str) -> Option<Arc<SymbolFeed>> {
+        self.feeds.read().unwrap().get(symbol).cloned()
+    }
+
+    /// Publisher side: creates the feed on first use.
+    fn feed(&self, symbol: &str) -> Arc<SymbolFeed> {
+        if let Some(f) = self.get(symbol) {
+            return f;
+        }
+        self.feeds
+            .write()
+            .unwrap()
+            .entry(symbol.to_string())
+            .or_insert_with(|| Arc::new(SymbolFeed::new()))
+            .clone()
+    }
+
+    pub fn publish_snapshot(&self, symbol: &str, version: u64, asks: &BookSide, bids: &RevSide) {
+        let feed = self.feed(symbol);
+        let mut st = feed.state.lock().unwrap();
+        st.seq += 1;
+        st.version = version;
+        st.asks = asks.clone();
+        st.bids = bids.clone();
+        let _ = feed.book_tx.send(Arc::new(st.snapshot_msg()));
+    }
+
+    pub fn publish_delta(&self, symbol: &str, d: &AppliedDelta) {
+        let feed = self.feed(symbol);
+        let mut st = feed.state.lock().unwrap();
+        st.seq += 1;
+        st.version = d.to_version;
+        let FeedState { asks, bids, .. } = &mut *st;
+        apply_levels(asks, bids, &d.asks, &d.bids);
+        let _ = feed.book_tx.send(Arc::new(BookMsg::Delta {
+            seq: st.seq,
+            from_version: d.from_version,
+            to_version: d.to_version,
+            bids: d.bids.clone(),
+            asks: d.asks.clone(),
+        }));
+    }
+
+    pub fn publish_trade(&self, symbol: &str, t: &TradeEvent) {
+        let _ = self.feed(symbol).trades_tx.send(Arc::new(t.clone()));
+    }
+
+    pub fn publish_features(&self, symbol: &str, row: &FeatureRow) {
+        let _ = self.feed(symbol).features_tx.send(Arc::new(row.clone()));
+    }
+}
+
+pub async fn serve(hub: Arc<BookHub>, bind: &str) -> Result<()> {
+    let listener = TcpListener::bind(bind).await?;
+    tracing::info!(bind, "book server listening");
+    serve_listener(hub, listener).await
+}
+
+/// `serve` on an already bound listener.
+pub async fn serve_listener(hub: Arc<BookHub>, listener: TcpListener) -> Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let hub = hub.clone();
+        let span = tracing::info_span!("book_client", %peer);
+        tokio::spawn(
+            async move {
+                if let Err(e) = handle_client(hub, stream).await {
+                    tracing::warn!(error = %e, "book server client failed");
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
+
+#[derive(Deserialize)]
+struct ClientReq {
+    op: String,
+    channel: String,
+    #[serde(default)]
+    depth: Option<usize>,
+}
+
+enum Channel<'a> {
+    Book(&'a str),
+    Trades(&'a str),
+    Features(&'a str),
+}
+
+fn parse_channel(c: &str) -> Option<Channel<'_>> {
+    if let Some(s) = c.strip_prefix("book.") {
+        Some(Channel::Book(s))
+    } else if let Some(s) = c.strip_prefix("trades.") {
+        Some(Channel::Trades(s))
+    } else {
+        c.strip_prefix("features.").map(Channel::Features)
+    }
+}
+
+async fn handle_client(hub: Arc<BookHub>, stream: TcpStream) -> Result<()> {
+    let mut ws = accept_async(stream).await?;
+    let (out_tx, mut out_rx) = mpsc::channel::<String>(CLIENT_OUT_CAP);
+    let mut subs: HashMap<String, JoinHandle<()>> = HashMap::new();
+
+    let res = loop {
+        tokio::select! {
+            Some(line) = out_rx.recv() => {
+                if let Err(e) = ws.send(WsMsg::Text(line)).await { break Err(e.into()); }
+            }
+            msg = ws.next() => {
+                let text = match msg {
+                    Some(Ok(WsMsg::Text(t))) => t,
+                    Some(Ok(WsMsg::Close(_))) | None => break Ok(()),
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => break Err(e.into()),
+                };
+                let reply = match handle_request(&hub, &text, &mut subs, &out_tx) {
+                    Ok(r) => r,
+                    Err(e) => serde_json::json!({ "op": "error", "msg": e.to_string() }),
+                };
+                if let Err(e) = ws.send(WsMsg::Text(reply.to_string())).await { break Err(e.into()); }
+            }
+        }
+    };
+    for (_, h) in subs.drain() {
+        h.abort();
+    }
+    res
+}
+
+fn handle_request(
+    hub: &BookHub,
+    text: &str,
+    subs: &mut HashMap<String, JoinHandle<()>>,
+    out: &mpsc::Sender<String>,
+) -> Result<serde_json::Value> {
+    let req: ClientReq = serde_json::from_str(text)?;
+    let chan = parse_channel(&req.channel).ok_or_else(|| anyhow!("unknown channel {}", req.channel))?;
+    match req.op.as_str() {
+        "subscribe" => {
+            if subs.contains_key(&req.channel) {
+                return Err(anyhow!("already subscribed to {}", req.channel));
+            }
+            let (Channel::Book(sym) | Channel::Trades(sym) | Channel::Features(sym)) = chan;
+            let feed = hub.get(sym).ok_or_else(|| anyhow!("unknown symbol {sym}"))?;
+            let handle = match chan {
+                Channel::Book(_) => tokio::spawn(forward_book(feed, req.channel.clone(), req.depth, out.clone())),
+                Channel::Trades(_) => tokio::spawn(forward_trades(feed, req.channel.clone(), out.clone())),
+                Channel::Features(_) => tokio::spawn(forward_features(feed, req.channel.clone(), out.clone())),
+            };
+            subs.insert(req.channel.clone(), handle);
+            Ok(serde_json::json!({ "op": "subscribed", "channel": req.channel }))
+        }
+        "unsubscribe" => {
+            let h = subs.remove(&req.channel).ok_or_else(|| anyhow!("not subscribed to {}", req.channel))?;
+            h.abort();
+            Ok(serde_json::json!({ "op": "unsubscribed", "channel": req.channel }))
+        }
+        op => Err(anyhow!("unknown op {op}")),
+    }
+}
+
+fn with_channel<T: Serialize>(channel: &str, msg: &T) -> String {
+    let mut v = serde_json::to_value(msg).unwrap_or_default();
+    if let Some(o) = v.as_object_mut() {
+        o.insert("channel".into(), channel.into());
+    }
+    v.to_string()
+}
+
+fn top_msg(channel: &str, seq: u64, version: u64, asks: &BookSide, bids: &RevSide, n: usize) -> String {
+    serde_json::json!({
+        "channel": channel,
+        "type": "top",
+        "seq": seq,
+        "version": version,
+        "bids": bids.iter().take(n).map(|(k, q)| [(k.0).0, *q]).collect::<Vec<_>>(),
+        "asks": asks.iter().take(n).map(|(k, q)| [k.0, *q]).collect::<Vec<_>>(),
+    })
+    .to_string()
+}
+
+async fn forward_book(feed: Arc<SymbolFeed>, channel: String, depth: Option<usize>, out: mpsc::Sender<String>) {
+    loop {
+        let (snap, mut rx) = feed.subscribe_book();
+        // top-N subscribers keep a private replica so every frame they get is consistent
+        let mut asks = BookSide::new();
+        let mut bids = RevSide::new();
+        let first = match (&snap, depth) {
+            (BookMsg::Snapshot { seq, version, bids: b, asks: a }, Some(n)) => {
+                asks.extend(a.iter().map(|[p, q]| (OrderedFloat(*p), *q)));
+                bids.extend(b.iter().map(|[p, q]| (Reverse(OrderedFloat(*p)), *q)));
+                top_msg(&channel, *seq, *version, &asks, &bids, n)
+            }
+            _ => with_channel(&channel, &snap),
+        };
+        if out.send(first).await.is_err() {
+            return;
+        }
+
+        loop {
+            let msg = match rx.recv().await {
+                Ok(m) => m,
+                Err(broadcast::error::RecvError::Lagged(_)) => break, // start over from a fresh snapshot
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            let line = match depth {
+                None => with_channel(&channel, &*msg),
+                Some(n) => match &*msg {
+                    BookMsg::Snapshot { seq, version, bids: b, asks: a } => {
+                        asks = a.iter().map(|[p, q]| (OrderedFloat(*p), *q)).collect();
+                        bids = b.iter().map(|[p, q]| (Reverse(OrderedFloat(*p)), *q)).collect();
+                        top_msg(&channel, *seq, *version, &asks, &bids, n)
+                    }
+                    BookMsg::Delta { seq, to_version, bids: b, asks: a, .. } => {
+                        apply_levels(&mut asks, &mut bids, a, b);
+                        top_msg(&channel, *seq, *to_version, &asks, &bids, n)
+                    }
+                },
+            };
+            if out.send(line).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn forward_trades(feed: Arc<SymbolFeed>, channel: String, out: mpsc::Sender<String>) {
+    let mut rx = feed.trades_tx.subscribe();
+    loop {
+        let t = match rx.recv().await {
+            Ok(t) => t,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                let gap = serde_json::json!({ "channel": channel, "type": "lagged", "missed": n });
+                if out.send(gap.to_string()).await.is_err() { return; }
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let line = serde_json::json!({ "channel": channel, "type": "trade", "data": &*t });
+        if out.send(line.to_string()).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn forward_features(feed: Arc<SymbolFeed>, channel: String, out: mpsc::Sender<String>) {
+    let mut rx = feed.features_tx.subscribe();
+    loop {
+        let row = match rx.recv().await {
+            Ok(r) => r,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                let gap = serde_json::json!({ "channel": channel, "type": "lagged", "missed": n });
+                if out.send(gap.to_string()).await.is_err() { return; }
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let line = serde_json::json!({ "channel": channel, "type": "features", "data": &*row });
+        if out.send(line.to_string()).await.is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,273 @@
+// shm.rs
+//
+// Seqlock-protected top-of-book in a memory-mapped file, one file per symbol
+// (`<dir>/mexc-tob-<SYMBOL>`). The recorder is the only writer; any number of
+// local processes map the same file and read consistent snapshots without a
+// syscall per read.
+//
+// The region is an array of 64-bit words, all accessed atomically:
+//
+//   0   magic "MEXCTOB1"
+//   1   layout version (low 32) | levels N (high 32)
+//   2   seq, odd while a write is in progress
+//   3   book version (lastUpdateId / toVersion)
+//   4   ts_recv_ms   local receive time of the update
+//   5   ts_exch_ms   exchange send time, i64::MIN if unknown
+//   6   ts_write_ns  wall clock when the region was written
+//   7   n_bids (low 32) | n_asks (high 32)
+//   8-9 symbol, ASCII, zero padded
+//   10  gaps     recorder counters, written outside the seqlock (monotonic,
+//   11  resyncs  read them with `counters()`)
+//   16.. bids as N (price, qty) pairs of f64 bits, then asks likewise
+use anyhow::{anyhow, Result};
+use memmap2::{Mmap, MmapMut};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+use crate::clock;
+use crate::types::{BookSide, RevSide};
+
+const MAGIC: u64 = u64::from_le_bytes(*b"MEXCTOB1");
+const LAYOUT_VERSION: u64 = 1;
+const HEADER_WORDS: usize = 16;
+
+const W_MAGIC: usize = 0;
+const W_LAYOUT: usize = 1;
+const W_SEQ: usize = 2;
+const W_VERSION: usize = 3;
+const W_TS_RECV: usize = 4;
+const W_TS_EXCH: usize = 5;
+const W_TS_WRITE: usize = 6;
+const W_COUNTS: usize = 7;
+const W_SYMBOL: usize = 8;
+const W_GAPS: usize = 10;
+const W_RESYNCS: usize = 11;
+
+fn region_words(levels: usize) -> usize {
+    HEADER_WORDS + 4 * levels
+}
+
+pub fn shm_path<P: AsRef<Path>>(dir: P, symbol: &str) -> PathBuf {
+    dir.as_ref().join(format!("mexc-tob-{symbol}"))
+}
+
+fn words(ptr: *const u8, len: usize) -> &'static [AtomicU64] {
+    // mmap'd regions are page aligned and the mapping outlives every borrow
+    // handed out below (both owners keep the Mmap alongside the slice).
+    unsafe { std::slice::from_raw_parts(ptr as *const AtomicU64, len / 8) }
+}
+
+pub struct ShmWriter {
+    _map: MmapMut,
+    w: &'static [AtomicU64],
+    levels: usize,
+}
+
+impl ShmWriter {
+    /// Reuses an existing region of the same symbol and layout in place, so
+    /// that readers mapping it (a dashboard across a recorder restart) keep
+    /// working. Anything else is replaced by a fresh file renamed over it:
+    /// truncating a mapped file would SIGBUS its readers, which keep the old
+    /// one instead.
+    pub fn create<P: AsRef<Path>>(dir: P, symbol: &str, levels: usize) -> Result<Self> {
+        if symbol.len() > 16 {
+            return Err(anyhow!("symbol {symbol} longer than 16 bytes"));
+        }
+        let path = shm_path(dir, symbol);
+        let len = region_words(levels) * 8;
+        let mut sym = [0u8; 16];
+        sym[..symbol.len()].copy_from_slice(symbol.as_bytes());
+        let sym_words = [
+            u64::from_le_bytes(sym[..8].try_into().unwrap()),
+            u64::from_le_bytes(sym[8..].try_into().unwrap()),
+        ];
+        let layout = LAYOUT_VERSION | ((levels as u64) << 32);
+
+        if let Ok(file) = OpenOptions::new().read(true).write(true).open(&path) {
+            if file.metadata()?.len() == len as u64 {
+                let map = unsafe { MmapMut::map_mut(&file)? };
+                let w = words(map.as_ptr(), len);
+                let same = w[W_MAGIC].load(Ordering::Acquire) == MAGIC
+                    && w[W_LAYOUT].load(Ordering::Relaxed) == layout
+                    && w[W_SYMBOL].load(Ordering::Relaxed) == sym_words[0]
+                    && w[W_SYMBOL + 1].load(Ordering::Relaxed) == sym_words[1];
+                if same {
+                    // a writer that died mid-publish left seq odd
+                    let seq = w[W_SEQ].load(Ordering::Relaxed);
+                    w[W_SEQ].store(seq + (seq & 1), Ordering::Release);
+                    w[W_GAPS].store(0, Ordering::Relaxed);
+                    w[W_RESYNCS].store(0, Ordering::Relaxed);
+                    return Ok(Self { _map: map, w, levels });
+                }
+            }
+        }
+
+        let tmp = path.with_file_name(format!("{}.tmp", path.file_name().unwrap().to_string_lossy()));
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&tmp)?;
+        file.set_len(len as u64)?;
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        let w = words(map.as_ptr(), len);
+        w[W_SYMBOL].store(sym_words[0], Ordering::Relaxed);
+        w[W_SYMBOL + 1].store(sym_words[1], Ordering::Relaxed);
+        w[W_LAYOUT].store(layout, Ordering::Relaxed);
+        w[W_SEQ].store(0, Ordering::Relaxed);
+        w[W_TS_EXCH].store(i64::MIN as u64, Ordering::Relaxed);
+        // magic last: readers refuse the file until the header is complete
+        w[W_MAGIC].store(MAGIC, Ordering::Release);
+        std::fs::rename(&tmp, &path)?;
+
+        Ok(Self { _map: map, w, levels })
+    }
+
+    pub fn publish(&mut self, version: u64, ts_recv_ms: i64, ts_exch_ms: Option<i64>, asks: &BookSide, bids: &RevSide) {
+        let w = self.w;
+        let seq = w[W_SEQ].load(Ordering::Relaxed);
+        w[W_SEQ].store(seq + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        w[W_VERSION].store(version, Ordering::Relaxed);
+        w[W_TS_RECV].store(ts_recv_ms as u64, Ordering::Relaxed);
+        w[W_TS_EXCH].store(ts_exch_ms.unwrap_or(i64::MIN) as u64, Ordering::Relaxed);
+        w[W_TS_WRITE].store(clock::now_ns() as u64, Ordering::Relaxed);
+
+        let base = HEADER_WORDS;
+        let mut nb = 0;
+        for (i, (k, q)) in bids.iter().take(self.levels).enumerate() {
+            w[base + 2 * i].store(((k.0).0).to_bits(), Ordering::Relaxed);
+            w[base + 2 * i + 1].store(q.to_bits(), Ordering::Relaxed);
+            nb += 1;
+        }
+        let base = HEADER_WORDS + 2 * self.levels;
+        let mut na = 0;
+        for (i, (k, q)) in asks.iter().take(self.levels).enumerate() {
+            w[base + 2 * i].store(k.0.to_bits(), Ordering::Relaxed);
+            w[base + 2 * i + 1].store(q.to_bits(), Ordering::Relaxed);
+            na += 1;
+        }
+        w[W_COUNTS].store(nb | (na << 32), Ordering::Relaxed);
+
+        w[W_SEQ].store(seq + 2, Ordering::Release);
+    }
+
+    pub fn set_counters(&mut self, gaps: u64, resyncs: u64) {
+        self.w[W_GAPS].store(gaps, Ordering::Relaxed);
+        self.w[W_RESYNCS].store(resyncs, Ordering::Relaxed);
+    }
+}
+
+/// One consistent read of the region. `bids`/`asks` are best-first.
+#[derive(Debug, Clone, Default)]
+pub struct TopOfBook {
+    pub seq: u64,
+    pub version: u64,
+    pub ts_recv_ms: i64,
+    pub ts_exch_ms: Option<i64>,
+    pub ts_write_ns: i64,
+    pub bids: Vec<[f64; 2]>,
+    pub asks: Vec<[f64; 2]>,
+}
+
+pub struct ShmReader {
+    _map: Mmap,
+    w: &'static [AtomicU64],
+    levels: usize,
+    symbol: String,
+}
+
+impl ShmReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = OpenOptions::new().read(true).open(path.as_ref())?;
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER_WORDS * 8 {
+            return Err(anyhow!("{}: region too small", path.as_ref().display()));
+        }
+        let w = words(map.as_ptr(), map.len());
+        if w[W_MAGIC].load(Ordering::Acquire) != MAGIC {
+            return Err(anyhow!("{}: bad magic", path.as_ref().display()));
+        }
+        let layout = w[W_LAYOUT].load(Ordering::Relaxed);
+        if layout & 0xffff_ffff != LAYOUT_VERSION {
+            return Err(anyhow!("{}: unsupported layout {}", path.as_ref().display(), layout & 0xffff_ffff));
+        }
+        let levels = (layout >> 32) as usize;
+        if map.len() < region_words(levels) * 8 {
+            return Err(anyhow!("{}: truncated region", path.as_ref().display()));
+        }
+        let mut sym = [0u8; 16];
+        sym[..8].copy_from_slice(&w[W_SYMBOL].load(Ordering::Relaxed).to_le_bytes());
+        sym[8..].copy_from_slice(&w[W_SYMBOL + 1].load(Ordering::Relaxed).to_le_bytes());
+        let symbol = String::from_utf8_lossy(&sym).trim_end_matches('\0').to_string();
+        Ok(Self { _map: map, w, levels, symbol })
+    }
+
+    pub fn open_symbol<P: AsRef<Path>>(dir: P, symbol: &str) -> Result<Self> {
+        Self::open(shm_path(dir, symbol))
+    }
+
+    pub fn symbol(&self) -> &str { &self.symbol }
+    pub fn levels(&self) -> usize { self.levels }
+
+    /// Current seq without copying the book; cheap way to poll for changes.
+    pub fn seq(&self) -> u64 {
+        self.w[W_SEQ].load(Ordering::Acquire)
+    }
+
+    /// Recorder gap and resync counts.
+    pub fn counters(&self) -> (u64, u64) {
+        (self.w[W_GAPS].load(Ordering::Relaxed), self.w[W_RESYNCS].load(Ordering::Relaxed))
+    }
+
+    /// Single attempt; `false` if the writer was active and `out` is garbage.
+    pub fn try_read_into(&self, out: &mut TopOfBook) -> bool {
+        let w = self.w;
+        let s1 = w[W_SEQ].load(Ordering::Acquire);
+        if s1 & 1 == 1 {
+            return false;
+        }
+        out.version = w[W_VERSION].load(Ordering::Relaxed);
+        out.ts_recv_ms = w[W_TS_RECV].load(Ordering::Relaxed) as i64;
+        let exch = w[W_TS_EXCH].load(Ordering::Relaxed) as i64;
+        out.ts_exch_ms = (exch != i64::MIN).then_some(exch);
+        out.ts_write_ns = w[W_TS_WRITE].load(Ordering::Relaxed) as i64;
+        let counts = w[W_COUNTS].load(Ordering::Relaxed);
+        let nb = ((counts & 0xffff_ffff) as usize).min(self.levels);
+        let na = ((counts >> 32) as usize).min(self.levels);
+
+        out.bids.clear();
+        for i in 0..nb {
+            let p = f64::from_bits(w[HEADER_WORDS + 2 * i].load(Ordering::Relaxed));
+            let q = f64::from_bits(w[HEADER_WORDS + 2 * i + 1].load(Ordering::Relaxed));
+            out.bids.push([p, q]);
+        }
+        let base = HEADER_WORDS + 2 * self.levels;
+        out.asks.clear();
+        for i in 0..na {
+            let p = f64::from_bits(w[base + 2 * i].load(Ordering::Relaxed));
+            let q = f64::from_bits(w[base + 2 * i + 1].load(Ordering::Relaxed));
+            out.asks.push([p, q]);
+        }
+
+        fence(Ordering::Acquire);
+        let s2 = w[W_SEQ].load(Ordering::Relaxed);
+        out.seq = s1;
+        s1 == s2
+    }
+
+    /// Spins until a consistent copy is obtained. Reuses `out`'s buffers.
+    pub fn read_into(&self, out: &mut TopOfBook) {
+        while !self.try_read_into(out) {
+            std::hint::spin_loop();
+        }
+    }
+
+    pub fn read(&self) -> TopOfBook {
+        let mut out = TopOfBook {
+            bids: Vec::with_capacity(self.levels),
+            asks: Vec::with_capacity(self.levels),
+            ..Default::default()
+        };
+        self.read_into(&mut out);
+        out
+    }
+}
@@ -1,32 +1,174 @@
 // store.rs
-use anyhow::Result;
-use serde::Serialize;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 use std::fs::{create_dir_all, OpenOptions};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
 use time::OffsetDateTime;
 use base64::Engine as _;
 
+use crate::clock::Stamp;
+use crate::columnar::{ParquetSink, Row, RowPayload};
+use crate::manifest::{invalidate_manifest, write_manifest};
+use crate::rawlog::{RawChannel, RawWriter, RAW_FILE_NAME};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageFormat {
+    #[default]
+    Ndjson,
+    Parquet,
+    Both,
+}
+
+impl StorageFormat {
+    fn ndjson(self) -> bool { matches!(self, Self::Ndjson | Self::Both) }
+    fn parquet(self) -> bool { matches!(self, Self::Parquet | Self::Both) }
+}
+
+impl std::str::FromStr for StorageFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ndjson" => Ok(Self::Ndjson),
+            "parquet" => Ok(Self::Parquet),
+            "both" => Ok(Self::Both),
+            _ => Err(anyhow!("unknown storage format {s} (ndjson|parquet|both)")),
+        }
+    }
+}
+
+/// Where [`DataStore::capture_raw`] puts WS frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RawCapture {
+    /// Length-delimited binary records in `raw.pb.zst`.
+    #[default]
+    Pb,
+    /// Legacy base64 `*_pb_raw` lines in `events.ndjson.zst`.
+    B64,
+    Off,
+}
+
+/// A partition is finalized (files closed, `manifest.json` written) this long
+/// after the first event of the next hour, so stragglers stamped just before the
+/// boundary by another task still land in it.
+const FINALIZE_GRACE_MS: i64 = 10_000;
+
+#[derive(Default)]
+struct SymbolCursor {
+    seq: u64,
+    dir: Option<PathBuf>,
+}
+
+/// Every event gets a per-symbol `seq` (from 1 per process) at a single point,
+/// so events from concurrent tasks have one well-defined order on disk. Each
+/// symbol has its own writer lock; symbols never wait on each other's I/O.
 pub struct DataStore {
     base: PathBuf,
+    format: StorageFormat,
+    parquet: Option<Mutex<ParquetSink>>,
+    raw_capture: RawCapture,
+    raw: Mutex<HashMap<String, RawWriter>>,
+    symbols: Mutex<HashMap<String, Arc<Mutex<SymbolCursor>>>>,
+    /// Left-behind partitions and when to finalize them.
+    pending: Mutex<Vec<(PathBuf, i64)>>,
+    finalizers: Mutex<Vec<JoinHandle<()>>>,
 }
 
 impl DataStore {
     pub fn new<P: AsRef<Path>>(base: P) -> Result<Self> {
+        Self::with_format(base, StorageFormat::Ndjson, 0)
+    }
+
+    pub fn with_format<P: AsRef<Path>>(base: P, format: StorageFormat, row_group_rows: usize) -> Result<Self> {
         let base = base.as_ref().to_path_buf();
         create_dir_all(&base)?;
-        Ok(Self { base })
+        let parquet = format.parquet().then(|| Mutex::new(ParquetSink::new(row_group_rows)));
+        Ok(Self {
+            base,
+            format,
+            parquet,
+            raw_capture: RawCapture::default(),
+            raw: Mutex::new(HashMap::new()),
+            symbols: Mutex::new(HashMap::new()),
+            pending: Mutex::new(Vec::new()),
+            finalizers: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn with_raw_capture(mut self, mode: RawCapture) -> Self {
+        self.raw_capture = mode;
+        self
+    }
+
+    /// Finishes open parquet and raw files (NDJSON needs nothing, every append
+    /// is a complete zstd frame) and writes the manifest of every partition
+    /// touched by this process.
+    pub fn close(&self) -> Result<()> {
+        if let Some(pq) = &self.parquet {
+            pq.lock().unwrap().close_all()?;
+        }
+        for (_, w) in self.raw.lock().unwrap().drain() {
+            w.finish()?;
+        }
+        let mut dirs: Vec<PathBuf> = self.pending.lock().unwrap().drain(..).map(|(d, _)| d).collect();
+        dirs.extend(self.symbols.lock().unwrap().values().filter_map(|c| c.lock().unwrap().dir.clone()));
+        for h in self.finalizers.lock().unwrap().drain(..) {
+            let _ = h.join();
+        }
+        for d in dirs {
+            write_manifest(&Partition::from_dir(&d)?)?;
+        }
+        Ok(())
+    }
+
+    fn finalize_due(&self, now_ms: i64) {
+        let due: Vec<PathBuf> = {
+            let mut pending = self.pending.lock().unwrap();
+            let (due, keep) = pending.drain(..).partition(|(_, at)| *at <= now_ms);
+            *pending = keep;
+            due.into_iter().map(|(d, _)| d).collect()
+        };
+        for dir in due {
+            if let Err(e) = self.finalize(dir.clone()) {
+                tracing::error!(partition = %dir.display(), error = %e, "finalize failed");
+            }
+        }
+    }
+
+    /// Closes whatever is still open in `dir`, then hashes and scans it on a
+    /// background thread; that can take a while for a busy hour.
+    fn finalize(&self, dir: PathBuf) -> Result<()> {
+        let mut raw = self.raw.lock().unwrap();
+        let stale: Vec<String> = raw.iter().filter(|(_, w)| w.path().parent() == Some(&dir)).map(|(k, _)| k.clone()).collect();
+        for k in stale {
+            if let Some(w) = raw.remove(&k) {
+                w.finish()?;
+            }
+        }
+        drop(raw);
+        if let Some(pq) = &self.parquet {
+            pq.lock().unwrap().close_dir(&dir)?;
+        }
+        let part = Partition::from_dir(&dir)?;
+        let h = std::thread::spawn(move || {
+            if let Err(e) = write_manifest(&part) {
+                tracing::error!(partition = %part.dir.display(), error = %e, "manifest failed");
+            }
+        });
+        let mut finalizers = self.finalizers.lock().unwrap();
+        finalizers.retain(|h| !h.is_finished());
+        finalizers.push(h);
+        Ok(())
     }
 
     fn part_dir(&self, symbol: &str, ts_ms: i64) -> PathBuf {
-        let t = OffsetDateTime::from_unix_timestamp_nanos((ts_ms as i128) * 1_000_000)
-            .unwrap_or_else(|_| OffsetDateTime::UNIX_EPOCH);
-        let date = t.date();
-        let hour = t.hour();
-        self.base
-            .join(format!("symbol={}", symbol))
-            .join(format!("date={:04}-{:02}-{:02}", date.year(), u8::from(date.month()), date.day()))
-            .join(format!("hour={:02}", hour))
+        partition_dir(&self.base, symbol, ts_ms)
     }
 
     fn events_path(&self, symbol: &str, ts_ms: i64) -> PathBuf {
@@ -43,36 +185,419 @@ impl DataStore {
         Ok(enc)
     }
 
-    pub fn append_event_json<T: Serialize>(&self, symbol: &str, ts_ms: i64, kind: &str, payload: &T) -> Result<()> {
-        let p = self.events_path(symbol, ts_ms);
-        let mut enc = Self::open_zstd(p)?;
-        let line = serde_json::json!({
-            "ts_ms": ts_ms,
-            "symbol": symbol,
-            "kind": kind,
-            "payload": payload
-        })
-        .to_string();
-        enc.write_all(line.as_bytes())?;
-        enc.write_all(b"\n")?;
-        enc.finish()?;
-        Ok(())
+    /// The single ordering point: assigns the per-symbol `seq` and writes while
+    /// holding that symbol's lock, so on-disk order within a file matches `seq`.
+    fn append(&self, symbol: &str, recv: Stamp, kind: &str, payload: RowPayload) -> Result<u64> {
+        let cursor = self.symbols.lock().unwrap().entry(symbol.to_string()).or_default().clone();
+        let mut cur = cursor.lock().unwrap();
+        cur.seq += 1;
+        let seq = cur.seq;
+        let dir = self.part_dir(symbol, recv.ms());
+        if cur.dir.as_ref() != Some(&dir) {
+            invalidate_manifest(&dir)?;
+            if let Some(old) = cur.dir.replace(dir.clone()) {
+                self.pending.lock().unwrap().push((old, recv.ms() + FINALIZE_GRACE_MS));
+            }
+        }
+
+        if self.format.ndjson() {
+            let mut line = serde_json::json!({
+                "ts_ms": recv.ms(),
+                "ts_ns": recv.ts_ns,
+                "recv_seq": recv.recv_seq,
+                "seq": seq,
+                "symbol": symbol,
+                "kind": kind,
+            });
+            match &payload {
+                RowPayload::Json(v) => line["payload"] = v.clone(),
+                RowPayload::Raw(raw) => {
+                    line["payload_b64"] = base64::engine::general_purpose::STANDARD.encode(raw).into()
+                }
+            }
+            let mut enc = Self::open_zstd(self.events_path(symbol, recv.ms()))?;
+            enc.write_all(line.to_string().as_bytes())?;
+            enc.write_all(b"\n")?;
+            enc.finish()?;
+        }
+        if let Some(pq) = &self.parquet {
+            let row = Row {
+                ts_ms: recv.ms(),
+                ts_ns: Some(recv.ts_ns),
+                recv_seq: Some(recv.recv_seq),
+                seq: Some(seq),
+                symbol: symbol.to_string(),
+                payload,
+            };
+            pq.lock().unwrap().append(&dir, kind, row)?;
+        }
+        drop(cur);
+        self.finalize_due(recv.ms());
+        Ok(seq)
+    }
+
+    /// `recv` is when the event arrived, not when it is written; it decides the
+    /// partition. Returns the per-symbol ingestion seq.
+    pub fn append_event_json<T: Serialize>(&self, symbol: &str, recv: Stamp, kind: &str, payload: &T) -> Result<u64> {
+        self.append(symbol, recv, kind, RowPayload::Json(serde_json::to_value(payload)?))
+    }
+
+    /// Records one WS frame according to the configured [`RawCapture`].
+    pub fn capture_raw(&self, symbol: &str, recv: Stamp, channel: RawChannel, raw: &[u8]) -> Result<()> {
+        let ts_ms = recv.ms();
+        match self.raw_capture {
+            RawCapture::Off => Ok(()),
+            RawCapture::B64 => {
+                let kind = match channel {
+                    RawChannel::AggreDepth => "depth_pb_raw",
+                    _ => "pb_raw",
+                };
+                self.append_event_raw_b64(symbol, recv, kind, raw).map(|_| ())
+            }
+            RawCapture::Pb => {
+                let path = self.part_dir(symbol, ts_ms).join(RAW_FILE_NAME);
+                let mut map = self.raw.lock().unwrap();
+                if map.get(symbol).is_some_and(|w| w.path() != path) {
+                    if let Some(old) = map.remove(symbol) {
+                        old.finish()?;
+                    }
+                }
+                let w = match map.get_mut(symbol) {
+                    Some(w) => w,
+                    None => {
+                        if let Some(dir) = path.parent() {
+                            invalidate_manifest(dir)?;
+                        }
+                        map.entry(symbol.to_string()).or_insert(RawWriter::open(&path)?)
+                    }
+                };
+                w.write(recv.ts_ns, channel, raw)
+            }
+        }
+    }
+
+    /// Ends the open `raw.pb.zst` frames, so idle symbols' frames become
+    /// readable too; call every [`crate::rawlog::FLUSH_EVERY`].
+    pub fn flush_raw(&self) {
+        for w in self.raw.lock().unwrap().values_mut() {
+            if let Err(e) = w.flush() {
+                tracing::error!(path = %w.path().display(), error = %e, "raw flush failed");
+            }
+        }
+    }
+
+    pub fn append_event_raw_b64(&self, symbol: &str, recv: Stamp, kind: &str, raw: &[u8]) -> Result<u64> {
+        self.append(symbol, recv, kind, RowPayload::Raw(raw.to_vec()))
+    }
+}
+
+/// `base/symbol=X/date=Y/hour=Z` for the UTC hour holding `ts_ms`.
+pub fn partition_dir(base: &Path, symbol: &str, ts_ms: i64) -> PathBuf {
+    let t = OffsetDateTime::from_unix_timestamp_nanos((ts_ms as i128) * 1_000_000)
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH);
+    let date = t.date();
+    base.join(format!("symbol={}", symbol))
+        .join(format!("date={:04}-{:02}-{:02}", date.year(), u8::from(date.month()), date.day()))
+        .join(format!("hour={:02}", t.hour()))
+}
+
+/// One `symbol=/date=/hour=` directory under a data root.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Partition {
+    pub symbol: String,
+    pub date: String,
+    pub hour: u8,
+    pub dir: PathBuf,
+}
+
+impl Partition {
+    /// Parses a `.../symbol=X/date=Y/hour=Z` path.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut parts = dir.iter().rev().filter_map(|c| c.to_str());
+        let kv = |c: Option<&str>, key: &str| {
+            c.and_then(|c| c.strip_prefix(key)).and_then(|c| c.strip_prefix('=')).map(str::to_string)
+        };
+        let (Some(hour), Some(date), Some(symbol)) =
+            (kv(parts.next(), "hour"), kv(parts.next(), "date"), kv(parts.next(), "symbol"))
+        else {
+            return Err(anyhow!("{} is not a symbol=/date=/hour= partition", dir.display()));
+        };
+        Ok(Self { symbol, date, hour: hour.parse()?, dir: dir.to_path_buf() })
+    }
+
+    pub fn events_path(&self) -> PathBuf {
+        self.dir.join("events.ndjson.zst")
+    }
+
+    pub fn raw_path(&self) -> PathBuf {
+        self.dir.join(RAW_FILE_NAME)
+    }
+
+    /// Path relative to the data root, for mirroring the layout elsewhere.
+    pub fn rel_dir(&self) -> PathBuf {
+        PathBuf::from(format!("symbol={}", self.symbol))
+            .join(format!("date={}", self.date))
+            .join(format!("hour={:02}", self.hour))
     }
 
-    pub fn append_event_raw_b64(&self, symbol: &str, ts_ms: i64, kind: &str, raw: &[u8]) -> Result<()> {
-        let p = self.events_path(symbol, ts_ms);
-        let mut enc = Self::open_zstd(p)?;
-        let b64 = base64::engine::general_purpose::STANDARD.encode(raw);
-        let line = serde_json::json!({
-            "ts_ms": ts_ms,
-            "symbol": symbol,
-            "kind": kind,
-            "payload_b64": b64
+    pub fn start_ms(&self) -> Result<i64> {
+        let mut it = self.date.split('-').map(|s| s.parse::<i32>());
+        let (Some(Ok(y)), Some(Ok(m)), Some(Ok(d))) = (it.next(), it.next(), it.next()) else {
+            return Err(anyhow!("bad partition date {}", self.date));
+        };
+        let date = time::Date::from_calendar_date(y, time::Month::try_from(m as u8)?, d as u8)?;
+        let t = date.with_hms(self.hour, 0, 0)?.assume_utc();
+        Ok((t.unix_timestamp_nanos() / 1_000_000) as i64)
+    }
+
+    pub fn end_ms(&self) -> Result<i64> {
+        Ok(self.start_ms()? + 3_600_000)
+    }
+}
+
+fn hive_dirs(dir: &Path, key: &str) -> Result<Vec<(String, PathBuf)>> {
+    let mut out = Vec::new();
+    if !dir.is_dir() {
+        return Ok(out);
+    }
+    for e in std::fs::read_dir(dir)? {
+        let p = e?.path();
+        if !p.is_dir() {
+            continue;
+        }
+        let Some(name) = p.file_name().and_then(|n| n.to_str()) else { continue };
+        if let Some(v) = name.strip_prefix(key).and_then(|s| s.strip_prefix('=')) {
+            out.push((v.to_string(), p.clone()));
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+/// All hour partitions under `root`, sorted by symbol, date, hour.
+pub fn list_partitions<P: AsRef<Path>>(root: P) -> Result<Vec<Partition>> {
+    let mut out = Vec::new();
+    for (symbol, sdir) in hive_dirs(root.as_ref(), "symbol")? {
+        for (date, ddir) in hive_dirs(&sdir, "date")? {
+            for (hour, hdir) in hive_dirs(&ddir, "hour")? {
+                let Ok(hour) = hour.parse::<u8>() else { continue };
+                out.push(Partition { symbol: symbol.clone(), date: date.clone(), hour, dir: hdir });
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// One line of `events.ndjson.zst`, as written by [`DataStore`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredEvent {
+    pub ts_ms: i64,
+    /// Absent in files written before nanosecond stamps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ts_ns: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recv_seq: Option<u64>,
+    /// Per-symbol ingestion seq, see [`DataStore`]; restarts at 1 with each process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    pub symbol: String,
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_b64: Option<String>,
+    /// Frame bytes when the event comes from `raw.pb.zst`, see [`raw_frame_events`].
+    #[serde(skip)]
+    pub raw_bytes: Option<Vec<u8>>,
+}
+
+impl StoredEvent {
+    pub fn raw(&self) -> Option<Result<Vec<u8>>> {
+        if let Some(b) = &self.raw_bytes {
+            return Some(Ok(b.clone()));
+        }
+        self.payload_b64
+            .as_ref()
+            .map(|b| base64::engine::general_purpose::STANDARD.decode(b).map_err(Into::into))
+    }
+
+    pub fn payload_as<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        let v = self.payload.clone().ok_or_else(|| anyhow!("{} event without payload", self.kind))?;
+        Ok(serde_json::from_value(v)?)
+    }
+}
+
+/// Streams events from one `events.ndjson.zst` (any number of concatenated zstd frames).
+/// A read error (truncated or corrupt frame) is yielded once and ends the stream;
+/// lines that do not parse are yielded as errors and skipped.
+pub struct EventReader {
+    lines: std::io::Lines<std::io::BufReader<zstd::stream::read::Decoder<'static, std::io::BufReader<std::fs::File>>>>,
+    done: bool,
+}
+
+impl Iterator for EventReader {
+    type Item = Result<StoredEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            let line = match self.lines.next()? {
+                Ok(l) => l,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(serde_json::from_str(&line).map_err(Into::into));
+        }
+    }
+}
+
+pub fn read_events<P: AsRef<Path>>(path: P) -> Result<EventReader> {
+    use std::io::BufRead as _;
+    let file = std::fs::File::open(path)?;
+    let dec = zstd::stream::read::Decoder::new(file)?;
+    Ok(EventReader { lines: std::io::BufReader::new(dec).lines(), done: false })
+}
+
+/// Events later than this behind the slowest source are still placed correctly.
+pub const REORDER_WINDOW_NS: i64 = 1_000_000_000;
+
+struct Keyed {
+    key: (i64, Option<u64>, Option<u64>, usize, u64),
+    ev: StoredEvent,
+}
+
+impl PartialEq for Keyed {
+    fn eq(&self, other: &Self) -> bool { self.key == other.key }
+}
+impl Eq for Keyed {}
+impl PartialOrd for Keyed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+impl Ord for Keyed {
+    fn cmp(&self, other: &Self) -> Ordering { self.key.cmp(&other.key) }
+}
+
+/// Deterministic merge of several event streams by (ts, seq, recv_seq, source,
+/// position). Sources need only be roughly ordered: an event is emitted once
+/// every live source has read past its ts plus `window_ns`. Errors from a
+/// source are passed through in place.
+pub struct MergedEvents<I> {
+    sources: Vec<Option<I>>,
+    watermark: Vec<i64>,
+    read: Vec<u64>,
+    heap: BinaryHeap<Reverse<Keyed>>,
+    errors: VecDeque<anyhow::Error>,
+    window_ns: i64,
+}
+
+impl<I: Iterator<Item = Result<StoredEvent>>> MergedEvents<I> {
+    pub fn new(sources: Vec<I>, window_ns: i64) -> Self {
+        let n = sources.len();
+        Self {
+            sources: sources.into_iter().map(Some).collect(),
+            watermark: vec![i64::MIN; n],
+            read: vec![0; n],
+            heap: BinaryHeap::new(),
+            errors: VecDeque::new(),
+            window_ns,
+        }
+    }
+
+    /// Live source that has read the least, if any.
+    fn laggard(&self) -> Option<(usize, i64)> {
+        (0..self.sources.len())
+            .filter(|&i| self.sources[i].is_some())
+            .map(|i| (i, self.watermark[i]))
+            .min_by_key(|&(_, wm)| wm)
+    }
+
+    fn pull(&mut self, i: usize) {
+        let Some(src) = self.sources[i].as_mut() else { return };
+        match src.next() {
+            Some(Ok(ev)) => {
+                let ts = ev.ts_ns.unwrap_or(ev.ts_ms.saturating_mul(1_000_000));
+                self.watermark[i] = self.watermark[i].max(ts);
+                self.read[i] += 1;
+                self.heap.push(Reverse(Keyed { key: (ts, ev.seq, ev.recv_seq, i, self.read[i]), ev }));
+            }
+            Some(Err(e)) => self.errors.push_back(e),
+            None => self.sources[i] = None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<StoredEvent>>> Iterator for MergedEvents<I> {
+    type Item = Result<StoredEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(e) = self.errors.pop_front() {
+                return Some(Err(e));
+            }
+            let ready = match (self.heap.peek(), self.laggard()) {
+                (None, None) => return None,
+                (Some(_), None) => true,
+                (Some(Reverse(top)), Some((_, wm))) => top.key.0.saturating_add(self.window_ns) <= wm,
+                (None, Some(_)) => false,
+            };
+            if ready {
+                return self.heap.pop().map(|Reverse(k)| Ok(k.ev));
+            }
+            if let Some((i, _)) = self.laggard() {
+                self.pull(i);
+            }
+        }
+    }
+}
+
+/// Frames of a `raw.pb.zst` as events of the kind `capture_raw` would have
+/// given them in b64 mode, so they can be merged with `events.ndjson.zst`.
+/// They carry no `seq` and sort before an event with the same stamp.
+pub fn raw_frame_events<P: AsRef<Path>>(path: P, symbol: &str) -> Result<impl Iterator<Item = Result<StoredEvent>>> {
+    let symbol = symbol.to_string();
+    Ok(crate::rawlog::read_raw_frames(path)?.map(move |f| {
+        let f = f?;
+        Ok(StoredEvent {
+            ts_ms: f.recv_ts_ns.div_euclid(1_000_000),
+            ts_ns: Some(f.recv_ts_ns),
+            recv_seq: None,
+            seq: None,
+            symbol: symbol.clone(),
+            kind: match f.channel {
+                RawChannel::AggreDepth => "depth_pb_raw",
+                _ => "pb_raw",
+            }
+            .to_string(),
+            payload: None,
+            payload_b64: None,
+            raw_bytes: Some(f.data),
         })
-        .to_string();
-        enc.write_all(line.as_bytes())?;
-        enc.write_all(b"\n")?;
-        enc.finish()?;
-        Ok(())
+    }))
+}
+
+/// Everything recorded in one partition, events and raw frames, in (ts, seq) order.
+pub fn read_partition(part: &Partition) -> Result<MergedEvents<Box<dyn Iterator<Item = Result<StoredEvent>>>>> {
+    let mut sources: Vec<Box<dyn Iterator<Item = Result<StoredEvent>>>> = Vec::new();
+    if part.events_path().exists() {
+        sources.push(Box::new(read_events(part.events_path())?));
+    }
+    if part.raw_path().exists() {
+        sources.push(Box::new(raw_frame_events(part.raw_path(), &part.symbol)?));
     }
-}
\ No newline at end of file
+    Ok(MergedEvents::new(sources, REORDER_WINDOW_NS))
+}
+
+/// Merges several `events.ndjson.zst` files (e.g. the same hour of different
+/// symbols, or files from overlapping recorder runs).
+pub fn read_events_merged<P: AsRef<Path>>(paths: &[P]) -> Result<MergedEvents<EventReader>> {
+    let readers = paths.iter().map(read_events).collect::<Result<Vec<_>>>()?;
+    Ok(MergedEvents::new(readers, REORDER_WINDOW_NS))
+}
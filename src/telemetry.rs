@@ -7,6 +7,13 @@ pub struct Telemetry {
     rest_rtt: Mutex<Histogram<u64>>,
     pub gap_counter: Mutex<u64>,
     pub resync_counter: Mutex<u64>,
+    pub crossed_counter: Mutex<u64>,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Telemetry {
@@ -16,6 +23,7 @@ impl Telemetry {
             rest_rtt: Mutex::new(Histogram::new_with_max(60_000, 3).unwrap()),
             gap_counter: Mutex::new(0),
             resync_counter: Mutex::new(0),
+            crossed_counter: Mutex::new(0),
         }
     }
 
@@ -28,7 +36,7 @@ impl Telemetry {
         let _ = h.record(v_ms);
     }
 
-    pub async fn snapshot(&self) -> ((f64,f64,f64,u64), (f64,f64,f64,u64), u64, u64) {
+    pub async fn snapshot(&self) -> ((f64,f64,f64,u64), (f64,f64,f64,u64), u64, u64, u64) {
         let w = self.ws_rtt.lock().await;
         let r = self.rest_rtt.lock().await;
         let ws = (w.value_at_quantile(0.50) as f64,
@@ -41,6 +49,7 @@ impl Telemetry {
                   r.len() as u64);
         let gaps = *self.gap_counter.lock().await;
         let resyncs = *self.resync_counter.lock().await;
-        (ws, rr, gaps, resyncs)
+        let crossed = *self.crossed_counter.lock().await;
+        (ws, rr, gaps, resyncs, crossed)
     }
 }
@@ -1,5 +1,10 @@
 //types.rs
+use ordered_float::OrderedFloat;
 use serde::{Serialize, Deserialize};
+use std::{cmp::Reverse, collections::BTreeMap};
+
+pub type BookSide = BTreeMap<OrderedFloat<f64>, f64>;
+pub type RevSide = BTreeMap<Reverse<OrderedFloat<f64>>, f64>;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DepthSnapshot {
@@ -20,6 +25,16 @@ pub struct DepthDelta {
     pub asks: Vec<[f64;2]>,
 }
 
+/// Levels touched by one applied WS diff; qty 0.0 means the level was removed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedDelta {
+    pub from_version: u64,
+    pub to_version: u64,
+    pub ts_exch_ms: Option<i64>,
+    pub bids: Vec<[f64;2]>,
+    pub asks: Vec<[f64;2]>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TradeEvent {
     pub symbol: String,
@@ -47,3 +62,157 @@ pub struct TelemetrySample {
     pub p99_ms: f64,
     pub count: u64,
 }
+
+/// Result of comparing the live book with a REST snapshot rolled forward to the same version.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BookValidation {
+    pub symbol: String,
+    pub ts_recv_ms: i64,
+    pub rest_version: u64,
+    pub local_version: u64,
+    /// Buffered deltas applied to the REST snapshot to reach `local_version`.
+    pub replayed_deltas: usize,
+    /// False when the delta buffer did not cover the REST version; nothing was compared.
+    pub aligned: bool,
+    pub compared_levels: usize,
+    pub mismatched_levels: usize,
+    pub missing_local: usize,
+    pub missing_rest: usize,
+    pub qty_diff: usize,
+    pub mismatch_ratio: f64,
+    pub resync: bool,
+}
+
+/// One crossed-book episode (`best bid >= best ask`), recorded when it ends.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossedBook {
+    pub symbol: String,
+    pub ts_recv_ms: i64,
+    /// Book version when the cross was first seen.
+    pub version: u64,
+    pub best_bid: f64,
+    pub best_ask: f64,
+    /// Bids at or above the best ask and asks at or below the best bid, when first seen.
+    pub bids: Vec<[f64; 2]>,
+    pub asks: Vec<[f64; 2]>,
+    /// `resync`, `prune` or `recovered` (uncrossed on its own while tolerated).
+    pub action: String,
+    pub pruned_levels: usize,
+    /// Prices `prune` removed; published as qty-0 levels of the same update,
+    /// and removed again when the stored data is replayed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pruned_asks: Vec<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pruned_bids: Vec<f64>,
+    /// Updates applied while crossed, including the first.
+    pub updates: u32,
+    pub duration_ms: i64,
+}
+
+/// Decimal places of a symbol's prices (tick) and quantities (lot).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Precision {
+    pub price_dp: u32,
+    pub qty_dp: u32,
+}
+
+impl Precision {
+    pub fn tick_size(&self) -> f64 {
+        10f64.powi(-(self.price_dp as i32))
+    }
+
+    pub fn lot_size(&self) -> f64 {
+        10f64.powi(-(self.qty_dp as i32))
+    }
+
+    pub fn price_on_grid(&self, p: f64) -> bool {
+        on_grid(p, self.price_dp)
+    }
+
+    pub fn qty_on_grid(&self, q: f64) -> bool {
+        on_grid(q, self.qty_dp)
+    }
+
+    pub fn fmt_price(&self, p: f64) -> String {
+        format!("{:.*}", self.price_dp as usize, p)
+    }
+
+    pub fn fmt_qty(&self, q: f64) -> String {
+        format!("{:.*}", self.qty_dp as usize, q)
+    }
+}
+
+fn on_grid(x: f64, dp: u32) -> bool {
+    let scaled = x * 10f64.powi(dp as i32);
+    (scaled - scaled.round()).abs() < 1e-6
+}
+
+/// `status` of an `/api/v3/exchangeInfo` symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolStatus {
+    Online,
+    Paused,
+    Offline,
+    Unknown,
+}
+
+impl SymbolStatus {
+    pub fn from_code(code: &str) -> Self {
+        match code {
+            "1" => Self::Online,
+            "2" => Self::Paused,
+            "3" => Self::Offline,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Symbol metadata from `/api/v3/exchangeInfo`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SymbolInfo {
+    pub symbol: String,
+    pub status: SymbolStatus,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub spot_trading: bool,
+    pub precision: Precision,
+}
+
+impl SymbolInfo {
+    /// Online and open for spot trading.
+    pub fn tradable(&self) -> bool {
+        self.status == SymbolStatus::Online && self.spot_trading
+    }
+}
+
+/// Stored as `exchange_info` in the symbol's partition at startup and on every refresh.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExchangeInfoEvent {
+    pub ts_recv_ms: i64,
+    pub server_time_ms: Option<i64>,
+    #[serde(flatten)]
+    pub info: SymbolInfo,
+}
+
+/// A difference between two `/api/v3/exchangeInfo` fetches, stored as
+/// `symbol_listed`, `symbol_status_changed` or `symbol_delisted`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymbolChange {
+    pub symbol: String,
+    pub ts_recv_ms: i64,
+    /// `None` for a listing.
+    pub old: Option<SymbolInfo>,
+    /// `None` for a delisting.
+    pub new: Option<SymbolInfo>,
+}
+
+impl SymbolChange {
+    pub fn kind(&self) -> &'static str {
+        match (&self.old, &self.new) {
+            (None, _) => "symbol_listed",
+            (_, None) => "symbol_delisted",
+            _ => "symbol_status_changed",
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// validate.rs
+//
+// Live book vs. `/api/v3/depth`. The REST snapshot is nearly always older than
+// the live book by the time it arrives, so it is rolled forward with the
+// recently applied WS deltas until it reaches the live version, then both are
+// compared level by level within the price range the snapshot covers (the
+// live book is deeper, the snapshot is cut at `limit`). Deltas chain the way
+// the book applies them: a version jump of up to `MAX_VERSION_GAP` is bridged.
+use ordered_float::OrderedFloat;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+
+use crate::book::{load_levels, MAX_VERSION_GAP};
+use crate::types::{AppliedDelta, BookSide, BookValidation, DepthSnapshot, RevSide};
+
+/// The last `cap` applied deltas, oldest first.
+pub struct DeltaBuffer {
+    cap: usize,
+    q: VecDeque<AppliedDelta>,
+    /// `to_version` of the newest delta dropped for space; snapshots older
+    /// than that can no longer be rolled forward.
+    evicted_to: Option<u64>,
+}
+
+impl DeltaBuffer {
+    pub fn new(cap: usize) -> Self {
+        Self { cap, q: VecDeque::with_capacity(cap.min(65536)), evicted_to: None }
+    }
+
+    pub fn push(&mut self, d: AppliedDelta) {
+        if self.q.len() >= self.cap {
+            if let Some(old) = self.q.pop_front() {
+                self.evicted_to = Some(old.to_version);
+            }
+        }
+        self.q.push_back(d);
+    }
+
+    /// After a resync the buffered deltas no longer chain onto the book.
+    pub fn clear(&mut self) {
+        self.q.clear();
+        self.evicted_to = None;
+    }
+}
+
+fn qty_eq(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-12 + 1e-9 * a.abs().max(b.abs())
+}
+
+/// Compares `(asks, bids)` at `local_version` with a REST snapshot. `None`
+/// while the snapshot is ahead of the live book; retry after more deltas.
+pub fn validate(
+    rest: &DepthSnapshot,
+    buffer: &DeltaBuffer,
+    asks: &BookSide,
+    bids: &RevSide,
+    local_version: u64,
+) -> Option<BookValidation> {
+    let rest_version = rest.last_update_id;
+    if rest_version > local_version {
+        return None;
+    }
+    let (rest_asks, rest_bids) = (&rest.asks, &rest.bids);
+    let mut v = BookValidation {
+        symbol: rest.symbol.clone(),
+        ts_recv_ms: rest.ts_recv_ms,
+        rest_version,
+        local_version,
+        ..Default::default()
+    };
+
+    let (mut ra, mut rb) = (BookSide::new(), RevSide::new());
+    load_levels(&mut ra, &mut rb, rest_asks, rest_bids);
+    if buffer.evicted_to.is_some_and(|to| to > rest_version) {
+        return Some(v);
+    }
+    let mut ver = rest_version;
+    for d in buffer.q.iter().filter(|d| d.to_version > rest_version) {
+        if d.from_version > ver + 1 + MAX_VERSION_GAP {
+            return Some(v);
+        }
+        for [p, q] in &d.asks {
+            if *q == 0.0 { ra.remove(&OrderedFloat(*p)); } else { ra.insert(OrderedFloat(*p), *q); }
+        }
+        for [p, q] in &d.bids {
+            if *q == 0.0 { rb.remove(&Reverse(OrderedFloat(*p))); } else { rb.insert(Reverse(OrderedFloat(*p)), *q); }
+        }
+        ver = d.to_version;
+        v.replayed_deltas += 1;
+    }
+    if ver != local_version {
+        return Some(v);
+    }
+    v.aligned = true;
+
+    // compare inside the snapshot's price range only
+    let ask_max = rest_asks.iter().map(|l| l[0]).fold(f64::NEG_INFINITY, f64::max);
+    let bid_min = rest_bids.iter().map(|l| l[0]).fold(f64::INFINITY, f64::min);
+    let mut tally = |ours: Option<f64>, theirs: Option<f64>| {
+        v.compared_levels += 1;
+        match (ours, theirs) {
+            (Some(a), Some(b)) if qty_eq(a, b) => return,
+            (Some(_), Some(_)) => v.qty_diff += 1,
+            (None, _) => v.missing_local += 1,
+            (_, None) => v.missing_rest += 1,
+        }
+        v.mismatched_levels += 1;
+    };
+    let ask_px: std::collections::BTreeSet<_> =
+        asks.keys().chain(ra.keys()).filter(|p| p.0 <= ask_max).copied().collect();
+    for p in ask_px {
+        tally(asks.get(&p).copied(), ra.get(&p).copied());
+    }
+    let bid_px: std::collections::BTreeSet<_> =
+        bids.keys().chain(rb.keys()).filter(|p| (p.0).0 >= bid_min).copied().collect();
+    for p in bid_px {
+        tally(bids.get(&p).copied(), rb.get(&p).copied());
+    }
+    if v.compared_levels > 0 {
+        v.mismatch_ratio = v.mismatched_levels as f64 / v.compared_levels as f64;
+    }
+    Some(v)
+}
@@ -0,0 +1,118 @@
+// audit.rs
+//
+// `audit_symbol` over two stored hours with a crossed book, a version gap
+// with its resync, a stale period and trades in and outside the spread.
+use std::path::PathBuf;
+
+use mexc_spot_public::audit::{audit_symbol, AuditOptions};
+use mexc_spot_public::clock::Stamp;
+use mexc_spot_public::rawlog::RawChannel;
+use mexc_spot_public::store::DataStore;
+use mexc_spot_public::types::{DepthDelta, DepthSnapshot, TradeEvent};
+
+mod common;
+use common::{frame, SYMBOL};
+
+/// 2024-05-01T10:00:00Z
+const T0: i64 = 1_714_557_600_000;
+const HOUR: i64 = 3_600_000;
+
+struct Writer {
+    store: DataStore,
+    seq: u64,
+}
+
+impl Writer {
+    fn stamp(&mut self, ms: i64) -> Stamp {
+        self.seq += 1;
+        Stamp { ts_ns: ms * 1_000_000, recv_seq: self.seq }
+    }
+
+    fn frame(&mut self, ms: i64, v: u64, asks: &[[f64; 2]], bids: &[[f64; 2]]) {
+        let s = self.stamp(ms);
+        self.store.capture_raw(SYMBOL, s, RawChannel::AggreDepth, &frame(v, v, asks, bids)).unwrap();
+    }
+
+    fn snapshot(&mut self, ms: i64, v: u64, asks: Vec<[f64; 2]>, bids: Vec<[f64; 2]>) {
+        let snap = DepthSnapshot { symbol: SYMBOL.into(), ts_recv_ms: ms, last_update_id: v, bids, asks };
+        let s = self.stamp(ms);
+        self.store.append_event_json(SYMBOL, s, "depth_snapshot", &snap).unwrap();
+    }
+
+    fn trade(&mut self, ms: i64, price: f64) {
+        let t = TradeEvent { symbol: SYMBOL.into(), ts_recv_ms: ms, id: None, price, qty: 1.0, side: None, ts_exch_ms: None };
+        let s = self.stamp(ms);
+        self.store.append_event_json(SYMBOL, s, "trade", &t).unwrap();
+    }
+}
+
+fn write_data() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mexc-audit-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut w = Writer { store: DataStore::new(&dir).unwrap(), seq: 0 };
+
+    // hour 10
+    w.snapshot(T0, 100, vec![[100.5, 1.0], [101.0, 2.0]], vec![[100.0, 1.0], [99.5, 2.0]]);
+    w.frame(T0 + 1000, 101, &[[100.5, 0.5]], &[]);
+    w.trade(T0 + 1500, 100.2);
+    // crossed for half a second
+    w.frame(T0 + 2000, 102, &[], &[[100.8, 1.0]]);
+    w.frame(T0 + 2500, 103, &[], &[[100.8, 0.0]]);
+    let delta = DepthDelta {
+        symbol: SYMBOL.into(),
+        ts_recv_ms: T0 + 3000,
+        from_version: 103,
+        to_version: 103,
+        bids: vec![[100.0, 1.0], [99.5, 2.0]],
+        asks: vec![[100.5, 0.5], [101.0, 2.0]],
+    };
+    let s = w.stamp(T0 + 3000);
+    w.store.append_event_json(SYMBOL, s, "depth_delta", &delta).unwrap();
+    // a gap, unchecked trade while broken, and the resync two seconds later
+    w.frame(T0 + 4000, 5000, &[[100.6, 1.0]], &[]);
+    w.trade(T0 + 4100, 200.0);
+    w.snapshot(T0 + 6000, 5000, vec![[100.0, 1.0]], vec![[99.0, 1.0]]);
+
+    // hour 11: the first frame ends a long stale period
+    w.frame(T0 + HOUR + 1000, 5001, &[], &[[99.5, 1.0]]);
+    w.trade(T0 + HOUR + 1500, 90.0);
+    // same version, different top
+    w.snapshot(T0 + HOUR + 2000, 5001, vec![[100.0, 2.0]], vec![[99.5, 1.0], [99.0, 1.0]]);
+    w.store.close().unwrap();
+    dir
+}
+
+#[test]
+fn hourly_report_counts_gaps_crossings_and_resyncs() {
+    let dir = write_data();
+    let report = audit_symbol(&dir, SYMBOL, "2024-05-01", "2024-05-01", &AuditOptions::default()).unwrap();
+    assert_eq!(report.hours.len(), 2);
+
+    let h10 = &report.hours[0];
+    assert_eq!(h10.partition, format!("symbol={SYMBOL}/date=2024-05-01/hour=10"));
+    assert_eq!((h10.depth_frames, h10.applied_deltas, h10.snapshots, h10.trades), (4, 3, 2, 2));
+    assert_eq!((h10.crossed, h10.crossed_ms_total), (1, 500));
+    assert_eq!((h10.gaps, h10.resyncs, h10.resync_ms_total, h10.resync_ms_max), (1, 1, 2000, 2000));
+    assert_eq!((h10.delta_checks, h10.delta_mismatches), (1, 0));
+    assert_eq!((h10.snapshot_checks, h10.snapshot_mismatches), (0, 0));
+    assert_eq!((h10.trades_checked, h10.trades_outside_spread), (1, 0));
+    assert_eq!((h10.stale_periods, h10.bad_qty, h10.bad_price, h10.off_grid, h10.read_errors), (0, 0, 0, 0, 0));
+
+    let h11 = &report.hours[1];
+    assert_eq!(h11.partition, format!("symbol={SYMBOL}/date=2024-05-01/hour=11"));
+    assert_eq!((h11.depth_frames, h11.applied_deltas, h11.snapshots, h11.trades), (1, 1, 1, 1));
+    assert_eq!((h11.stale_periods, h11.stale_ms_max), (1, HOUR - 3000));
+    assert_eq!((h11.snapshot_checks, h11.snapshot_mismatches), (1, 1));
+    assert_eq!((h11.resyncs, h11.resync_ms_total), (1, 0));
+    assert_eq!((h11.trades_checked, h11.trades_outside_spread), (1, 1));
+    assert_eq!((h11.gaps, h11.crossed), (0, 0));
+
+    let t = &report.total;
+    assert_eq!((t.depth_frames, t.gaps, t.resyncs, t.crossed, t.stale_periods), (5, 1, 2, 1, 1));
+    assert!(h10.summary_line().starts_with(&format!("{}: 4 frames, 1 gaps, 1 resyncs (2000 ms), 1 crossed (500 ms)", h10.partition)));
+
+    // a range without data is empty, not an error
+    let none = audit_symbol(&dir, SYMBOL, "2024-05-02", "2024-05-03", &AuditOptions::default()).unwrap();
+    assert!(none.hours.is_empty());
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,270 @@
+// book_props.rs
+//
+// Property tests for `book::handle_diff_update`: version arithmetic, delta
+// merging and hostile wire input. The same inputs are fuzzed without bounds by
+// the cargo-fuzz targets in fuzz/.
+use bytes::Bytes;
+use prost::Message;
+use proptest::prelude::*;
+
+use mexc_spot_public::book::{handle_diff_update, load_levels};
+use mexc_spot_public::error::{parse_num, IngestError};
+use mexc_spot_public::mexc_pb::{push_data_v3_api_wrapper::Body, PublicAggreDepthV3ApiItem, PublicAggreDepthsV3Api, PushDataV3ApiWrapper};
+use mexc_spot_public::types::{BookSide, RevSide};
+
+const CHANNEL: &str = "spot@public.aggre.depth.v3.api.pb@10ms@BTCUSDT";
+
+#[derive(Debug, Clone)]
+struct WireDelta {
+    from: String,
+    to: String,
+    asks: Vec<(String, String)>,
+    bids: Vec<(String, String)>,
+}
+
+impl WireDelta {
+    fn encode(&self) -> Bytes {
+        let items = |l: &[(String, String)]| {
+            l.iter().map(|(p, q)| PublicAggreDepthV3ApiItem { price: p.clone(), quantity: q.clone() }).collect()
+        };
+        PushDataV3ApiWrapper {
+            channel: CHANNEL.to_string(),
+            symbol: Some("BTCUSDT".to_string()),
+            symbol_id: None,
+            create_time: Some(1),
+            send_time: Some(1),
+            body: Some(Body::PublicAggreDepths(PublicAggreDepthsV3Api {
+                asks: items(&self.asks),
+                bids: items(&self.bids),
+                event_type: CHANNEL.to_string(),
+                from_version: self.from.clone(),
+                to_version: self.to.clone(),
+            })),
+        }
+        .encode_to_vec()
+        .into()
+    }
+}
+
+/// Valid levels on a small price grid, so updates hit the same prices often.
+#[derive(Debug, Clone)]
+struct Delta {
+    span: u64,
+    asks: Vec<(u32, u32)>,
+    bids: Vec<(u32, u32)>,
+}
+
+fn price(tick: u32) -> String {
+    format!("{}.{:02}", 100 + tick / 100, tick % 100)
+}
+
+fn qty(lots: u32) -> String {
+    format!("{}.{}", lots / 10, lots % 10)
+}
+
+/// Deltas as the exchange would send them after version `start`.
+fn to_wire(start: u64, deltas: &[Delta]) -> Vec<WireDelta> {
+    let mut v = start;
+    deltas
+        .iter()
+        .map(|d| {
+            let from = v + 1;
+            v += d.span;
+            let side = |l: &[(u32, u32)]| l.iter().map(|&(t, q)| (price(t), qty(q))).collect();
+            WireDelta { from: from.to_string(), to: v.to_string(), asks: side(&d.asks), bids: side(&d.bids) }
+        })
+        .collect()
+}
+
+/// One delta covering `deltas`, keeping only the last update per price.
+fn merge(deltas: &[WireDelta]) -> WireDelta {
+    let last_wins = |sides: Vec<&(String, String)>| {
+        let mut out: Vec<(String, String)> = Vec::new();
+        for (p, q) in sides {
+            out.retain(|(op, _)| op != p);
+            out.push((p.clone(), q.clone()));
+        }
+        out
+    };
+    WireDelta {
+        from: deltas[0].from.clone(),
+        to: deltas[deltas.len() - 1].to.clone(),
+        asks: last_wins(deltas.iter().flat_map(|d| &d.asks).collect()),
+        bids: last_wins(deltas.iter().flat_map(|d| &d.bids).collect()),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Book {
+    asks: BookSide,
+    bids: RevSide,
+    snap_ver: u64,
+    last_to_ver: Option<u64>,
+}
+
+impl Book {
+    fn seeded(version: u64) -> Self {
+        let mut b = Book { asks: BookSide::new(), bids: RevSide::new(), snap_ver: version, last_to_ver: None };
+        load_levels(&mut b.asks, &mut b.bids, &[[100.5, 1.0], [101.0, 2.0]], &[[100.0, 1.0], [99.5, 2.0]]);
+        b
+    }
+
+    fn apply(&mut self, buf: Bytes) -> Result<(), IngestError> {
+        handle_diff_update(buf, &mut self.asks, &mut self.bids, &mut self.snap_ver, &mut self.last_to_ver).map(|_| ())
+    }
+}
+
+fn delta_strategy() -> impl Strategy<Value = Delta> {
+    let levels = prop::collection::vec((0u32..40, 0u32..30), 0..6);
+    (1u64..4, levels.clone(), levels).prop_map(|(span, asks, bids)| Delta { span, asks, bids })
+}
+
+fn start_strategy() -> impl Strategy<Value = u64> {
+    prop_oneof![0u64..10_000, (u64::MAX / 2)..(u64::MAX / 2 + 10_000), (u64::MAX - 1_000_000)..(u64::MAX - 100_000)]
+}
+
+/// Arbitrary text, biased toward things that look almost numeric.
+fn wire_string() -> impl Strategy<Value = String> {
+    prop_oneof![
+        any::<String>(),
+        "[-+]?[0-9]{0,22}(\\.[0-9]{0,12})?([eE][-+]?[0-9]{1,4})?",
+        Just("NaN".to_string()),
+        Just("inf".to_string()),
+        Just("-0".to_string()),
+        Just(u64::MAX.to_string()),
+        Just("18446744073709551616".to_string()),
+        any::<u64>().prop_map(|v| v.to_string()),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn split_and_merged_deltas_build_the_same_book(
+        start in start_strategy(),
+        deltas in prop::collection::vec(delta_strategy(), 1..40),
+        cuts in prop::collection::vec(any::<bool>(), 40),
+    ) {
+        let wire = to_wire(start, &deltas);
+        let mut split = Book::seeded(start);
+        for d in &wire {
+            split.apply(d.encode()).unwrap();
+        }
+
+        let mut merged = Book::seeded(start);
+        let mut chunk_start = 0;
+        for i in 0..wire.len() {
+            if i + 1 == wire.len() || cuts[i] {
+                merged.apply(merge(&wire[chunk_start..=i]).encode()).unwrap();
+                chunk_start = i + 1;
+            }
+        }
+
+        prop_assert_eq!(&split.asks, &merged.asks);
+        prop_assert_eq!(&split.bids, &merged.bids);
+        prop_assert_eq!(split.snap_ver, merged.snap_ver);
+        prop_assert_eq!(split.snap_ver, start + deltas.iter().map(|d| d.span).sum::<u64>());
+    }
+
+    #[test]
+    fn stale_and_reordered_deltas_are_never_applied(
+        start in start_strategy(),
+        deltas in prop::collection::vec(delta_strategy(), 1..40),
+        // (position, replay an earlier delta instead of the next one, swap with the next)
+        noise in prop::collection::vec((any::<prop::sample::Index>(), any::<bool>()), 0..20),
+    ) {
+        let wire = to_wire(start, &deltas);
+        let mut delivery: Vec<&WireDelta> = wire.iter().collect();
+        for (at, replay) in &noise {
+            let i = at.index(delivery.len());
+            if *replay {
+                let earlier = delivery[at.index(i + 1)];
+                delivery.insert(i, earlier);
+            } else if i + 1 < delivery.len() {
+                delivery.swap(i, i + 1);
+            }
+        }
+
+        let mut book = Book::seeded(start);
+        for d in delivery {
+            let before = book.clone();
+            let to: u64 = d.to.parse().unwrap();
+            match book.apply(d.encode()) {
+                Ok(()) => {
+                    prop_assert!(to > before.snap_ver, "applied {}..{} at version {}", d.from, d.to, before.snap_ver);
+                    prop_assert_eq!(book.snap_ver, to);
+                }
+                Err(e) => {
+                    prop_assert!(to <= before.snap_ver || matches!(e, IngestError::SequenceGap { .. }), "{e}");
+                    prop_assert_eq!(&book, &before);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn duplicates_do_not_change_the_result(
+        start in start_strategy(),
+        deltas in prop::collection::vec(delta_strategy(), 1..30),
+        dups in prop::collection::vec(any::<prop::sample::Index>(), 0..15),
+    ) {
+        let wire = to_wire(start, &deltas);
+        let mut clean = Book::seeded(start);
+        for d in &wire {
+            clean.apply(d.encode()).unwrap();
+        }
+
+        let mut delivery: Vec<&WireDelta> = wire.iter().collect();
+        for at in &dups {
+            let i = at.index(delivery.len());
+            delivery.insert(i + 1, delivery[i]);
+        }
+        let mut noisy = Book::seeded(start);
+        for d in delivery {
+            let _ = noisy.apply(d.encode());
+        }
+        prop_assert_eq!(noisy, clean);
+    }
+
+    #[test]
+    fn hostile_frames_never_panic_or_corrupt(
+        bytes in prop::collection::vec(any::<u8>(), 0..512),
+        snap_ver in any::<u64>(),
+        last_to_ver in any::<Option<u64>>(),
+    ) {
+        let mut book = Book::seeded(snap_ver);
+        book.last_to_ver = last_to_ver;
+        let before = book.clone();
+        if book.apply(Bytes::from(bytes)).is_err() {
+            prop_assert_eq!(book, before);
+        }
+    }
+
+    #[test]
+    fn hostile_fields_never_panic_or_corrupt(
+        from in wire_string(),
+        to in wire_string(),
+        asks in prop::collection::vec((wire_string(), wire_string()), 0..4),
+        bids in prop::collection::vec((wire_string(), wire_string()), 0..4),
+        snap_ver in prop_oneof![any::<u64>(), Just(u64::MAX), Just(0)],
+        last_to_ver in any::<Option<u64>>(),
+    ) {
+        let mut book = Book::seeded(snap_ver);
+        book.last_to_ver = last_to_ver;
+        let before = book.clone();
+        match book.apply(WireDelta { from, to, asks, bids }.encode()) {
+            Ok(()) => {
+                prop_assert!(book.snap_ver > before.snap_ver);
+                for (p, q) in book.asks.iter().map(|(p, q)| (p.0, *q)).chain(book.bids.iter().map(|(p, q)| ((p.0).0, *q))) {
+                    prop_assert!(p.is_finite() && p > 0.0 && q.is_finite() && q > 0.0, "level {p} x {q}");
+                }
+            }
+            Err(_) => prop_assert_eq!(book, before),
+        }
+    }
+
+    #[test]
+    fn parse_num_never_panics(s in wire_string()) {
+        let _ = parse_num::<f64>("price", &s);
+        let _ = parse_num::<u64>("toVersion", &s);
+    }
+}
@@ -0,0 +1,323 @@
+// cli.rs
+//
+// The offline subcommands (`cat`, `replay`, `book-at`, `stats`, `verify`,
+// `features`, `sample`, `align`) against a small data set written through `DataStore`, and
+// the exit codes.
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use mexc_spot_public::clock::Stamp;
+use mexc_spot_public::rawlog::RawChannel;
+use mexc_spot_public::store::{partition_dir, DataStore};
+use mexc_spot_public::types::{DepthSnapshot, TradeEvent};
+
+mod common;
+use common::{frame, SYMBOL};
+
+/// 2024-05-01T10:00:00Z
+const T0: i64 = 1_714_557_600_000;
+
+fn stamp(ms: i64, seq: u64) -> Stamp {
+    Stamp { ts_ns: ms * 1_000_000, recv_seq: seq }
+}
+
+/// A snapshot at T0, three frames a second apart and a trade.
+fn write_data(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mexc-cli-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = DataStore::new(&dir).unwrap();
+    let snap = DepthSnapshot {
+        symbol: SYMBOL.to_string(),
+        ts_recv_ms: T0,
+        last_update_id: 100,
+        bids: vec![[100.0, 1.0], [99.5, 2.0]],
+        asks: vec![[100.5, 1.0], [101.0, 2.0]],
+    };
+    store.append_event_json(SYMBOL, stamp(T0, 1), "depth_snapshot", &snap).unwrap();
+    let frames = [
+        frame(101, 101, &[["100.5", "0.5"]], &[]),
+        frame(102, 103, &[], &[["100.2", "3"]]),
+        frame(104, 104, &[["100.5", "0"]], &[]),
+    ];
+    for (i, f) in frames.iter().enumerate() {
+        store.capture_raw(SYMBOL, stamp(T0 + 1000 * (i as i64 + 1), 2 + i as u64), RawChannel::AggreDepth, f).unwrap();
+    }
+    let trade = TradeEvent {
+        symbol: SYMBOL.to_string(),
+        ts_recv_ms: T0 + 2500,
+        id: Some(1),
+        price: 100.3,
+        qty: 0.1,
+        side: Some("BUY".to_string()),
+        ts_exch_ms: Some(T0 + 2400),
+    };
+    store.append_event_json(SYMBOL, stamp(T0 + 2500, 10), "trade", &trade).unwrap();
+    store.close().unwrap();
+    dir
+}
+
+fn cli(data: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mexc-spot-public"))
+        .arg("--data-dir")
+        .arg(data)
+        .args(args)
+        .env("MEXC_CONFIG", data.join("no-such-config.toml"))
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap()
+}
+
+fn lines(out: &Output) -> Vec<serde_json::Value> {
+    String::from_utf8_lossy(&out.stdout).lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+#[test]
+fn cat_filters_by_kind_and_time() {
+    let data = write_data("cat");
+    let out = cli(&data, &["cat", "--kind", "trade"]);
+    assert!(out.status.success());
+    let events = lines(&out);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["payload"]["price"], 100.3);
+
+    let out = cli(&data, &["cat", "--from", &(T0 + 1500).to_string(), "--to", "2024-05-01T10:00:02.600Z"]);
+    let kinds: Vec<_> = lines(&out).iter().map(|e| e["kind"].as_str().unwrap().to_string()).collect();
+    assert_eq!(kinds, ["depth_pb_raw", "trade"]);
+
+    let out = cli(&data, &["cat", "--limit", "2"]);
+    assert_eq!(lines(&out).len(), 2);
+    let _ = std::fs::remove_dir_all(&data);
+}
+
+#[test]
+fn book_at_rebuilds_the_book() {
+    let data = write_data("book-at");
+    let out = cli(&data, &["--symbols", SYMBOL, "book-at", &(T0 + 2100).to_string(), "--levels", "2"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let book: DepthSnapshot = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(book.last_update_id, 103);
+    assert_eq!(book.asks, [[100.5, 0.5], [101.0, 2.0]]);
+    assert_eq!(book.bids, [[100.2, 3.0], [100.0, 1.0]]);
+
+    // before the first snapshot there is no book
+    let out = cli(&data, &["book-at", "2024-05-01"]);
+    assert_eq!(out.status.code(), Some(3));
+    let _ = std::fs::remove_dir_all(&data);
+}
+
+#[test]
+fn replay_prints_every_change() {
+    let data = write_data("replay");
+    let out = cli(&data, &["replay"]);
+    assert!(out.status.success());
+    let books = lines(&out);
+    let versions: Vec<_> = books.iter().map(|b| b["last_update_id"].as_u64().unwrap()).collect();
+    assert_eq!(versions, [100, 101, 103, 104]);
+    assert_eq!(books[3]["asks"][0][0], 101.0);
+    assert_eq!(books[3]["bids"].as_array().unwrap().len(), 1);
+
+    let out = cli(&data, &["replay", "--from", &(T0 + 1500).to_string()]);
+    assert_eq!(lines(&out).len(), 2);
+    let _ = std::fs::remove_dir_all(&data);
+}
+
+#[test]
+fn stats_reads_the_manifest() {
+    let data = write_data("stats");
+    let out = cli(&data, &["stats", "--json"]);
+    assert!(out.status.success());
+    let rows: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(rows.as_array().unwrap().len(), 1);
+    assert_eq!(rows[0]["manifest"], true);
+    assert_eq!(rows[0]["stats"]["raw_frames"], 3);
+    assert_eq!(rows[0]["stats"]["by_kind"]["trade"], 1);
+    let _ = std::fs::remove_dir_all(&data);
+}
+
+#[test]
+fn verify_after_a_restart_and_with_write_missing() {
+    let data = write_data("verify");
+    let stdout = |out: &Output| String::from_utf8_lossy(&out.stdout).into_owned();
+    let out = cli(&data, &["verify"]);
+    assert!(out.status.success(), "{}", stdout(&out));
+    assert!(stdout(&out).contains("verified 1 partitions ok"));
+
+    // a restart appends to the hour, then dies before writing a new manifest
+    let store = DataStore::new(&data).unwrap();
+    let snap = DepthSnapshot { symbol: SYMBOL.to_string(), ts_recv_ms: T0 + 5000, last_update_id: 200, bids: vec![], asks: vec![] };
+    store.append_event_json(SYMBOL, stamp(T0 + 5000, 20), "depth_snapshot", &snap).unwrap();
+    drop(store);
+    let out = cli(&data, &["verify"]);
+    assert_eq!(out.status.code(), Some(3));
+    assert!(stdout(&out).contains("no manifest"), "{}", stdout(&out));
+
+    // a manifest written now is not a verification
+    let out = cli(&data, &["verify", "--write-missing"]);
+    assert!(out.status.success());
+    assert!(stdout(&out).contains("verified 0 partitions ok, 0 damaged, 0 still open, 1 manifests written"), "{}", stdout(&out));
+    assert!(cli(&data, &["verify"]).status.success());
+
+    // unless the data is already cut short
+    let part = partition_dir(&data, SYMBOL, T0);
+    std::fs::remove_file(part.join("manifest.json")).unwrap();
+    let raw = std::fs::OpenOptions::new().write(true).open(part.join("raw.pb.zst")).unwrap();
+    raw.set_len(raw.metadata().unwrap().len() - 3).unwrap();
+    let out = cli(&data, &["verify", "--write-missing"]);
+    assert_eq!(out.status.code(), Some(3));
+    assert!(stdout(&out).contains("DAMAGED"), "{}", stdout(&out));
+    let _ = std::fs::remove_dir_all(&data);
+}
+
+#[test]
+fn usage_errors_exit_with_2() {
+    let data = std::env::temp_dir();
+    assert_eq!(cli(&data, &["no-such-command"]).status.code(), Some(2));
+    assert_eq!(cli(&data, &["book-at"]).status.code(), Some(2));
+    let help = cli(&data, &["book-at", "--help"]);
+    assert!(help.status.success());
+    assert!(String::from_utf8_lossy(&help.stdout).contains("--levels"));
+    // runs, but the time does not parse
+    assert_eq!(cli(&data, &["book-at", "yesterday"]).status.code(), Some(1));
+}
+
+#[test]
+fn features_replay_book_and_trades() {
+    let data = write_data("features");
+    let out = cli(&data, &["features", "--interval-ms", "0", "--windows-ms", "1000,10000"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let rows = lines(&out);
+    let versions: Vec<_> = rows.iter().map(|r| r["version"].as_u64().unwrap()).collect();
+    assert_eq!(versions, [100, 101, 103, 104]);
+    assert_eq!(rows[0]["mid"], 100.25);
+    assert_eq!(rows[0]["microprice"], 100.25);
+    assert_eq!(rows[0]["depth_imbalance"], 0.0);
+    // bid up to 100.2 x 3 against 100.5 x 0.5
+    assert!((rows[2]["microprice"].as_f64().unwrap() - (100.5 * 3.0 + 100.2 * 0.5) / 3.5).abs() < 1e-9);
+
+    let last = &rows[3];
+    let [short, long] = [&last["windows"][0], &last["windows"][1]];
+    assert_eq!(short["window_ms"], 1000);
+    // ask 100.5 x 0.5 taken out: +0.5
+    assert_eq!(short["ofi"], 0.5);
+    assert_eq!(short["trades"], 1);
+    assert_eq!(long["ofi"], 4.0);
+    assert_eq!(long["signed_volume"], 0.1);
+    assert_eq!(long["vwap"], 100.3);
+    let vol = ((100.35f64 / 100.25).ln().powi(2) + (100.6f64 / 100.35).ln().powi(2)).sqrt();
+    assert!((long["realized_vol"].as_f64().unwrap() - vol).abs() < 1e-12);
+
+    // earlier data still fills the windows
+    let out = cli(&data, &["features", "--interval-ms", "0", "--windows-ms", "10000", "--from", &(T0 + 1500).to_string()]);
+    let rows = lines(&out);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0]["windows"][0]["ofi"], 3.5);
+
+    // one row per second of book time at most
+    let out = cli(&data, &["features", "--interval-ms", "1500"]);
+    assert_eq!(lines(&out).len(), 2);
+    let _ = std::fs::remove_dir_all(&data);
+}
+
+#[test]
+fn sample_rows_on_a_fixed_grid() {
+    let data = write_data("sample");
+    // a resync 3.5s after the last frame, then one more frame
+    let store = DataStore::new(&data).unwrap();
+    let snap = DepthSnapshot {
+        symbol: SYMBOL.to_string(),
+        ts_recv_ms: T0 + 6500,
+        last_update_id: 200,
+        bids: vec![[100.0, 1.0]],
+        asks: vec![[100.5, 1.0]],
+    };
+    store.append_event_json(SYMBOL, stamp(T0 + 6500, 11), "depth_snapshot", &snap).unwrap();
+    store.capture_raw(SYMBOL, stamp(T0 + 7200, 12), RawChannel::AggreDepth, &frame(201, 201, &[], &[["100.1", "1"]])).unwrap();
+    store.close().unwrap();
+
+    let out_dir = data.join("samples");
+    let out = cli(&data, &["sample", "--interval-ms", "1000", "--levels", "2", "--format", "csv", "--out", out_dir.to_str().unwrap()]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let csv = std::fs::read_to_string(out_dir.join(format!("{SYMBOL}_1000ms.csv"))).unwrap();
+    let mut lines = csv.lines();
+    let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+    let rows: Vec<std::collections::HashMap<&str, &str>> =
+        lines.map(|l| header.iter().copied().zip(l.split(',')).collect()).collect();
+    let col = |name: &str| rows.iter().map(|r| r[name]).collect::<Vec<_>>();
+
+    let ts: Vec<i64> = col("ts_ms").iter().map(|t| t.parse::<i64>().unwrap() - T0).collect();
+    assert_eq!(ts, [1000, 2000, 3000, 4000, 5000, 6000, 7000]);
+    // first interval is partial; 3000.. has no events until the resync at 6500
+    assert_eq!(col("gap"), ["true", "false", "false", "true", "true", "true", "true"]);
+    assert_eq!(col("version_to"), ["100", "101", "103", "104", "104", "104", "200"]);
+    assert_eq!(col("version_from"), ["", "100", "101", "103", "104", "104", "104"]);
+    assert_eq!(col("updates"), ["0", "1", "1", "1", "0", "0", "0"]);
+    // book just before 2000: ask 100.5 down to 0.5
+    assert_eq!((rows[1]["ask_px_1"], rows[1]["ask_qty_1"], rows[1]["bid_px_2"]), ("100.5", "0.5", "99.5"));
+    assert_eq!((rows[2]["trades"], rows[2]["volume"]), ("1", "0.1"));
+    assert!((rows[2]["vwap"].parse::<f64>().unwrap() - 100.3).abs() < 1e-9);
+    assert_eq!(col("last_trade_price"), ["", "", "100.3", "100.3", "100.3", "100.3", "100.3"]);
+    assert_eq!(rows[6]["bid_px_2"], "");
+
+    let out = cli(&data, &["sample", "--interval-ms", "500", "--from", &(T0 + 2000).to_string(), "--out", out_dir.to_str().unwrap()]);
+    assert!(out.status.success());
+    let file = std::fs::File::open(out_dir.join(format!("{SYMBOL}_500ms.parquet"))).unwrap();
+    let pq = parquet::file::reader::SerializedFileReader::new(file).unwrap();
+    use parquet::file::reader::FileReader;
+    assert_eq!(pq.metadata().file_metadata().num_rows(), 11);
+    assert_eq!(pq.metadata().file_metadata().schema_descr().num_columns(), 2 + 4 * 10 + 12);
+    let _ = std::fs::remove_dir_all(&data);
+}
+
+#[test]
+fn align_matches_trades_to_the_book_before_them() {
+    let data = write_data("align");
+    // the book from T0 + 3000: bids 100.2 x 3, 100 x 1, 99.5 x 2; asks 101 x 2
+    let store = DataStore::new(&data).unwrap();
+    let trades = [(100.0, 2.0, "SELL"), (101.0, 1.5, "BUY"), (101.0, 1.0, "BUY"), (99.8, 0.5, "SELL")];
+    for (i, (price, qty, side)) in trades.into_iter().enumerate() {
+        let t = TradeEvent {
+            symbol: SYMBOL.to_string(),
+            ts_recv_ms: T0 + 3600 + i as i64,
+            id: Some(2 + i as u64),
+            price,
+            qty,
+            side: Some(side.to_string()),
+            ts_exch_ms: Some(T0 + 3500),
+        };
+        store.append_event_json(SYMBOL, stamp(t.ts_recv_ms, 20 + i as u64), "trade", &t).unwrap();
+    }
+    store.close().unwrap();
+
+    let out = cli(&data, &["align"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let rows = lines(&out);
+    assert_eq!(rows.len(), 5);
+    let col = |name: &str| rows.iter().map(|r| r[name].clone()).collect::<Vec<_>>();
+
+    // the first trade printed at T0 + 2400, against the book of T0 + 2000
+    assert_eq!(col("book_version"), [103, 104, 104, 104, 104]);
+    assert_eq!(rows[0]["bid"], serde_json::json!([100.2, 3.0]));
+    assert_eq!(col("aggressor"), ["sell", "sell", "buy", "buy", "sell"]);
+    assert_eq!(col("side_agrees"), [false, true, true, true, true]);
+    assert_eq!(col("levels_walked"), [0, 2, 1, 1, 2]);
+    assert_eq!(
+        col("mismatch"),
+        [
+            serde_json::json!("inside_spread"),
+            "exceeds_visible".into(),
+            serde_json::Value::Null,
+            "exceeds_visible".into(),
+            "off_level".into()
+        ]
+    );
+    // what the first buy left at 101
+    assert_eq!(col("visible_qty")[1..], [1.0, 2.0, 0.5, 0.0]);
+    assert!((rows[1]["slippage_bps"].as_f64().unwrap() - 0.2 / 100.2 * 1e4).abs() < 1e-9);
+    assert_eq!(rows[2]["slippage_bps"], 0.0);
+    // the trade itself is kept as stored
+    assert_eq!((rows[3]["id"].clone(), rows[3]["side"].clone()), (4.into(), "BUY".into()));
+
+    let summary = String::from_utf8_lossy(&out.stderr);
+    assert!(summary.contains("5 trades, 5 aligned, side agrees 4 / disagrees 1"), "{summary}");
+    let _ = std::fs::remove_dir_all(&data);
+}
@@ -0,0 +1,51 @@
+// clock.rs
+//
+// `Clock::stamp` from many threads at once: sequence order and time order
+// agree, with re-anchoring going on at the same time.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use mexc_spot_public::clock::{Clock, Stamp};
+
+#[test]
+fn seq_and_time_orders_agree_across_threads() {
+    const THREADS: usize = 8;
+    const PER_THREAD: usize = 20_000;
+    let clock = Arc::new(Clock::new());
+    let done = Arc::new(AtomicBool::new(false));
+    let reanchor = {
+        let (clock, done) = (clock.clone(), done.clone());
+        std::thread::spawn(move || {
+            while !done.load(Ordering::Relaxed) {
+                clock.reanchor();
+                std::thread::yield_now();
+            }
+        })
+    };
+    let workers: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let clock = clock.clone();
+            std::thread::spawn(move || {
+                let mut out = Vec::with_capacity(PER_THREAD);
+                for i in 0..PER_THREAD {
+                    // plain reads in between take times but no seqs
+                    if i % 3 == 0 {
+                        clock.now_ns();
+                    }
+                    out.push(clock.stamp());
+                }
+                out
+            })
+        })
+        .collect();
+    let mut all: Vec<Stamp> = workers.into_iter().flat_map(|w| w.join().unwrap()).collect();
+    done.store(true, Ordering::Relaxed);
+    reanchor.join().unwrap();
+
+    all.sort_by_key(|s| s.recv_seq);
+    assert_eq!(all.len(), THREADS * PER_THREAD);
+    for (i, w) in all.windows(2).enumerate() {
+        assert_eq!(w[0].recv_seq + 1, w[1].recv_seq, "seq gap at {i}");
+        assert!(w[0].ts_ns < w[1].ts_ns, "seq {} has ts {} but seq {} has ts {}", w[0].recv_seq, w[0].ts_ns, w[1].recv_seq, w[1].ts_ns);
+    }
+}
@@ -0,0 +1,225 @@
+// columnar.rs
+//
+// `ParquetSink`: every kind's table read back with the arrow reader, and the
+// `.inprogress` file renamed on close.
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use arrow::array::{Array, AsArray, RecordBatch};
+use arrow::datatypes::{DataType, Float64Type, Int64Type, UInt64Type};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::json;
+
+use mexc_spot_public::columnar::{ParquetSink, Row, RowPayload};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mexc-columnar-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn row(i: i64, payload: RowPayload) -> Row {
+    Row { ts_ms: 1_000 + i, ts_ns: Some(1_000_000_000 + i), recv_seq: Some(i as u64), seq: Some(10 + i as u64), symbol: "BTCUSDT".into(), payload }
+}
+
+/// Writes `payloads` as `kind` and reads the closed file back as one batch.
+fn round_trip(name: &str, kind: &str, payloads: Vec<RowPayload>) -> RecordBatch {
+    let dir = scratch_dir(name);
+    let mut sink = ParquetSink::new(1000);
+    let n = payloads.len();
+    for (i, p) in payloads.into_iter().enumerate() {
+        sink.append(&dir, kind, row(i as i64, p)).unwrap();
+    }
+    let written = sink.close_all().unwrap();
+    assert_eq!(written, [dir.join(format!("{kind}.parquet"))]);
+    let batch = read_all(&written[0]);
+    assert_eq!(batch.num_rows(), n);
+    check_common(&batch);
+    let _ = std::fs::remove_dir_all(&dir);
+    batch
+}
+
+fn read_all(path: &Path) -> RecordBatch {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap().build().unwrap();
+    let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+    arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap()
+}
+
+fn check_common(b: &RecordBatch) {
+    let ts = b.column_by_name("ts_ms").unwrap().as_primitive::<Int64Type>();
+    let ns = b.column_by_name("ts_ns").unwrap().as_primitive::<Int64Type>();
+    let recv = b.column_by_name("recv_seq").unwrap().as_primitive::<UInt64Type>();
+    let seq = b.column_by_name("seq").unwrap().as_primitive::<UInt64Type>();
+    let sym = b.column_by_name("symbol").unwrap().as_string::<i32>();
+    for i in 0..b.num_rows() {
+        assert_eq!(ts.value(i), 1_000 + i as i64);
+        assert_eq!(ns.value(i), 1_000_000_000 + i as i64);
+        assert_eq!(recv.value(i), i as u64);
+        assert_eq!(seq.value(i), 10 + i as u64);
+        assert_eq!(sym.value(i), "BTCUSDT");
+    }
+}
+
+fn columns(b: &RecordBatch) -> Vec<String> {
+    b.schema().fields().iter().skip(5).map(|f| f.name().clone()).collect()
+}
+
+fn i64s(b: &RecordBatch, name: &str) -> Vec<Option<i64>> {
+    b.column_by_name(name).unwrap().as_primitive::<Int64Type>().iter().collect()
+}
+
+fn u64s(b: &RecordBatch, name: &str) -> Vec<Option<u64>> {
+    b.column_by_name(name).unwrap().as_primitive::<UInt64Type>().iter().collect()
+}
+
+fn f64s(b: &RecordBatch, name: &str) -> Vec<Option<f64>> {
+    b.column_by_name(name).unwrap().as_primitive::<Float64Type>().iter().collect()
+}
+
+fn strs(b: &RecordBatch, name: &str) -> Vec<Option<String>> {
+    b.column_by_name(name).unwrap().as_string::<i32>().iter().map(|s| s.map(str::to_string)).collect()
+}
+
+/// `<name>_price`/`<name>_qty` back as `[[price, qty], ...]` per row; `None` for a null list.
+fn levels(b: &RecordBatch, name: &str) -> Vec<Option<Vec<[f64; 2]>>> {
+    let px = b.column_by_name(&format!("{name}_price")).unwrap().as_list::<i32>();
+    let qty = b.column_by_name(&format!("{name}_qty")).unwrap().as_list::<i32>();
+    (0..b.num_rows())
+        .map(|i| {
+            if px.is_null(i) {
+                assert!(qty.is_null(i));
+                return None;
+            }
+            let (p, q) = (px.value(i), qty.value(i));
+            let (p, q) = (p.as_primitive::<Float64Type>(), q.as_primitive::<Float64Type>());
+            Some((0..p.len()).map(|j| [p.value(j), q.value(j)]).collect())
+        })
+        .collect()
+}
+
+#[test]
+fn depth_delta_round_trip() {
+    let b = round_trip(
+        "delta",
+        "depth_delta",
+        vec![
+            RowPayload::Json(json!({ "ts_recv_ms": 5, "from_version": 11, "to_version": 12, "bids": [[100.0, 1.5], [99.5, 2.0]], "asks": [] })),
+            RowPayload::Json(json!({ "ts_recv_ms": 6, "from_version": 13, "to_version": 13, "asks": [[100.5, 0.0]] })),
+        ],
+    );
+    assert_eq!(columns(&b), ["ts_recv_ms", "from_version", "to_version", "bids_price", "bids_qty", "asks_price", "asks_qty"]);
+    assert_eq!(i64s(&b, "ts_recv_ms"), [Some(5), Some(6)]);
+    assert_eq!(u64s(&b, "from_version"), [Some(11), Some(13)]);
+    assert_eq!(u64s(&b, "to_version"), [Some(12), Some(13)]);
+    assert_eq!(levels(&b, "bids"), [Some(vec![[100.0, 1.5], [99.5, 2.0]]), None]);
+    assert_eq!(levels(&b, "asks"), [Some(vec![]), Some(vec![[100.5, 0.0]])]);
+}
+
+#[test]
+fn depth_snapshot_round_trip() {
+    let b = round_trip(
+        "snapshot",
+        "depth_snapshot",
+        vec![RowPayload::Json(json!({ "symbol": "BTCUSDT", "ts_recv_ms": 5, "last_update_id": 1000, "bids": [[100.0, 1.0]], "asks": [[100.5, 2.0]] }))],
+    );
+    assert_eq!(columns(&b), ["ts_recv_ms", "last_update_id", "bids_price", "bids_qty", "asks_price", "asks_qty"]);
+    assert_eq!(u64s(&b, "last_update_id"), [Some(1000)]);
+    assert_eq!(levels(&b, "bids"), [Some(vec![[100.0, 1.0]])]);
+    assert_eq!(levels(&b, "asks"), [Some(vec![[100.5, 2.0]])]);
+}
+
+#[test]
+fn trade_round_trip() {
+    let b = round_trip(
+        "trade",
+        "trade",
+        vec![
+            RowPayload::Json(json!({ "ts_recv_ms": 5, "id": 7, "price": 100.25, "qty": 0.5, "side": "BUY", "ts_exch_ms": 4 })),
+            RowPayload::Json(json!({ "ts_recv_ms": 6, "id": null, "price": 100.0, "qty": 1.0, "side": null, "ts_exch_ms": null })),
+        ],
+    );
+    assert_eq!(columns(&b), ["ts_recv_ms", "id", "price", "qty", "side", "ts_exch_ms"]);
+    assert_eq!(u64s(&b, "id"), [Some(7), None]);
+    assert_eq!(f64s(&b, "price"), [Some(100.25), Some(100.0)]);
+    assert_eq!(f64s(&b, "qty"), [Some(0.5), Some(1.0)]);
+    assert_eq!(strs(&b, "side"), [Some("BUY".to_string()), None]);
+    assert_eq!(i64s(&b, "ts_exch_ms"), [Some(4), None]);
+}
+
+#[test]
+fn book_ticker_round_trip() {
+    let b = round_trip(
+        "ticker",
+        "book_ticker",
+        vec![RowPayload::Json(json!({ "ts_recv_ms": 5, "bid_price": 100.0, "bid_qty": 1.0, "ask_price": 100.5, "ask_qty": 2.0 }))],
+    );
+    assert_eq!(columns(&b), ["ts_recv_ms", "bid_price", "bid_qty", "ask_price", "ask_qty"]);
+    assert_eq!(f64s(&b, "bid_price"), [Some(100.0)]);
+    assert_eq!(f64s(&b, "ask_qty"), [Some(2.0)]);
+}
+
+#[test]
+fn depth_pb_round_trip() {
+    let b = round_trip(
+        "pb",
+        "depth_pb",
+        vec![RowPayload::Json(json!({
+            "channel": "spot@public.aggre.depth.v3.api.pb@10ms@BTCUSDT", "send_time_ms": 4, "event_type": "depth",
+            "from_version": 11, "to_version": 12, "bids": [[100.0, 1.0]], "asks": [[100.5, 0.0], [101.0, 3.0]]
+        }))],
+    );
+    assert_eq!(columns(&b), ["channel", "send_time_ms", "event_type", "from_version", "to_version", "bids_price", "bids_qty", "asks_price", "asks_qty"]);
+    assert_eq!(strs(&b, "channel"), [Some("spot@public.aggre.depth.v3.api.pb@10ms@BTCUSDT".to_string())]);
+    assert_eq!(i64s(&b, "send_time_ms"), [Some(4)]);
+    assert_eq!(levels(&b, "asks"), [Some(vec![[100.5, 0.0], [101.0, 3.0]])]);
+}
+
+#[test]
+fn raw_frames_round_trip() {
+    let b = round_trip("raw", "depth_pb_raw", vec![RowPayload::Raw(vec![0, 1, 2, 255]), RowPayload::Raw(Vec::new())]);
+    assert_eq!(columns(&b), ["payload"]);
+    assert_eq!(b.schema().field_with_name("payload").unwrap().data_type(), &DataType::Binary);
+    let p = b.column_by_name("payload").unwrap().as_binary::<i32>();
+    assert_eq!(p.value(0), [0, 1, 2, 255]);
+    assert_eq!(p.value(1), [] as [u8; 0]);
+}
+
+#[test]
+fn other_kinds_keep_json_payloads() {
+    let payload = json!({ "action": "prune", "updates": 2 });
+    let b = round_trip("other", "crossed_book", vec![RowPayload::Json(payload.clone())]);
+    assert_eq!(columns(&b), ["payload"]);
+    let s = strs(&b, "payload")[0].clone().unwrap();
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&s).unwrap(), payload);
+}
+
+#[test]
+fn files_are_renamed_when_closed() {
+    let dir = scratch_dir("rename");
+    let (tmp, dst) = (dir.join("trade.parquet.inprogress"), dir.join("trade.parquet"));
+    let mut sink = ParquetSink::new(1000);
+    let trade = || RowPayload::Json(json!({ "price": 1.0, "qty": 1.0 }));
+    sink.append(&dir, "trade", row(0, trade())).unwrap();
+    assert!(tmp.exists() && !dst.exists());
+
+    // rows of another partition close this one
+    let next = dir.join("next");
+    sink.append(&next, "trade", row(1, trade())).unwrap();
+    assert!(!tmp.exists() && dst.exists());
+    assert_eq!(read_all(&dst).num_rows(), 1);
+    assert!(next.join("trade.parquet.inprogress").exists());
+
+    // back in the first partition: that closes `next`, and the second file
+    // there gets the next free name
+    sink.append(&dir, "trade", row(2, trade())).unwrap();
+    assert!(next.join("trade.parquet").exists());
+    assert!(dir.join("trade.1.parquet.inprogress").exists());
+    assert_eq!(sink.close_dir(&next).unwrap(), [] as [PathBuf; 0]);
+
+    // dropping the sink closes the rest
+    drop(sink);
+    assert!(!dir.join("trade.1.parquet.inprogress").exists());
+    assert_eq!(read_all(&dir.join("trade.1.parquet")).num_rows(), 1);
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,31 @@
+// common/mod.rs
+//
+// Helpers shared by the integration tests that build depth frames by hand.
+use prost::Message;
+
+use mexc_spot_public::mexc_pb::{push_data_v3_api_wrapper::Body, PublicAggreDepthV3ApiItem, PublicAggreDepthsV3Api, PushDataV3ApiWrapper};
+
+pub const SYMBOL: &str = "BTCUSDT";
+
+/// An encoded aggregated-depth push for `SYMBOL` covering versions `from..=to`.
+pub fn frame<L: ToString>(from: u64, to: u64, asks: &[[L; 2]], bids: &[[L; 2]]) -> Vec<u8> {
+    let items = |l: &[[L; 2]]| {
+        l.iter().map(|[p, q]| PublicAggreDepthV3ApiItem { price: p.to_string(), quantity: q.to_string() }).collect()
+    };
+    let chan = format!("spot@public.aggre.depth.v3.api.pb@10ms@{SYMBOL}");
+    PushDataV3ApiWrapper {
+        channel: chan.clone(),
+        symbol: Some(SYMBOL.to_string()),
+        symbol_id: None,
+        create_time: None,
+        send_time: None,
+        body: Some(Body::PublicAggreDepths(PublicAggreDepthsV3Api {
+            asks: items(asks),
+            bids: items(bids),
+            event_type: chan,
+            from_version: from.to_string(),
+            to_version: to.to_string(),
+        })),
+    }
+    .encode_to_vec()
+}
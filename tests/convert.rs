@@ -0,0 +1,84 @@
+// convert.rs
+//
+// `convert_all` reruns: finished partitions are skipped, interrupted ones are
+// redone from scratch, and a malformed partition fails alone.
+use std::path::{Path, PathBuf};
+
+use mexc_spot_public::clock::Stamp;
+use mexc_spot_public::convert::{convert_all, ConvertManifest, ConvertOptions, MANIFEST_NAME};
+use mexc_spot_public::store::DataStore;
+use mexc_spot_public::types::TradeEvent;
+
+const SYMBOL: &str = "BTCUSDT";
+/// 2024-05-01T10:00:00Z
+const T0: i64 = 1_714_557_600_000;
+const HOUR: i64 = 3_600_000;
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mexc-convert-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Three trades in each of two hours.
+fn write_data(root: &Path) {
+    let store = DataStore::new(root).unwrap();
+    for (i, ts) in [T0, T0 + 1, T0 + 2, T0 + HOUR, T0 + HOUR + 1, T0 + HOUR + 2].into_iter().enumerate() {
+        let t = TradeEvent {
+            symbol: SYMBOL.to_string(),
+            ts_recv_ms: ts,
+            id: Some(i as u64),
+            price: 100.0,
+            qty: 1.0,
+            side: Some("BUY".to_string()),
+            ts_exch_ms: Some(ts),
+        };
+        store.append_event_json(SYMBOL, Stamp { ts_ns: ts * 1_000_000, recv_seq: i as u64 }, "trade", &t).unwrap();
+    }
+    store.close().unwrap();
+}
+
+fn manifest(dir: &Path) -> ConvertManifest {
+    serde_json::from_slice(&std::fs::read(dir.join(MANIFEST_NAME)).unwrap()).unwrap()
+}
+
+#[test]
+fn reruns_skip_finished_partitions_and_redo_interrupted_ones() {
+    let dir = scratch_dir("rerun");
+    let (src, out) = (dir.join("data"), dir.join("out"));
+    write_data(&src);
+    // an hour directory whose date does not parse
+    let bad = src.join(format!("symbol={SYMBOL}/date=2024-13-45/hour=10"));
+    std::fs::create_dir_all(&bad).unwrap();
+    std::fs::copy(src.join(format!("symbol={SYMBOL}/date=2024-05-01/hour=10/events.ndjson.zst")), bad.join("events.ndjson.zst")).unwrap();
+    let opts = ConvertOptions { jobs: 2, ..Default::default() };
+
+    let sum = convert_all(&src, &out, &opts).unwrap();
+    assert_eq!((sum.converted, sum.skipped), (2, 0));
+    assert_eq!(sum.failed.len(), 1);
+    assert_eq!(sum.failed[0].0, bad);
+    let (h10, h11) = (out.join(format!("symbol={SYMBOL}/date=2024-05-01/hour=10")), out.join(format!("symbol={SYMBOL}/date=2024-05-01/hour=11")));
+    let first = manifest(&h10);
+    assert_eq!(first.rows.get("trade"), Some(&3));
+    assert_eq!(first.files, ["trade.parquet"]);
+    assert_eq!(manifest(&h11).rows.get("trade"), Some(&3));
+
+    let sum = convert_all(&src, &out, &opts).unwrap();
+    assert_eq!((sum.converted, sum.skipped, sum.failed.len()), (0, 2, 1));
+    assert_eq!(manifest(&h10).converted_at_ms, first.converted_at_ms);
+
+    // interrupted in hour 11: files without a manifest, one still in progress
+    std::fs::remove_file(h11.join(MANIFEST_NAME)).unwrap();
+    std::fs::write(h11.join("trade.1.parquet.inprogress"), b"partial").unwrap();
+    std::fs::write(h11.join("trade.2.parquet"), b"stale").unwrap();
+    let sum = convert_all(&src, &out, &opts).unwrap();
+    assert_eq!((sum.converted, sum.skipped, sum.failed.len()), (1, 1, 1));
+    assert_eq!(manifest(&h10).converted_at_ms, first.converted_at_ms);
+    let redone = manifest(&h11);
+    assert_eq!(redone.rows.get("trade"), Some(&3));
+    assert_eq!(redone.files, ["trade.parquet"]);
+    let mut left: Vec<String> = std::fs::read_dir(&h11).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    left.sort();
+    assert_eq!(left, [MANIFEST_NAME, "trade.parquet"]);
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,166 @@
+// crossed.rs
+//
+// `CrossedGuard` with the prune policy: the pruned levels go out as part of
+// the published delta, so a WebSocket subscriber's replica matches the
+// recorder's book, and in the `crossed_book` event, so replaying the stored
+// frames reproduces it.
+use std::cmp::Reverse;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use ordered_float::OrderedFloat;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message as WsMsg, MaybeTlsStream, WebSocketStream};
+
+use mexc_spot_public::book::{CrossedGuard, CrossedPolicy, DepthBook};
+use mexc_spot_public::replay::BookReplay;
+use mexc_spot_public::server::{serve_listener, BookHub};
+use mexc_spot_public::store::StoredEvent;
+use mexc_spot_public::types::{AppliedDelta, BookSide, CrossedBook, DepthSnapshot, RevSide};
+
+mod common;
+use common::{frame, SYMBOL};
+
+type Client = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+fn snapshot() -> DepthSnapshot {
+    DepthSnapshot {
+        symbol: SYMBOL.into(),
+        ts_recv_ms: 1,
+        last_update_id: 10,
+        asks: vec![[100.5, 1.0], [100.7, 1.0], [101.0, 2.0]],
+        bids: vec![[100.0, 1.0], [99.5, 2.0]],
+    }
+}
+
+fn levels(asks: &BookSide, bids: &RevSide) -> (Vec<[f64; 2]>, Vec<[f64; 2]>) {
+    (asks.iter().map(|(p, q)| [p.0, *q]).collect(), bids.iter().map(|(p, q)| [(p.0).0, *q]).collect())
+}
+
+fn apply(asks: &mut BookSide, bids: &mut RevSide, a: &[[f64; 2]], b: &[[f64; 2]]) {
+    for [p, q] in a {
+        if *q == 0.0 { asks.remove(&OrderedFloat(*p)); } else { asks.insert(OrderedFloat(*p), *q); }
+    }
+    for [p, q] in b {
+        if *q == 0.0 { bids.remove(&Reverse(OrderedFloat(*p))); } else { bids.insert(Reverse(OrderedFloat(*p)), *q); }
+    }
+}
+
+/// The recorder's book, seeded from `snapshot()`.
+fn recorder() -> DepthBook {
+    let mut book = DepthBook::new(Some(CrossedGuard::new(CrossedPolicy::Prune, 10)));
+    book.load_snapshot(&snapshot());
+    book
+}
+
+/// The depth loop's step for one frame.
+fn feed(rec: &mut DepthBook, data: &[u8]) -> (AppliedDelta, Option<CrossedBook>) {
+    let out = rec.apply_frame(SYMBOL, data.to_vec().into(), 2);
+    assert!(!out.crossed.resync && out.error.is_none());
+    (out.delta.unwrap(), out.crossed.event)
+}
+
+async fn next_data(ws: &mut Client) -> Value {
+    loop {
+        let msg = tokio::time::timeout(Duration::from_secs(5), ws.next()).await.expect("no message").unwrap().unwrap();
+        if let WsMsg::Text(t) = msg {
+            let v: Value = serde_json::from_str(&t).unwrap();
+            if v.get("op").is_none() {
+                return v;
+            }
+        }
+    }
+}
+
+fn value_levels(v: &Value) -> Vec<[f64; 2]> {
+    serde_json::from_value(v.clone()).unwrap()
+}
+
+#[test]
+fn the_published_delta_carries_the_pruned_levels() {
+    let mut rec = recorder();
+    // a bid through two asks: both are dropped
+    let (d, ev) = feed(&mut rec, &frame(11, 11, &[], &[[100.8, 1.0]]));
+    let ev = ev.unwrap();
+    assert_eq!(ev.action, "prune");
+    assert_eq!((ev.pruned_asks.clone(), ev.pruned_bids.clone(), ev.pruned_levels), (vec![100.5, 100.7], vec![], 2));
+    assert_eq!(d.bids, [[100.8, 1.0]]);
+    assert_eq!(d.asks, [[100.5, 0.0], [100.7, 0.0]]);
+
+    // an ask through the bids
+    let (d, ev) = feed(&mut rec, &frame(12, 12, &[[99.6, 1.0]], &[]));
+    assert_eq!(ev.unwrap().pruned_bids, [100.8, 100.0]);
+    assert_eq!(d.bids, [[100.8, 0.0], [100.0, 0.0]]);
+    let (asks, bids) = levels(&rec.asks, &rec.bids);
+    assert_eq!(asks, [[99.6, 1.0], [101.0, 2.0]]);
+    assert_eq!(bids, [[99.5, 2.0]]);
+}
+
+#[tokio::test]
+async fn hub_subscribers_follow_the_prune() {
+    let mut rec = recorder();
+    let hub = Arc::new(BookHub::new());
+    hub.publish_snapshot(SYMBOL, rec.version, &rec.asks, &rec.bids);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_listener(hub.clone(), listener));
+    let subscribe = json!({ "op": "subscribe", "channel": format!("book.{SYMBOL}") }).to_string();
+    let mut ws: Client = connect_async(format!("ws://{addr}")).await.unwrap().0;
+    ws.send(WsMsg::Text(subscribe.clone())).await.unwrap();
+
+    let snap = next_data(&mut ws).await;
+    let (mut asks, mut bids) = (BookSide::new(), RevSide::new());
+    apply(&mut asks, &mut bids, &value_levels(&snap["asks"]), &value_levels(&snap["bids"]));
+
+    for f in [frame(11, 11, &[], &[[100.8, 1.0]]), frame(12, 12, &[[100.9, 3.0]], &[[99.0, 1.0]])] {
+        let (d, _) = feed(&mut rec, &f);
+        hub.publish_delta(SYMBOL, &d);
+        let m = next_data(&mut ws).await;
+        assert_eq!(m["type"], "delta");
+        apply(&mut asks, &mut bids, &value_levels(&m["asks"]), &value_levels(&m["bids"]));
+    }
+    assert_eq!(levels(&asks, &bids), levels(&rec.asks, &rec.bids));
+
+    // and a new subscriber's snapshot comes from the hub's own mirror
+    let mut late: Client = connect_async(format!("ws://{addr}")).await.unwrap().0;
+    late.send(WsMsg::Text(subscribe)).await.unwrap();
+    let snap = next_data(&mut late).await;
+    assert_eq!(snap["version"], 12);
+    assert_eq!((value_levels(&snap["asks"]), value_levels(&snap["bids"])), levels(&rec.asks, &rec.bids));
+}
+
+#[test]
+fn replay_applies_the_stored_prune() {
+    let mut rec = recorder();
+    let event = |ts_ms: i64, seq: Option<u64>, kind: &str, payload: Option<Value>, raw: Option<Vec<u8>>| StoredEvent {
+        ts_ms,
+        ts_ns: Some(ts_ms * 1_000_000),
+        recv_seq: None,
+        seq,
+        symbol: SYMBOL.into(),
+        kind: kind.into(),
+        payload,
+        payload_b64: None,
+        raw_bytes: raw,
+    };
+    let mut stored = vec![event(1, Some(1), "depth_snapshot", Some(serde_json::to_value(snapshot()).unwrap()), None)];
+    for (i, f) in [frame(11, 11, &[], &[[100.8, 1.0]]), frame(12, 12, &[[100.9, 3.0]], &[])].into_iter().enumerate() {
+        let ts = 2 + i as i64;
+        let (_, ev) = feed(&mut rec, &f);
+        // as `read_partition` orders them: the raw frame, then what the recorder stored for it
+        stored.push(event(ts, None, "depth_pb_raw", None, Some(f)));
+        if let Some(ev) = ev {
+            stored.push(event(ts, Some(2 + i as u64), "crossed_book", Some(serde_json::to_value(ev).unwrap()), None));
+        }
+    }
+
+    let mut replay = BookReplay::new(SYMBOL);
+    for ev in &stored {
+        replay.apply(ev);
+    }
+    assert!(replay.depth.valid);
+    assert_eq!(replay.depth.version, 12);
+    assert_eq!(levels(&replay.depth.asks, &replay.depth.bids), levels(&rec.asks, &rec.bids));
+}
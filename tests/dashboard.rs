@@ -0,0 +1,52 @@
+// dashboard.rs
+//
+// `Dashboard` rows from shared-memory regions: spread, imbalance, counters and
+// the row of a symbol without a book yet.
+use std::cmp::Reverse;
+
+use ordered_float::OrderedFloat;
+
+use mexc_spot_public::dashboard::{discover, Dashboard};
+use mexc_spot_public::shm::ShmWriter;
+use mexc_spot_public::types::{BookSide, RevSide};
+
+#[test]
+fn rows_show_spread_imbalance_and_empty_books() {
+    let dir = std::env::temp_dir().join(format!("mexc-dashboard-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut btc = ShmWriter::create(&dir, "BTCUSDT", 4).unwrap();
+    let _eth = ShmWriter::create(&dir, "ETHUSDT", 4).unwrap();
+    let mut asks = BookSide::new();
+    let mut bids = RevSide::new();
+    asks.insert(OrderedFloat(100.5), 1.0);
+    bids.insert(Reverse(OrderedFloat(100.0)), 2.0);
+    bids.insert(Reverse(OrderedFloat(99.5)), 1.0);
+    btc.publish(7, 1_000, Some(990), &asks, &bids);
+    btc.set_counters(3, 1);
+
+    assert_eq!(discover(&dir).unwrap(), ["BTCUSDT", "ETHUSDT"]);
+    let mut dash = Dashboard::open(&dir, &[]).unwrap();
+    dash.refresh();
+    let screen = dash.render();
+    let lines: Vec<&str> = screen.strip_prefix("\x1b[H\x1b[2J").unwrap().lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("symbol"));
+
+    let cols: Vec<&str> = lines[1].split_whitespace().collect();
+    assert_eq!(cols[..3], ["BTCUSDT", "100.000000", "100.500000"]);
+    // 0.5 / 100.25 mid, in bps
+    assert_eq!(cols[3], "49.88");
+    // (3 - 1) / (3 + 1) of the quantities shown
+    assert_eq!(cols[4], "0.500");
+    // exchange to receive latency, then gaps and resyncs
+    assert_eq!(cols[6], "10");
+    assert_eq!(cols[8..10], ["3", "1"]);
+
+    assert_eq!(lines[2].split_whitespace().collect::<Vec<_>>(), ["ETHUSDT", "(no", "book", "yet)"]);
+
+    // a missing region is an error, not an empty row
+    assert!(Dashboard::open(&dir, &["XRPUSDT".to_string()]).is_err());
+    let _ = std::fs::remove_dir_all(&dir);
+}
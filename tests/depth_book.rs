@@ -0,0 +1,90 @@
+// depth_book.rs
+//
+// `DepthBook::apply_frame`, the depth loop's per-frame step: levels off the
+// `exchangeInfo` tick/lot grid are counted, skipped frames leave the book
+// valid and a gap breaks it once; a broken book reports no deltas until the
+// next snapshot.
+use mexc_spot_public::book::{CrossedGuard, CrossedPolicy, DepthBook};
+use mexc_spot_public::error::IngestError;
+use mexc_spot_public::types::{DepthSnapshot, Precision};
+
+mod common;
+use common::{frame, SYMBOL};
+
+fn book(crossed: Option<CrossedGuard>) -> DepthBook {
+    let mut book = DepthBook::new(crossed);
+    book.precision = Some(Precision { price_dp: 2, qty_dp: 3 });
+    book.load_snapshot(&DepthSnapshot {
+        symbol: SYMBOL.into(),
+        ts_recv_ms: 1,
+        last_update_id: 10,
+        asks: vec![[100.5, 1.0]],
+        bids: vec![[100.0, 1.0]],
+    });
+    book
+}
+
+#[test]
+fn off_grid_levels_are_counted() {
+    let mut book = book(None);
+    let out = book.apply_frame(SYMBOL, frame(11, 11, &[["100.51", "0.5"]], &[["99.9", "2"]]).into(), 2);
+    assert_eq!((out.off_grid, out.delta.is_some()), (0, true));
+
+    // a price between ticks and a quantity below the lot, still applied
+    let out = book.apply_frame(SYMBOL, frame(12, 12, &[["100.505", "1"]], &[["99.8", "0.0001"]]).into(), 3);
+    assert_eq!(out.off_grid, 2);
+    assert!(out.delta.is_some() && out.error.is_none());
+    assert_eq!(book.version, 12);
+
+    // large prices and quantities are checked to the same tick and lot
+    let out = book.apply_frame(SYMBOL, frame(13, 13, &[["60000.01", "2500000.001"]], &[]).into(), 4);
+    assert_eq!(out.off_grid, 0);
+    let out = book.apply_frame(SYMBOL, frame(14, 14, &[["60000.005", "1"], ["60001", "2500000.0005"]], &[]).into(), 5);
+    assert_eq!(out.off_grid, 2);
+
+    // unknown precision checks nothing
+    book.precision = None;
+    let out = book.apply_frame(SYMBOL, frame(15, 15, &[["100.505", "2"]], &[]).into(), 6);
+    assert_eq!(out.off_grid, 0);
+}
+
+#[test]
+fn a_gap_breaks_the_book_once() {
+    let mut book = book(None);
+    let stale = frame(9, 10, &[["101", "1"]], &[]);
+    let out = book.apply_frame(SYMBOL, stale.into(), 2);
+    assert!(matches!(out.error, Some(IngestError::Stale { .. })));
+    assert!(!out.broke && book.valid);
+
+    let out = book.apply_frame(SYMBOL, frame(11, 11, &[], &[["100.1", "1"]]).into(), 3);
+    assert!(out.error.is_none());
+    let out = book.apply_frame(SYMBOL, frame(5000, 5000, &[], &[["100.2", "1"]]).into(), 4);
+    assert!(matches!(out.error, Some(IngestError::SequenceGap { needed: 12, .. })));
+    assert!(out.broke && out.delta.is_none() && !book.valid);
+    let out = book.apply_frame(SYMBOL, frame(9000, 9000, &[], &[["100.2", "1"]]).into(), 5);
+    assert!(out.error.is_some() && !out.broke);
+
+    book.load_snapshot(&DepthSnapshot { symbol: SYMBOL.into(), ts_recv_ms: 6, last_update_id: 9000, asks: vec![], bids: vec![] });
+    assert!(book.valid);
+    // true only for the call that broke a valid book
+    assert!(book.break_book());
+    assert!(!book.break_book());
+}
+
+#[test]
+fn a_broken_book_reports_no_delta() {
+    let mut book = book(Some(CrossedGuard::new(CrossedPolicy::Prune, 10)));
+    book.apply_frame(SYMBOL, frame(11, 11, &[], &[["100.1", "1"]]).into(), 2);
+    let out = book.apply_frame(SYMBOL, frame(5000, 5000, &[], &[["100.2", "1"]]).into(), 3);
+    assert!(out.broke);
+
+    // the next frame in sequence still applies, crossed, but nothing downstream sees it
+    let out = book.apply_frame(SYMBOL, frame(12, 12, &[["99", "1"]], &[["100.1", "1"]]).into(), 3);
+    assert!(out.error.is_none() && !out.broke);
+    assert!(out.delta.is_none() && !out.crossed.started && out.crossed.event.is_none());
+    assert_eq!(book.version, 12);
+
+    book.load_snapshot(&DepthSnapshot { symbol: SYMBOL.into(), ts_recv_ms: 4, last_update_id: 20, asks: vec![], bids: vec![] });
+    let out = book.apply_frame(SYMBOL, frame(21, 21, &[], &[["100.1", "1"]]).into(), 5);
+    assert!(out.delta.is_some());
+}
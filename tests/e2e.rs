@@ -0,0 +1,539 @@
+// e2e.rs
+//
+// Runs the recorder binary against `mock::MockExchange` and checks what it
+// stored. The recorder reconnects when the exchange closes the depth socket,
+// so each test stops it with SIGINT once its scenario has run.
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use mexc_spot_public::align::{AlignedTrade, Aggressor, Mismatch};
+use mexc_spot_public::features::FeatureRow;
+use mexc_spot_public::mock::{Delta, MockExchange, Scenario, Step};
+use mexc_spot_public::store::{list_partitions, read_partition, StoredEvent};
+use mexc_spot_public::types::{CrossedBook, DepthDelta, DepthSnapshot, ExchangeInfoEvent, Precision, SymbolChange, SymbolStatus, TradeEvent};
+
+const SYMBOL: &str = "BTCUSDT";
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mexc-e2e-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn spawn_recorder(ex: &MockExchange, dir: &Path, extra_config: &str) -> Child {
+    spawn_recorder_for(ex, dir, SYMBOL, "", extra_config)
+}
+
+/// `symbols` is comma separated; `exchange_config` goes into `[exchange]`.
+fn spawn_recorder_for(ex: &MockExchange, dir: &Path, symbols: &str, exchange_config: &str, extra_config: &str) -> Child {
+    let cfg = format!(
+        "[exchange]\nrest_url = \"{}\"\n{exchange_config}\n\n[websocket]\nurl = \"{}\"\n\n[logging]\nlevel = \"warn\"\n\n{extra_config}",
+        ex.rest_url(),
+        ex.ws_url()
+    );
+    let cfg_path = dir.join("config.toml");
+    std::fs::write(&cfg_path, cfg).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_mexc-spot-public"))
+        .args(["--data-dir", dir.join("data").to_str().unwrap(), "--symbols", symbols, "record"])
+        .env("MEXC_CONFIG", &cfg_path)
+        .env_remove("RUST_LOG")
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap()
+}
+
+async fn wait_exit(child: &mut Child) -> std::process::ExitStatus {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status;
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            panic!("recorder did not exit");
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// Stops the recorder like an operator would; it closes the store and exits 0.
+async fn stop(child: &mut Child) -> std::process::ExitStatus {
+    Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap();
+    wait_exit(child).await
+}
+
+fn stored_events(dir: &Path) -> Vec<StoredEvent> {
+    let mut out = Vec::new();
+    for part in list_partitions(dir.join("data")).unwrap() {
+        out.extend(read_partition(&part).unwrap().map(|e| e.unwrap()));
+    }
+    out
+}
+
+fn of_symbol(events: &[StoredEvent], symbol: &str) -> Vec<StoredEvent> {
+    events.iter().filter(|e| e.symbol == symbol).cloned().collect()
+}
+
+fn of_kind<T: serde::de::DeserializeOwned>(events: &[StoredEvent], kind: &str) -> Vec<T> {
+    events.iter().filter(|e| e.kind == kind).map(|e| e.payload_as().unwrap()).collect()
+}
+
+/// Records `scenario` and returns what was stored.
+async fn record(name: &str, ex: &MockExchange, extra_config: &str, scenario: Scenario) -> Vec<StoredEvent> {
+    let dir = scratch_dir(name);
+    let mut child = spawn_recorder(ex, &dir, extra_config);
+    ex.run(&scenario).await.unwrap();
+    let status = stop(&mut child).await;
+    assert!(status.success(), "recorder failed: {status}");
+    let events = stored_events(&dir);
+    let _ = std::fs::remove_dir_all(&dir);
+    events
+}
+
+fn seed() -> Step {
+    Step::Snapshot {
+        version: 1000,
+        asks: vec![[100.5, 1.0], [101.0, 2.0], [102.0, 3.0]],
+        bids: vec![[100.0, 1.0], [99.5, 2.0], [99.0, 3.0]],
+    }
+}
+
+fn assert_book_matches(last: &DepthDelta, ex: &MockExchange) {
+    let book = ex.book(SYMBOL).unwrap();
+    assert_eq!(last.to_version, book.last_update_id);
+    assert_eq!(last.asks, book.asks);
+    assert_eq!(last.bids, book.bids);
+}
+
+#[tokio::test]
+async fn records_snapshot_deltas_and_trades() {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let scenario = Scenario {
+        symbol: SYMBOL.to_string(),
+        steps: vec![
+            seed(),
+            Step::WaitSubscribed,
+            Step::Depth(Delta::new().ask(100.5, 0.5).bid(100.2, 4.0)),
+            Step::Depth(Delta::new().ask(101.0, 0.0)),
+            Step::Depth(Delta::new().bid(99.0, 0.0).bid(98.0, 1.5).span(3)),
+            Step::Trade { price: 100.3, qty: 0.25, buyer_maker: false },
+            Step::Trade { price: 100.2, qty: 1.0, buyer_maker: true },
+            Step::Sleep(Duration::from_millis(800)),
+        ],
+    };
+    let events = record("basic", &ex, "", scenario).await;
+
+    let info: Vec<ExchangeInfoEvent> = of_kind(&events, "exchange_info");
+    assert_eq!(info.len(), 1);
+    assert_eq!(info[0].info.status, SymbolStatus::Online);
+    assert_eq!(info[0].info.quote_asset, "USDT");
+    assert_eq!(info[0].info.precision, Precision { price_dp: 2, qty_dp: 6 });
+
+    let snaps: Vec<DepthSnapshot> = of_kind(&events, "depth_snapshot");
+    assert_eq!(snaps.len(), 1);
+    assert_eq!(snaps[0].last_update_id, 1000);
+
+    let deltas: Vec<DepthDelta> = of_kind(&events, "depth_delta");
+    assert_eq!(deltas.len(), 3);
+    assert_eq!(deltas.iter().map(|d| (d.from_version, d.to_version)).collect::<Vec<_>>(), [(1001, 1001), (1002, 1002), (1003, 1005)]);
+    assert_book_matches(deltas.last().unwrap(), &ex);
+
+    assert_eq!(events.iter().filter(|e| e.kind == "depth_pb_raw").count(), 3);
+
+    let trades: Vec<TradeEvent> = of_kind(&events, "trade");
+    assert_eq!(trades.len(), 2);
+    assert_eq!(trades[0].side.as_deref(), Some("BUY"));
+    assert_eq!(trades[1].side.as_deref(), Some("SELL"));
+}
+
+#[tokio::test]
+async fn duplicate_frames_are_skipped() {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let scenario = Scenario {
+        symbol: SYMBOL.to_string(),
+        steps: vec![
+            seed(),
+            Step::WaitSubscribed,
+            Step::Depth(Delta::new().ask(100.5, 0.7)),
+            Step::Duplicate,
+            Step::Depth(Delta::new().bid(100.1, 1.0)),
+            Step::Duplicate,
+            Step::Duplicate,
+            Step::Sleep(Duration::from_millis(200)),
+        ],
+    };
+    let events = record("dup", &ex, "", scenario).await;
+
+    assert_eq!(of_kind::<DepthSnapshot>(&events, "depth_snapshot").len(), 1);
+    let deltas: Vec<DepthDelta> = of_kind(&events, "depth_delta");
+    assert_eq!(deltas.len(), 2);
+    assert_book_matches(deltas.last().unwrap(), &ex);
+}
+
+#[tokio::test]
+async fn sequence_gap_resyncs_from_rest() {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let scenario = Scenario {
+        symbol: SYMBOL.to_string(),
+        steps: vec![
+            seed(),
+            Step::WaitSubscribed,
+            Step::Depth(Delta::new().ask(100.5, 0.7)),
+            // lost frame that also changed the book; wider than the bridged gap
+            Step::Drop(Delta::new().bid(100.0, 0.0).bid(100.1, 9.0).span(5000)),
+            Step::Depth(Delta::new().ask(103.0, 1.0)),
+            Step::Sleep(Duration::from_millis(500)),
+            Step::Depth(Delta::new().ask(101.0, 0.4)),
+            Step::Sleep(Duration::from_millis(200)),
+        ],
+    };
+    let events = record("gap", &ex, "", scenario).await;
+
+    let snaps: Vec<DepthSnapshot> = of_kind(&events, "depth_snapshot");
+    assert_eq!(snaps.len(), 2);
+    assert_eq!(snaps[1].last_update_id, 1000 + 1 + 5000 + 1);
+    assert_eq!(snaps[1].bids[0], [100.1, 9.0]);
+
+    let deltas: Vec<DepthDelta> = of_kind(&events, "depth_delta");
+    assert_book_matches(deltas.last().unwrap(), &ex);
+}
+
+#[tokio::test]
+async fn failed_resync_is_retried() {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let scenario = Scenario {
+        symbol: SYMBOL.to_string(),
+        steps: vec![
+            seed(),
+            Step::WaitSubscribed,
+            Step::Depth(Delta::new().ask(100.5, 0.7)),
+            Step::FailRest { path: "/api/v3/depth".to_string(), status: 503, times: 2, retry_after_secs: None },
+            Step::Drop(Delta::new().ask(100.5, 0.0).span(5000)),
+            Step::Depth(Delta::new().ask(104.0, 1.0)),
+            Step::Sleep(Duration::from_millis(300)),
+            // each frame gives the stalled resync another chance
+            Step::Depth(Delta::new().ask(105.0, 1.0)),
+            Step::Sleep(Duration::from_millis(1200)),
+            Step::Depth(Delta::new().ask(106.0, 1.0)),
+            Step::Sleep(Duration::from_millis(1200)),
+            Step::Depth(Delta::new().ask(107.0, 1.0)),
+            Step::Sleep(Duration::from_millis(200)),
+            Step::Depth(Delta::new().bid(100.0, 2.0)),
+            Step::Sleep(Duration::from_millis(200)),
+        ],
+    };
+    let events = record("retry", &ex, "", scenario).await;
+
+    assert_eq!(ex.requests("/api/v3/depth"), 4);
+    assert_eq!(of_kind::<DepthSnapshot>(&events, "depth_snapshot").len(), 2);
+    let deltas: Vec<DepthDelta> = of_kind(&events, "depth_delta");
+    assert_book_matches(deltas.last().unwrap(), &ex);
+}
+
+#[tokio::test]
+async fn dropped_socket_reconnects_and_resyncs() {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let scenario = Scenario {
+        symbol: SYMBOL.to_string(),
+        steps: vec![
+            seed(),
+            Step::WaitSubscribed,
+            Step::Depth(Delta::new().ask(100.5, 0.7)),
+            Step::Sleep(Duration::from_millis(100)),
+            Step::Disconnect,
+            Step::WaitUnsubscribed,
+            // pushed to nobody while the recorder is away
+            Step::Depth(Delta::new().bid(100.0, 0.0).bid(100.1, 9.0)),
+            Step::WaitSubscribed,
+            Step::Sleep(Duration::from_millis(300)),
+            Step::Depth(Delta::new().ask(101.0, 0.0)),
+            Step::Trade { price: 100.3, qty: 0.25, buyer_maker: false },
+            Step::Sleep(Duration::from_millis(800)),
+        ],
+    };
+    let events = record("reconnect", &ex, "", scenario).await;
+
+    let snaps: Vec<DepthSnapshot> = of_kind(&events, "depth_snapshot");
+    assert_eq!(snaps.iter().map(|s| s.last_update_id).collect::<Vec<_>>(), [1000, 1002]);
+    assert_eq!(snaps[1].bids[0], [100.1, 9.0]);
+
+    let deltas: Vec<DepthDelta> = of_kind(&events, "depth_delta");
+    assert_eq!(deltas.iter().map(|d| (d.from_version, d.to_version)).collect::<Vec<_>>(), [(1001, 1001), (1003, 1003)]);
+    assert_book_matches(deltas.last().unwrap(), &ex);
+    assert_eq!(of_kind::<TradeEvent>(&events, "trade").len(), 1);
+}
+
+#[tokio::test]
+async fn crossed_book_is_pruned_and_recorded() {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let scenario = Scenario {
+        symbol: SYMBOL.to_string(),
+        steps: vec![
+            seed(),
+            Step::WaitSubscribed,
+            Step::Depth(Delta::new().bid(100.6, 1.0)),
+            Step::Sleep(Duration::from_millis(200)),
+        ],
+    };
+    let events = record("crossed", &ex, "[book]\ncrossed_policy = \"prune\"\n", scenario).await;
+
+    let crossed: Vec<CrossedBook> = of_kind(&events, "crossed_book");
+    assert_eq!(crossed.len(), 1);
+    assert_eq!(crossed[0].best_bid, 100.6);
+    assert_eq!(crossed[0].best_ask, 100.5);
+    assert_eq!(of_kind::<DepthSnapshot>(&events, "depth_snapshot").len(), 1);
+
+    let deltas: Vec<DepthDelta> = of_kind(&events, "depth_delta");
+    let last = deltas.last().unwrap();
+    assert_eq!(last.bids[0], [100.6, 1.0]);
+    assert_eq!(last.asks[0], [101.0, 2.0]);
+}
+
+#[tokio::test]
+async fn startup_fails_on_rejected_snapshot() {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let dir = scratch_dir("reject");
+    ex.run(&Scenario {
+        symbol: SYMBOL.to_string(),
+        steps: vec![Step::FailRest { path: "/api/v3/depth".to_string(), status: 400, times: 1, retry_after_secs: None }],
+    })
+    .await
+    .unwrap();
+    let mut child = spawn_recorder(&ex, &dir, "");
+    let status = wait_exit(&mut child).await;
+    assert!(!status.success());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn startup_fails_on_halted_symbol() {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let dir = scratch_dir("halted");
+    ex.run(&Scenario { symbol: SYMBOL.to_string(), steps: vec![Step::Status("2".to_string())] }).await.unwrap();
+    let mut child = spawn_recorder(&ex, &dir, "");
+    let status = wait_exit(&mut child).await;
+    assert!(!status.success());
+    assert_eq!(ex.requests("/api/v3/exchangeInfo"), 1);
+    assert_eq!(ex.requests("/api/v3/depth"), 0);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+fn scenario(symbol: &str, steps: Vec<Step>) -> Scenario {
+    Scenario { symbol: symbol.to_string(), steps }
+}
+
+#[tokio::test]
+async fn delisted_symbol_stops_recording() {
+    const OTHER: &str = "ETHUSDT";
+    const UNRECORDED: &str = "XRPUSDT";
+    let ex = MockExchange::start(&[SYMBOL, OTHER, UNRECORDED]).await.unwrap();
+    let dir = scratch_dir("delist");
+    ex.run(&scenario(SYMBOL, vec![seed()])).await.unwrap();
+    ex.run(&scenario(OTHER, vec![seed()])).await.unwrap();
+    let mut child = spawn_recorder_for(&ex, &dir, &format!("{SYMBOL},{OTHER}"), "info_refresh_secs = 1", "");
+
+    // seen by the same refresh as the delisting at the latest
+    ex.run(&scenario(UNRECORDED, vec![Step::Status("2".to_string())])).await.unwrap();
+    ex.run(&scenario(OTHER, vec![Step::WaitSubscribed, Step::Delist, Step::WaitUnsubscribed])).await.unwrap();
+    ex.run(&scenario(
+        SYMBOL,
+        vec![
+            Step::WaitSubscribed,
+            Step::Depth(Delta::new().ask(100.5, 0.5)),
+            Step::Sleep(Duration::from_millis(300)),
+        ],
+    ))
+    .await
+    .unwrap();
+    let status = stop(&mut child).await;
+    assert!(status.success(), "recorder failed: {status}");
+    let events = stored_events(&dir);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let other = of_symbol(&events, OTHER);
+    let changes: Vec<SymbolChange> = of_kind(&other, "symbol_delisted");
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].old.as_ref().unwrap().status, SymbolStatus::Online);
+    assert!(changes[0].new.is_none());
+    assert_eq!(of_kind::<DepthSnapshot>(&other, "depth_snapshot").len(), 1);
+
+    let deltas: Vec<DepthDelta> = of_kind(&of_symbol(&events, SYMBOL), "depth_delta");
+    assert_eq!(deltas.len(), 1);
+    assert!(of_symbol(&events, UNRECORDED).is_empty());
+}
+
+#[tokio::test]
+async fn ended_recorder_is_restarted() {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let dir = scratch_dir("restart");
+    ex.run(&scenario(
+        SYMBOL,
+        vec![seed(), Step::FailRest { path: "/api/v3/depth".to_string(), status: 503, times: 1, retry_after_secs: None }],
+    ))
+    .await
+    .unwrap();
+    // no retries: the first snapshot request failing ends the recorder
+    let mut child = spawn_recorder(&ex, &dir, "[rest]\nmax_retries = 0\n");
+    ex.run(&scenario(
+        SYMBOL,
+        vec![
+            Step::WaitSubscribed,
+            Step::Depth(Delta::new().ask(100.5, 0.5)),
+            Step::Sleep(Duration::from_millis(300)),
+        ],
+    ))
+    .await
+    .unwrap();
+    let status = stop(&mut child).await;
+    assert!(status.success(), "recorder failed: {status}");
+    let events = stored_events(&dir);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(ex.requests("/api/v3/depth"), 2);
+    assert_eq!(of_kind::<DepthSnapshot>(&events, "depth_snapshot").len(), 1);
+    let deltas: Vec<DepthDelta> = of_kind(&events, "depth_delta");
+    assert_eq!(deltas.len(), 1);
+    assert_book_matches(&deltas[0], &ex);
+}
+
+#[tokio::test]
+async fn listing_is_recorded_and_pause_resumes() {
+    const NEW: &str = "NEWUSDT";
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let dir = scratch_dir("listing");
+    ex.run(&scenario(SYMBOL, vec![seed()])).await.unwrap();
+    let mut child = spawn_recorder_for(
+        &ex,
+        &dir,
+        SYMBOL,
+        "info_refresh_secs = 1",
+        "[listings]\nauto_record = true\nquote_assets = [\"USDT\"]\n",
+    );
+
+    ex.run(&scenario(SYMBOL, vec![Step::WaitSubscribed])).await.unwrap();
+    ex.run(&scenario(
+        NEW,
+        vec![
+            Step::List,
+            seed(),
+            Step::WaitSubscribed,
+            Step::Depth(Delta::new().bid(100.1, 1.0)),
+            Step::Status("2".to_string()),
+            Step::WaitUnsubscribed,
+            Step::Status("1".to_string()),
+            Step::WaitSubscribed,
+            Step::Sleep(Duration::from_millis(300)),
+        ],
+    ))
+    .await
+    .unwrap();
+
+    let status = stop(&mut child).await;
+    assert!(status.success(), "recorder failed: {status}");
+    let events = stored_events(&dir);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let new = of_symbol(&events, NEW);
+    let listed: Vec<SymbolChange> = of_kind(&new, "symbol_listed");
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].new.as_ref().unwrap().quote_asset, "USDT");
+    let changes: Vec<SymbolChange> = of_kind(&new, "symbol_status_changed");
+    let statuses: Vec<_> = changes.iter().map(|c| c.new.as_ref().unwrap().status).collect();
+    assert_eq!(statuses, [SymbolStatus::Paused, SymbolStatus::Online]);
+    assert_eq!(of_kind::<DepthSnapshot>(&new, "depth_snapshot").len(), 2);
+    assert_eq!(of_kind::<DepthDelta>(&new, "depth_delta").len(), 1);
+}
+
+#[tokio::test]
+async fn features_are_computed_live() {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let scenario = Scenario {
+        symbol: SYMBOL.to_string(),
+        steps: vec![
+            seed(),
+            Step::WaitSubscribed,
+            Step::Depth(Delta::new().bid(100.2, 4.0)),
+            Step::Trade { price: 100.3, qty: 0.25, buyer_maker: false },
+            Step::Sleep(Duration::from_millis(800)),
+            Step::Depth(Delta::new().ask(100.5, 0.5)),
+            Step::Sleep(Duration::from_millis(300)),
+        ],
+    };
+    let events = record("features", &ex, "[features]\nenabled = true\ninterval_ms = 0\nwindows_ms = [60000]\n", scenario).await;
+
+    let rows: Vec<FeatureRow> = of_kind(&events, "features");
+    assert_eq!(rows.iter().map(|r| r.version).collect::<Vec<_>>(), [1000, 1001, 1002]);
+    assert!(rows[1].windows[0].ofi > 0.0, "a better bid is buying pressure");
+    let last = &rows[2].windows[0];
+    assert_eq!(last.trades, 1);
+    assert_eq!(last.signed_volume, 0.25);
+    assert_eq!(last.vwap, Some(100.3));
+}
+
+#[tokio::test]
+async fn samples_are_written_live() {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let dir = scratch_dir("sampler");
+    let samples = dir.join("samples");
+    let cfg = format!("[sampler]\nenabled = true\ninterval_ms = 200\nlevels = 2\nformat = \"csv\"\ndir = \"{}\"\n", samples.display());
+    let mut child = spawn_recorder(&ex, &dir, &cfg);
+    let scenario = Scenario {
+        symbol: SYMBOL.to_string(),
+        steps: vec![
+            seed(),
+            Step::WaitSubscribed,
+            Step::Depth(Delta::new().bid(100.2, 4.0)),
+            Step::Sleep(Duration::from_millis(700)),
+            Step::Depth(Delta::new().ask(100.5, 0.5)),
+            Step::Sleep(Duration::from_millis(700)),
+        ],
+    };
+    ex.run(&scenario).await.unwrap();
+    assert!(stop(&mut child).await.success());
+
+    let mut csv = String::new();
+    for part in list_partitions(&samples).unwrap() {
+        csv += &std::fs::read_to_string(part.dir.join("samples_200ms.csv")).unwrap();
+    }
+    let mut lines = csv.lines();
+    let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+    let idx = |name: &str| header.iter().position(|h| *h == name).unwrap();
+    let rows: Vec<Vec<&str>> = lines.filter(|l| !l.starts_with("ts_ms")).map(|l| l.split(',').collect()).collect();
+    assert!(rows.len() >= 5, "{csv}");
+    let ts: Vec<i64> = rows.iter().map(|r| r[idx("ts_ms")].parse().unwrap()).collect();
+    assert!(ts.windows(2).all(|w| w[1] - w[0] == 200), "{ts:?}");
+    assert_eq!(rows[0][idx("gap")], "true");
+    assert!(rows[1..].iter().all(|r| r[idx("gap")] == "false"));
+    assert_eq!(rows.iter().map(|r| r[idx("updates")].parse::<u64>().unwrap()).sum::<u64>(), 2);
+    assert_eq!(rows.last().unwrap()[idx("version_to")], "1002");
+    assert_eq!(rows.last().unwrap()[idx("ask_qty_1")], "0.5");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn trades_are_aligned_live() {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let scenario = Scenario {
+        symbol: SYMBOL.to_string(),
+        steps: vec![
+            seed(),
+            Step::WaitSubscribed,
+            Step::Depth(Delta::new().ask(100.5, 0.5)),
+            Step::Sleep(Duration::from_millis(50)),
+            Step::Trade { price: 100.5, qty: 0.2, buyer_maker: false },
+            Step::Trade { price: 100.0, qty: 1.5, buyer_maker: true },
+            Step::Sleep(Duration::from_millis(800)),
+        ],
+    };
+    let events = record("align", &ex, "[align]\nenabled = true\n", scenario).await;
+
+    let aligned: Vec<AlignedTrade> = of_kind(&events, "trade_aligned");
+    assert_eq!(aligned.len(), 2);
+    assert!(aligned.iter().all(|a| a.book_version == Some(1001) && a.side_agrees == Some(true)));
+    assert_eq!(aligned[0].aggressor, Some(Aggressor::Buy));
+    assert_eq!((aligned[0].visible_qty, aligned[0].mismatch), (Some(0.5), None));
+    assert_eq!(aligned[1].aggressor, Some(Aggressor::Sell));
+    assert_eq!(aligned[1].mismatch, Some(Mismatch::ExceedsVisible));
+}
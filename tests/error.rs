@@ -0,0 +1,46 @@
+// error.rs
+//
+// `IngestError::recovery`, one row per variant and REST status class.
+use prost::Message;
+use tokio_tungstenite::tungstenite;
+
+use mexc_spot_public::error::{parse_num, IngestError, Recovery};
+use mexc_spot_public::mexc_pb::PushDataV3ApiWrapper;
+
+fn rest(status: Option<u16>) -> IngestError {
+    IngestError::Rest { status, body: String::new() }
+}
+
+#[test]
+fn every_error_maps_to_its_recovery() {
+    let decode = PushDataV3ApiWrapper::decode(&[0xff, 0xff, 0xff][..]).unwrap_err();
+    let table: Vec<(IngestError, Recovery)> = vec![
+        (decode.into(), Recovery::Skip),
+        (IngestError::UnexpectedBody("no body".into()), Recovery::Skip),
+        (IngestError::Stale { from: 1, to: 2, version: 5 }, Recovery::Skip),
+        (parse_num::<f64>("price", "abc").unwrap_err(), Recovery::Resync),
+        (IngestError::SequenceGap { needed: 11, from: 2000, to: 2001 }, Recovery::Resync),
+        (IngestError::CrossedBook { bid: 101.0, ask: 100.0 }, Recovery::Resync),
+        (tungstenite::Error::ConnectionClosed.into(), Recovery::Abort),
+        (rest(None), Recovery::Retry),
+        (rest(Some(429)), Recovery::Retry),
+        (rest(Some(418)), Recovery::Retry),
+        (rest(Some(500)), Recovery::Retry),
+        (rest(Some(503)), Recovery::Retry),
+        (rest(Some(400)), Recovery::Abort),
+        (rest(Some(404)), Recovery::Abort),
+    ];
+    for (e, want) in table {
+        assert_eq!(e.recovery(), want, "{e}");
+    }
+}
+
+#[test]
+fn parse_errors_name_the_field() {
+    let e = parse_num::<u64>("fromVersion", "-1").unwrap_err();
+    assert!(matches!(&e, IngestError::Parse { field: "fromVersion", value } if value == "-1"));
+    assert_eq!(e.to_string(), "bad fromVersion \"-1\"");
+    assert_eq!(parse_num::<u64>("toVersion", "42").unwrap(), 42);
+    assert_eq!(rest(None).to_string(), "REST error: ");
+    assert_eq!(rest(Some(503)).to_string(), "REST 503: ");
+}
@@ -0,0 +1,46 @@
+// golden.rs
+//
+// Replays every fixture under tests/fixtures through the book builder and
+// compares the result with its checked-in `golden.json`. After an intended
+// behaviour change, regenerate with `UPDATE_GOLDEN=1 cargo test --test golden`
+// and review the diff. New fixtures come from a recorded partition:
+// `mexc-spot-public fixture <partition_dir> tests/fixtures/<name>`.
+use std::path::Path;
+
+use mexc_spot_public::fixture::{replay, Fixture, Golden, GOLDEN_FILE};
+
+fn first_difference(want: &str, got: &str) -> Option<String> {
+    let (w, g): (Vec<_>, Vec<_>) = (want.lines().collect(), got.lines().collect());
+    let at = (0..w.len().max(g.len())).find(|&i| w.get(i) != g.get(i))?;
+    Some(format!(
+        "line {}: want {:?}, got {:?}",
+        at + 1,
+        w.get(at).copied().unwrap_or("<end>"),
+        g.get(at).copied().unwrap_or("<end>")
+    ))
+}
+
+#[test]
+fn fixtures_match_golden_outputs() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut dirs: Vec<_> = std::fs::read_dir(&root).unwrap().map(|e| e.unwrap().path()).filter(|p| p.is_dir()).collect();
+    dirs.sort();
+    assert!(!dirs.is_empty(), "no fixtures in {}", root.display());
+
+    let mut failures = Vec::new();
+    for dir in &dirs {
+        let got = replay(&Fixture::load(dir).unwrap());
+        let golden_path = dir.join(GOLDEN_FILE);
+        if update {
+            got.write(&golden_path).unwrap();
+            continue;
+        }
+        let want = Golden::read(&golden_path).unwrap();
+        let (want, got) = (serde_json::to_string_pretty(&want).unwrap(), serde_json::to_string_pretty(&got).unwrap());
+        if let Some(diff) = first_difference(&want, &got) {
+            failures.push(format!("{}: {diff}", dir.file_name().unwrap().to_string_lossy()));
+        }
+    }
+    assert!(failures.is_empty(), "golden mismatches:\n{}", failures.join("\n"));
+}
@@ -0,0 +1,198 @@
+// mcast.rs
+//
+// Multicast fan-out on loopback: publish and receive, a gap made by a lossy
+// relay between two groups, recovery through the TCP service, and the
+// packet limits.
+use std::cmp::Reverse;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ordered_float::OrderedFloat;
+use socket2::{Domain, Protocol, Socket, Type};
+
+use mexc_spot_public::config::MulticastConfig;
+use mexc_spot_public::mcast::{decode, encode, Body, McastPublisher, McastReceiver, Packet, Received};
+use mexc_spot_public::types::{AppliedDelta, BookSide, RevSide, TradeEvent};
+
+const SYMBOL: &str = "BTCUSDT";
+const LOOPBACK: Ipv4Addr = Ipv4Addr::LOCALHOST;
+
+/// Two groups on ports unlikely to clash with a parallel run.
+fn groups() -> (SocketAddrV4, SocketAddrV4) {
+    let port = 20_000 + (std::process::id() % 20_000) as u16;
+    (SocketAddrV4::new(Ipv4Addr::new(239, 255, 77, 1), port), SocketAddrV4::new(Ipv4Addr::new(239, 255, 77, 2), port + 1))
+}
+
+async fn publisher(group: SocketAddrV4) -> (Arc<McastPublisher>, SocketAddr) {
+    let cfg = MulticastConfig {
+        enabled: true,
+        group: group.to_string(),
+        interface: LOOPBACK.to_string(),
+        ..Default::default()
+    };
+    let mc = Arc::new(McastPublisher::new(&cfg).unwrap());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(mc.clone().serve_retransmit_listener(listener));
+    (mc, addr)
+}
+
+fn receiver(group: SocketAddrV4, retransmit: Option<SocketAddr>) -> McastReceiver {
+    let rx = McastReceiver::join(group, LOOPBACK, retransmit).unwrap();
+    rx.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    rx
+}
+
+/// Forwards `from` to `to`, dropping the packets whose seq is in `drop`,
+/// until `from` has been quiet for a second.
+fn lossy_relay(from: SocketAddrV4, to: SocketAddrV4, drop: &'static [u64]) -> std::thread::JoinHandle<()> {
+    let mut rx = receiver(from, None);
+    rx.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    let tx = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+    tx.set_multicast_if_v4(&LOOPBACK).unwrap();
+    tx.set_multicast_loop_v4(true).unwrap();
+    std::thread::spawn(move || {
+        while let Ok(Received::Packet(p)) = rx.recv() {
+            if matches!(p.body, Body::Snapshot { .. }) || !drop.contains(&p.seq) {
+                tx.send_to(&encode(&p).unwrap(), &SocketAddr::from(to).into()).unwrap();
+            }
+        }
+    })
+}
+
+fn book() -> (BookSide, RevSide) {
+    let mut asks = BookSide::new();
+    let mut bids = RevSide::new();
+    asks.insert(OrderedFloat(100.5), 1.0);
+    asks.insert(OrderedFloat(101.0), 2.0);
+    bids.insert(Reverse(OrderedFloat(100.0)), 3.0);
+    (asks, bids)
+}
+
+fn delta(version: u64, bids: Vec<[f64; 2]>, asks: Vec<[f64; 2]>) -> AppliedDelta {
+    AppliedDelta { from_version: version, to_version: version, ts_exch_ms: None, bids, asks }
+}
+
+fn packet(r: Received) -> Packet {
+    match r {
+        Received::Packet(p) => p,
+        r => panic!("expected a packet, got {r:?}"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn publish_gap_and_recovery_on_loopback() {
+    let (upstream, downstream) = groups();
+    let (mc, rt_addr) = publisher(upstream).await;
+    let relay = lossy_relay(upstream, downstream, &[3, 4]);
+    let mut rx = receiver(downstream, Some(rt_addr));
+
+    let (asks, bids) = book();
+    mc.publish_snapshot(SYMBOL, 10, 1, &asks, &bids);
+    mc.publish_delta(SYMBOL, &delta(11, vec![[100.2, 1.0]], vec![]), 2);
+    let trade = TradeEvent {
+        symbol: SYMBOL.into(),
+        ts_recv_ms: 3,
+        id: Some(7),
+        price: 100.5,
+        qty: 0.5,
+        side: Some("BUY".into()),
+        ts_exch_ms: Some(2),
+    };
+    mc.publish_trade(SYMBOL, &trade);
+    mc.publish_delta(SYMBOL, &delta(12, vec![[100.0, 0.0]], vec![]), 4);
+    mc.publish_delta(SYMBOL, &delta(13, vec![], vec![[100.5, 0.0]]), 5);
+    mc.publish_delta(SYMBOL, &delta(14, vec![], vec![[100.8, 4.0]]), 6);
+
+    let rx = tokio::task::spawn_blocking(move || {
+        let snap = packet(rx.recv().unwrap());
+        assert_eq!((snap.seq, snap.ts_recv_ms), (0, 1));
+        assert_eq!(snap.body, Body::Snapshot { version: 10, bids: vec![[100.0, 3.0]], asks: vec![[100.5, 1.0], [101.0, 2.0]] });
+
+        let d = packet(rx.recv().unwrap());
+        assert_eq!(d.seq, 1);
+        assert_eq!(d.body, Body::Delta { from_version: 11, to_version: 11, bids: vec![[100.2, 1.0]], asks: vec![] });
+        let t = packet(rx.recv().unwrap());
+        assert_eq!(t.seq, 2);
+        assert_eq!(t.body, Body::Trade { id: Some(7), price: 100.5, qty: 0.5, side: Some("BUY".into()), ts_exch_ms: Some(2) });
+
+        match rx.recv().unwrap() {
+            Received::Gap { symbol, from, to } => assert_eq!((symbol.as_str(), from, to), (SYMBOL, 3, 4)),
+            r => panic!("expected a gap, got {r:?}"),
+        }
+        let after = packet(rx.recv().unwrap());
+        assert_eq!(after.seq, 5);
+
+        let missed = rx.recover(SYMBOL, 3, 4).unwrap();
+        assert_eq!(missed.iter().map(|p| p.seq).collect::<Vec<_>>(), [3, 4]);
+        assert_eq!(missed[0].body, Body::Delta { from_version: 12, to_version: 12, bids: vec![[100.0, 0.0]], asks: vec![] });
+        assert_eq!(missed[1].body, Body::Delta { from_version: 13, to_version: 13, bids: vec![], asks: vec![[100.5, 0.0]] });
+
+        let full = rx.snapshot(SYMBOL).unwrap().unwrap();
+        assert_eq!(full.seq, 5);
+        assert_eq!(full.body, Body::Snapshot { version: 14, bids: vec![[100.2, 1.0]], asks: vec![[100.8, 4.0], [101.0, 2.0]] });
+        assert!(rx.snapshot("ETHUSDT").unwrap().is_none());
+    })
+    .await;
+    relay.join().unwrap();
+    rx.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn bad_requests_get_an_error_line() {
+    let (group, _) = groups();
+    let group = SocketAddrV4::new(Ipv4Addr::new(239, 255, 77, 3), group.port() + 2);
+    let (mc, rt_addr) = publisher(group).await;
+    mc.publish_delta(SYMBOL, &delta(1, vec![[1.0, 1.0]], vec![]), 1);
+
+    tokio::task::spawn_blocking(move || {
+        let rx = receiver(group, Some(rt_addr));
+        assert!(rx.recover(SYMBOL, 5, 1).unwrap().is_empty());
+
+        let mut s = TcpStream::connect(rt_addr).unwrap();
+        s.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut rd = BufReader::new(s.try_clone().unwrap());
+        for bad in ["RETRANS BTCUSDT one 2\n", "HELLO\n"] {
+            s.write_all(bad.as_bytes()).unwrap();
+            let mut len = [0u8; 4];
+            rd.read_exact(&mut len).unwrap();
+            assert_eq!(u32::from_le_bytes(len), u32::MAX);
+            let mut line = String::new();
+            rd.read_line(&mut line).unwrap();
+            assert!(line.starts_with("ERR "), "{line}");
+        }
+        // the connection still answers
+        s.write_all(b"RETRANS BTCUSDT 1 1\n").unwrap();
+        let mut len = [0u8; 4];
+        rd.read_exact(&mut len).unwrap();
+        let mut frame = vec![0u8; u32::from_le_bytes(len) as usize];
+        rd.read_exact(&mut frame).unwrap();
+        assert_eq!(decode(&frame).unwrap().seq, 1);
+        rd.read_exact(&mut len).unwrap();
+        assert_eq!(u32::from_le_bytes(len), 0);
+    })
+    .await
+    .unwrap();
+}
+
+#[test]
+fn oversized_snapshots_are_rejected() {
+    let levels = vec![[1.0, 1.0]; u16::MAX as usize + 1];
+    let p = Packet { symbol: SYMBOL.into(), seq: 0, ts_recv_ms: 0, body: Body::Snapshot { version: 1, bids: levels, asks: vec![] } };
+    assert!(encode(&p).is_err());
+    let p = Packet { body: Body::Snapshot { version: 1, bids: vec![[1.0, 1.0]; u16::MAX as usize], asks: vec![] }, ..p };
+    assert_eq!(decode(&encode(&p).unwrap()).unwrap(), p);
+}
+
+#[test]
+fn symbols_longer_than_the_header_field_are_rejected() {
+    let long = "ABCDEFGHIJKLMNOPQ";
+    assert!(McastPublisher::check_symbol(&long[..16]).is_ok());
+    assert!(McastPublisher::check_symbol(long).is_err());
+    let p = Packet { symbol: long.into(), seq: 1, ts_recv_ms: 0, body: Body::Snapshot { version: 1, bids: vec![], asks: vec![] } };
+    assert!(encode(&p).is_err());
+    let p = Packet { symbol: long[..16].into(), ..p };
+    assert_eq!(decode(&encode(&p).unwrap()).unwrap(), p);
+}
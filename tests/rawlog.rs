@@ -0,0 +1,107 @@
+// rawlog.rs
+//
+// `raw.pb.zst` round trip, reading a truncated file, and appending after a
+// crash left an unfinished frame.
+use std::path::PathBuf;
+
+use mexc_spot_public::rawlog::{read_raw_frames, RawChannel, RawFrame, RawWriter, RAW_FILE_NAME};
+
+fn scratch_file(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mexc-rawlog-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir.join(RAW_FILE_NAME)
+}
+
+fn frames(n: usize, from: i64) -> Vec<(i64, RawChannel, Vec<u8>)> {
+    let channels = [RawChannel::AggreDepth, RawChannel::AggreDeals, RawChannel::Unknown];
+    (0..n).map(|i| (from + i as i64, channels[i % 3], vec![i as u8; i * 7])).collect()
+}
+
+fn write_all(w: &mut RawWriter, fs: &[(i64, RawChannel, Vec<u8>)]) {
+    for (ts, ch, data) in fs {
+        w.write(*ts, *ch, data).unwrap();
+    }
+}
+
+fn read_ok(path: &PathBuf) -> Vec<RawFrame> {
+    read_raw_frames(path).unwrap().map(|f| f.unwrap()).collect()
+}
+
+fn same(got: &[RawFrame], want: &[(i64, RawChannel, Vec<u8>)]) {
+    assert_eq!(got.len(), want.len());
+    for (g, (ts, ch, data)) in got.iter().zip(want) {
+        assert_eq!((g.recv_ts_ns, g.channel, g.flags, &g.data), (*ts, *ch, 0, data));
+    }
+}
+
+#[test]
+fn frames_round_trip() {
+    let path = scratch_file("round-trip");
+    let want = frames(50, 1_000);
+    let mut w = RawWriter::open(&path).unwrap();
+    write_all(&mut w, &want[..20]);
+    // flushed frames are readable while the writer is still open
+    w.flush().unwrap();
+    same(&read_ok(&path), &want[..20]);
+    write_all(&mut w, &want[20..]);
+    w.finish().unwrap();
+    same(&read_ok(&path), &want);
+
+    // reopening appends
+    let more = frames(5, 9_000);
+    let mut w = RawWriter::open(&path).unwrap();
+    write_all(&mut w, &more);
+    w.finish().unwrap();
+    same(&read_ok(&path), &[want, more].concat());
+    let _ = std::fs::remove_dir_all(path.parent().unwrap());
+}
+
+#[test]
+fn truncated_file_yields_frames_then_one_error() {
+    let path = scratch_file("truncated");
+    let want = frames(30, 1_000);
+    let mut w = RawWriter::open(&path).unwrap();
+    write_all(&mut w, &want[..10]);
+    w.flush().unwrap();
+    let first_frame = std::fs::metadata(&path).unwrap().len();
+    write_all(&mut w, &want[10..]);
+    w.finish().unwrap();
+
+    let full = std::fs::metadata(&path).unwrap().len();
+    let f = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    f.set_len(first_frame + (full - first_frame) / 2).unwrap();
+    let got: Vec<_> = read_raw_frames(&path).unwrap().collect();
+    let ok: Vec<RawFrame> = got.iter().take_while(|f| f.is_ok()).map(|f| f.as_ref().unwrap().clone()).collect();
+    assert!(ok.len() >= 10 && ok.len() < 30, "{} frames before the error", ok.len());
+    same(&ok, &want[..ok.len()]);
+    assert_eq!(got.len(), ok.len() + 1, "exactly one error at the end");
+    let _ = std::fs::remove_dir_all(path.parent().unwrap());
+}
+
+#[test]
+fn reopening_drops_an_unfinished_frame() {
+    let path = scratch_file("crash");
+    let before = frames(10, 1_000);
+    let mut w = RawWriter::open(&path).unwrap();
+    write_all(&mut w, &before);
+    w.finish().unwrap();
+    let good = std::fs::read(&path).unwrap();
+
+    // what a crash mid-frame leaves: the start of another frame
+    let torn = scratch_file("crash-torn");
+    let mut t = RawWriter::open(&torn).unwrap();
+    write_all(&mut t, &frames(10, 5_000));
+    t.finish().unwrap();
+    let tail = std::fs::read(&torn).unwrap();
+    std::fs::write(&path, [good.as_slice(), &tail[..tail.len() / 2]].concat()).unwrap();
+    assert!(read_raw_frames(&path).unwrap().any(|f| f.is_err()));
+
+    let after = frames(10, 2_000);
+    let mut w = RawWriter::open(&path).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), good.len() as u64);
+    write_all(&mut w, &after);
+    w.finish().unwrap();
+    same(&read_ok(&path), &[before, after].concat());
+    let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    let _ = std::fs::remove_dir_all(torn.parent().unwrap());
+}
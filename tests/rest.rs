@@ -0,0 +1,88 @@
+// rest.rs
+//
+// `MexcRestClient` against `mock::MockExchange`: retries, Retry-After and the
+// shared weight budget.
+use std::time::{Duration, Instant};
+
+use mexc_spot_public::config::RestConfig;
+use mexc_spot_public::error::IngestError;
+use mexc_spot_public::mock::{MockExchange, Scenario, Step};
+use mexc_spot_public::rest::MexcRestClient;
+
+const SYMBOL: &str = "BTCUSDT";
+
+async fn exchange(steps: Vec<Step>) -> MockExchange {
+    let ex = MockExchange::start(&[SYMBOL]).await.unwrap();
+    let mut all = vec![Step::Snapshot { version: 7, asks: vec![[100.5, 1.0]], bids: vec![[100.0, 2.0]] }];
+    all.extend(steps);
+    ex.run(&Scenario { symbol: SYMBOL.to_string(), steps: all }).await.unwrap();
+    ex
+}
+
+fn fail(status: u16, times: u32, retry_after_secs: Option<u64>) -> Step {
+    Step::FailRest { path: "/api/v3/depth".to_string(), status, times, retry_after_secs }
+}
+
+fn client(ex: &MockExchange, cfg: RestConfig) -> MexcRestClient {
+    MexcRestClient::new(&ex.rest_url(), &cfg, None).unwrap()
+}
+
+fn fast() -> RestConfig {
+    RestConfig { backoff_ms: 20, ..Default::default() }
+}
+
+#[tokio::test]
+async fn server_errors_are_retried() {
+    let ex = exchange(vec![fail(503, 2, None)]).await;
+    let depth = client(&ex, fast()).depth(SYMBOL, 10).await.unwrap();
+    assert_eq!(depth.last_update_id, 7);
+    assert_eq!(depth.asks, [["100.5".to_string(), "1".to_string()]]);
+    assert_eq!(ex.requests("/api/v3/depth"), 3);
+}
+
+#[tokio::test]
+async fn retries_give_up_after_max_retries() {
+    let ex = exchange(vec![fail(503, 10, None)]).await;
+    let err = client(&ex, RestConfig { max_retries: 2, ..fast() }).depth(SYMBOL, 10).await.unwrap_err();
+    assert!(matches!(err, IngestError::Rest { status: Some(503), .. }), "{err}");
+    assert_eq!(ex.requests("/api/v3/depth"), 3);
+}
+
+#[tokio::test]
+async fn client_errors_are_not_retried() {
+    let ex = exchange(vec![fail(400, 1, None)]).await;
+    let err = client(&ex, fast()).depth(SYMBOL, 10).await.unwrap_err();
+    assert!(matches!(err, IngestError::Rest { status: Some(400), .. }), "{err}");
+    assert_eq!(ex.requests("/api/v3/depth"), 1);
+}
+
+#[tokio::test]
+async fn retry_after_pauses_every_request() {
+    let ex = exchange(vec![fail(429, 1, Some(1))]).await;
+    let c = client(&ex, fast());
+    let t0 = Instant::now();
+    let (depth, time) = tokio::join!(c.depth(SYMBOL, 10), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        c.server_time().await
+    });
+    depth.unwrap();
+    time.unwrap();
+    assert!(t0.elapsed() >= Duration::from_secs(1));
+    assert_eq!(ex.requests("/api/v3/depth"), 2);
+}
+
+#[tokio::test]
+async fn weight_budget_is_enforced() {
+    let ex = exchange(vec![]).await;
+    let c = client(&ex, RestConfig { weight_limit: 10, weight_window_secs: 1, ..fast() });
+    let t0 = Instant::now();
+    // 10 x depth (1) drains the bucket, 2 x trades (5) need another second
+    for _ in 0..10 {
+        c.depth(SYMBOL, 5).await.unwrap();
+    }
+    assert!(t0.elapsed() < Duration::from_millis(500));
+    for _ in 0..2 {
+        c.trades(SYMBOL, 5).await.unwrap();
+    }
+    assert!(t0.elapsed() >= Duration::from_millis(900));
+}
@@ -0,0 +1,130 @@
+// server.rs
+//
+// `BookHub` behind the embedded WebSocket server: subscribe, snapshot, deltas,
+// the resnapshot of a lagging subscriber and unknown symbols.
+use std::cmp::Reverse;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use ordered_float::OrderedFloat;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message as WsMsg, MaybeTlsStream, WebSocketStream};
+
+use mexc_spot_public::server::{serve_listener, BookHub};
+use mexc_spot_public::types::{AppliedDelta, BookSide, RevSide};
+
+type Client = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+const SYMBOL: &str = "BTCUSDT";
+
+async fn start(hub: Arc<BookHub>) -> Client {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_listener(hub, listener));
+    connect_async(format!("ws://{addr}")).await.unwrap().0
+}
+
+async fn send(ws: &mut Client, v: Value) {
+    ws.send(WsMsg::Text(v.to_string())).await.unwrap();
+}
+
+async fn next(ws: &mut Client) -> Value {
+    loop {
+        let msg = tokio::time::timeout(Duration::from_secs(5), ws.next()).await.expect("no message").unwrap().unwrap();
+        if let WsMsg::Text(t) = msg {
+            return serde_json::from_str(&t).unwrap();
+        }
+    }
+}
+
+/// The next message that is not a subscribe/unsubscribe reply.
+async fn next_data(ws: &mut Client) -> Value {
+    loop {
+        let v = next(ws).await;
+        if v.get("op").is_none() {
+            return v;
+        }
+    }
+}
+
+fn seed(hub: &BookHub) {
+    let mut asks = BookSide::new();
+    let mut bids = RevSide::new();
+    asks.insert(OrderedFloat(100.5), 1.0);
+    bids.insert(Reverse(OrderedFloat(100.0)), 2.0);
+    hub.publish_snapshot(SYMBOL, 10, &asks, &bids);
+}
+
+fn delta(version: u64, bid: f64) -> AppliedDelta {
+    AppliedDelta { from_version: version, to_version: version, ts_exch_ms: None, bids: vec![[bid, 1.0]], asks: vec![] }
+}
+
+#[tokio::test]
+async fn subscriber_gets_snapshot_then_deltas() {
+    let hub = Arc::new(BookHub::new());
+    seed(&hub);
+    let mut ws = start(hub.clone()).await;
+
+    send(&mut ws, json!({ "op": "subscribe", "channel": "book.BTCUSDT" })).await;
+    let snap = next_data(&mut ws).await;
+    assert_eq!(snap["type"], "snapshot");
+    assert_eq!(snap["channel"], "book.BTCUSDT");
+    assert_eq!(snap["seq"], 1);
+    assert_eq!(snap["version"], 10);
+    assert_eq!(snap["bids"], json!([[100.0, 2.0]]));
+    assert_eq!(snap["asks"], json!([[100.5, 1.0]]));
+
+    hub.publish_delta(SYMBOL, &delta(11, 99.5));
+    hub.publish_delta(SYMBOL, &delta(12, 99.0));
+    for (seq, version, bid) in [(2, 11, 99.5), (3, 12, 99.0)] {
+        let d = next_data(&mut ws).await;
+        assert_eq!(d["type"], "delta");
+        assert_eq!(d["seq"], seq);
+        assert_eq!(d["to_version"], version);
+        assert_eq!(d["bids"], json!([[bid, 1.0]]));
+    }
+}
+
+#[tokio::test]
+async fn lagging_subscriber_is_resnapshotted() {
+    let hub = Arc::new(BookHub::new());
+    seed(&hub);
+    let mut ws = start(hub.clone()).await;
+    send(&mut ws, json!({ "op": "subscribe", "channel": "book.BTCUSDT" })).await;
+    assert_eq!(next_data(&mut ws).await["seq"], 1);
+
+    // on this single-threaded runtime nothing is forwarded until the loop is
+    // done, so the subscriber overflows the broadcast buffer
+    let n = 5000;
+    for i in 0..n {
+        hub.publish_delta(SYMBOL, &delta(11 + i, 90.0 - i as f64 * 0.001));
+    }
+    let snap = next_data(&mut ws).await;
+    assert_eq!(snap["type"], "snapshot");
+    assert_eq!(snap["seq"], 1 + n);
+    assert_eq!(snap["version"], 10 + n);
+    assert_eq!(snap["bids"].as_array().unwrap().len(), 1 + n as usize);
+
+    hub.publish_delta(SYMBOL, &delta(11 + n, 100.1));
+    let d = next_data(&mut ws).await;
+    assert_eq!(d["type"], "delta");
+    assert_eq!(d["seq"], 2 + n);
+}
+
+#[tokio::test]
+async fn unknown_symbol_is_rejected() {
+    let hub = Arc::new(BookHub::new());
+    seed(&hub);
+    let mut ws = start(hub.clone()).await;
+
+    for channel in ["book.ETHUSDT", "trades.ETHUSDT", "features.ETHUSDT"] {
+        send(&mut ws, json!({ "op": "subscribe", "channel": channel })).await;
+        let reply = next(&mut ws).await;
+        assert_eq!(reply["op"], "error", "{channel}");
+        assert_eq!(reply["msg"], "unknown symbol ETHUSDT");
+    }
+    send(&mut ws, json!({ "op": "subscribe", "channel": "trades.BTCUSDT" })).await;
+    assert_eq!(next(&mut ws).await["op"], "subscribed");
+}
@@ -0,0 +1,116 @@
+// shm.rs
+//
+// `ShmWriter`/`ShmReader`: consistent seqlock reads under a concurrent writer,
+// and readers surviving the writer being recreated.
+use std::cmp::Reverse;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use ordered_float::OrderedFloat;
+
+use mexc_spot_public::shm::{ShmReader, ShmWriter};
+use mexc_spot_public::types::{BookSide, RevSide};
+
+const SYMBOL: &str = "BTCUSDT";
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mexc-shm-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// `n` levels a side, every price and quantity derived from `v`.
+fn book(v: u64, n: usize) -> (BookSide, RevSide) {
+    let mut asks = BookSide::new();
+    let mut bids = RevSide::new();
+    for i in 0..n {
+        asks.insert(OrderedFloat(v as f64 + 1.0 + i as f64), v as f64);
+        bids.insert(Reverse(OrderedFloat(v as f64 - 1.0 - i as f64)), v as f64);
+    }
+    (asks, bids)
+}
+
+#[test]
+fn reads_are_consistent_under_a_concurrent_writer() {
+    let dir = scratch_dir("seqlock");
+    let levels = 8;
+    let mut w = ShmWriter::create(&dir, SYMBOL, levels).unwrap();
+    let r = ShmReader::open_symbol(&dir, SYMBOL).unwrap();
+    assert_eq!(r.symbol(), SYMBOL);
+    assert_eq!(r.levels(), levels);
+
+    let done = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let done = done.clone();
+        std::thread::spawn(move || {
+            for v in 1..=50_000u64 {
+                // a varying depth exercises the level counts too
+                let (asks, bids) = book(v, 1 + v as usize % levels);
+                w.publish(v, v as i64, Some(v as i64 - 1), &asks, &bids);
+            }
+            done.store(true, Ordering::Release);
+        })
+    };
+
+    let (mut reads, mut last_seq) = (0u64, 0u64);
+    while !done.load(Ordering::Acquire) || reads == 0 {
+        let t = r.read();
+        assert_eq!(t.seq % 2, 0);
+        assert!(t.seq >= last_seq, "seq went back from {last_seq} to {}", t.seq);
+        last_seq = t.seq;
+        if t.version == 0 {
+            continue;
+        }
+        let v = t.version;
+        let n = 1 + v as usize % levels;
+        assert_eq!(t.ts_recv_ms, v as i64);
+        assert_eq!(t.ts_exch_ms, Some(v as i64 - 1));
+        assert_eq!(t.bids.len(), n, "torn read at version {v}");
+        assert_eq!(t.asks.len(), n, "torn read at version {v}");
+        for (i, (b, a)) in t.bids.iter().zip(&t.asks).enumerate() {
+            assert_eq!(*b, [v as f64 - 1.0 - i as f64, v as f64], "torn read at version {v}");
+            assert_eq!(*a, [v as f64 + 1.0 + i as f64, v as f64], "torn read at version {v}");
+        }
+        reads += 1;
+    }
+    writer.join().unwrap();
+    let last = r.read();
+    assert_eq!(last.version, 50_000);
+    assert_eq!(last.seq, 100_000);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn readers_survive_the_writer_being_recreated() {
+    let dir = scratch_dir("recreate");
+    let (asks, bids) = book(10, 3);
+    let mut w = ShmWriter::create(&dir, SYMBOL, 4).unwrap();
+    w.publish(10, 1, None, &asks, &bids);
+    w.set_counters(2, 1);
+    let r = ShmReader::open_symbol(&dir, SYMBOL).unwrap();
+    assert_eq!(r.read().version, 10);
+    drop(w);
+
+    // a restarted recorder with the same layout continues the same region
+    let mut w = ShmWriter::create(&dir, SYMBOL, 4).unwrap();
+    assert_eq!(r.read().version, 10);
+    assert_eq!(r.counters(), (0, 0));
+    let (asks, bids) = book(11, 2);
+    w.publish(11, 2, None, &asks, &bids);
+    let t = r.read();
+    assert_eq!((t.version, t.seq, t.bids.len()), (11, 4, 2));
+    drop(w);
+
+    // a different layout gets a new file; the old mapping stays readable
+    let mut w = ShmWriter::create(&dir, SYMBOL, 16).unwrap();
+    let (asks, bids) = book(12, 10);
+    w.publish(12, 3, None, &asks, &bids);
+    assert_eq!(r.read().version, 11);
+    let r2 = ShmReader::open_symbol(&dir, SYMBOL).unwrap();
+    assert_eq!(r2.levels(), 16);
+    let t = r2.read();
+    assert_eq!((t.version, t.bids.len()), (12, 10));
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,102 @@
+// store.rs
+//
+// `DataStore` appends from concurrent threads keep each symbol's seq in file
+// order, and `MergedEvents` orders by (ts, seq) across partitions and sources.
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use mexc_spot_public::clock::Stamp;
+use mexc_spot_public::store::{partition_dir, read_events, read_events_merged, DataStore, MergedEvents, StoredEvent};
+
+/// 2024-05-01T10:00:00Z
+const T0: i64 = 1_714_557_600_000;
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mexc-store-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn stamp(ms: i64, recv_seq: u64) -> Stamp {
+    Stamp { ts_ns: ms * 1_000_000, recv_seq }
+}
+
+fn event(symbol: &str, ts_ns: i64, seq: Option<u64>) -> StoredEvent {
+    StoredEvent {
+        ts_ms: ts_ns.div_euclid(1_000_000),
+        ts_ns: Some(ts_ns),
+        recv_seq: None,
+        seq,
+        symbol: symbol.into(),
+        kind: "x".into(),
+        payload: None,
+        payload_b64: None,
+        raw_bytes: None,
+    }
+}
+
+#[test]
+fn concurrent_appends_keep_each_symbols_seq_in_file_order() {
+    let dir = scratch_dir("concurrent");
+    let store = Arc::new(DataStore::new(&dir).unwrap());
+    let threads: Vec<_> = (0..8)
+        .map(|t| {
+            let store = store.clone();
+            std::thread::spawn(move || {
+                let symbol = if t % 2 == 0 { "BTCUSDT" } else { "ETHUSDT" };
+                for i in 0..200 {
+                    store.append_event_json(symbol, stamp(T0 + i, t * 1000 + i as u64), "x", &t).unwrap();
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    store.close().unwrap();
+    for symbol in ["BTCUSDT", "ETHUSDT"] {
+        let path = partition_dir(&dir, symbol, T0).join("events.ndjson.zst");
+        let seqs: Vec<u64> = read_events(path).unwrap().map(|e| e.unwrap().seq.unwrap()).collect();
+        assert_eq!(seqs, (1..=800).collect::<Vec<_>>(), "{symbol}");
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn merged_partitions_are_ordered_by_ts_then_seq() {
+    let dir = scratch_dir("merged");
+    let store = DataStore::new(&dir).unwrap();
+    // same ms in both symbols, and stamps that arrive slightly out of order
+    for (symbol, ms, recv_seq) in [
+        ("BTCUSDT", T0 + 5, 1),
+        ("ETHUSDT", T0 + 5, 2),
+        ("BTCUSDT", T0 + 3, 3),
+        ("ETHUSDT", T0 + 1, 4),
+        ("BTCUSDT", T0 + 5, 5),
+        ("ETHUSDT", T0 + 9, 6),
+        ("BTCUSDT", T0 + 8, 7),
+    ] {
+        store.append_event_json(symbol, stamp(ms, recv_seq), "x", &recv_seq).unwrap();
+    }
+    store.close().unwrap();
+    let paths: Vec<PathBuf> = ["BTCUSDT", "ETHUSDT"].iter().map(|s| partition_dir(&dir, s, T0).join("events.ndjson.zst")).collect();
+
+    let got: Vec<(i64, String, u64)> = read_events_merged(&paths)
+        .unwrap()
+        .map(|e| e.unwrap())
+        .map(|e| (e.ts_ms - T0, e.symbol, e.seq.unwrap()))
+        .collect();
+    let want = [(1, "ETHUSDT", 2), (3, "BTCUSDT", 2), (5, "BTCUSDT", 1), (5, "ETHUSDT", 1), (5, "BTCUSDT", 3), (8, "BTCUSDT", 4), (9, "ETHUSDT", 3)];
+    assert_eq!(got, want.map(|(t, s, q)| (t, s.to_string(), q)));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn merge_breaks_ties_by_seq_and_places_late_events() {
+    let a = vec![event("A", 10, Some(2)), event("A", 10, Some(4)), event("A", 30, Some(5)), event("A", 20, Some(6))];
+    // raw frames carry no seq and go first on an equal ts
+    let b = vec![event("A", 10, None), event("A", 10, Some(3)), event("A", 25, Some(7))];
+    let merged = MergedEvents::new(vec![a.into_iter().map(Ok), b.into_iter().map(Ok)], 100);
+    let got: Vec<(i64, Option<u64>)> = merged.map(|e| e.unwrap()).map(|e| (e.ts_ns.unwrap(), e.seq)).collect();
+    assert_eq!(got, [(10, None), (10, Some(2)), (10, Some(3)), (10, Some(4)), (20, Some(6)), (25, Some(7)), (30, Some(5))]);
+}
@@ -0,0 +1,105 @@
+// validate.rs
+//
+// `validate`: rolling a REST snapshot forward over version jumps the book
+// bridges, the cases where it cannot be aligned, and level mismatches.
+use std::cmp::Reverse;
+
+use ordered_float::OrderedFloat;
+
+use mexc_spot_public::book::MAX_VERSION_GAP;
+use mexc_spot_public::types::{AppliedDelta, BookSide, DepthSnapshot, RevSide};
+use mexc_spot_public::validate::{validate, DeltaBuffer};
+
+fn rest(version: u64, asks: Vec<[f64; 2]>, bids: Vec<[f64; 2]>) -> DepthSnapshot {
+    DepthSnapshot { symbol: "BTCUSDT".into(), ts_recv_ms: 1, last_update_id: version, bids, asks }
+}
+
+fn delta(from: u64, to: u64, asks: Vec<[f64; 2]>, bids: Vec<[f64; 2]>) -> AppliedDelta {
+    AppliedDelta { from_version: from, to_version: to, ts_exch_ms: None, bids, asks }
+}
+
+fn book(asks: &[[f64; 2]], bids: &[[f64; 2]]) -> (BookSide, RevSide) {
+    let mut a = BookSide::new();
+    let mut b = RevSide::new();
+    for [p, q] in asks {
+        a.insert(OrderedFloat(*p), *q);
+    }
+    for [p, q] in bids {
+        b.insert(Reverse(OrderedFloat(*p)), *q);
+    }
+    (a, b)
+}
+
+fn buffer(deltas: Vec<AppliedDelta>, cap: usize) -> DeltaBuffer {
+    let mut buf = DeltaBuffer::new(cap);
+    for d in deltas {
+        buf.push(d);
+    }
+    buf
+}
+
+/// Deltas after version 100, with a jump the book bridges between 102 and 150.
+fn deltas() -> Vec<AppliedDelta> {
+    vec![
+        delta(90, 100, vec![[100.5, 9.0]], vec![]),
+        delta(101, 102, vec![[100.5, 0.5]], vec![]),
+        delta(150, 151, vec![], vec![[100.2, 3.0]]),
+        delta(152, 152, vec![[101.0, 0.0]], vec![]),
+    ]
+}
+
+#[test]
+fn aligned_over_bridged_version_jumps() {
+    let snap = rest(100, vec![[100.5, 1.0], [101.0, 2.0]], vec![[100.0, 1.0]]);
+    let (asks, bids) = book(&[[100.5, 0.5], [102.0, 7.0]], &[[100.2, 3.0], [100.0, 1.0], [99.0, 5.0]]);
+    let v = validate(&snap, &buffer(deltas(), 100), &asks, &bids, 152).unwrap();
+    assert!(v.aligned);
+    assert_eq!((v.rest_version, v.local_version, v.replayed_deltas), (100, 152, 3));
+    // 102.0 and 99.0 are outside the snapshot's range
+    assert_eq!((v.compared_levels, v.mismatched_levels), (3, 0));
+    assert_eq!(v.mismatch_ratio, 0.0);
+}
+
+#[test]
+fn unaligned_when_the_deltas_do_not_cover_the_snapshot() {
+    let snap = rest(100, vec![[100.5, 1.0]], vec![[100.0, 1.0]]);
+    let (asks, bids) = book(&[[100.5, 1.0]], &[[100.0, 1.0]]);
+
+    // a jump the book would not bridge
+    let far = 102 + MAX_VERSION_GAP + 2;
+    let buf = buffer(vec![delta(101, 102, vec![], vec![]), delta(far, far, vec![], vec![])], 100);
+    let v = validate(&snap, &buf, &asks, &bids, far).unwrap();
+    assert!(!v.aligned);
+    assert_eq!((v.replayed_deltas, v.compared_levels), (1, 0));
+
+    // the delta after the snapshot has been evicted from a full buffer
+    let v = validate(&snap, &buffer(deltas(), 2), &asks, &bids, 152).unwrap();
+    assert!(!v.aligned);
+    assert_eq!(v.replayed_deltas, 0);
+
+    // the buffer ends short of the live version
+    let v = validate(&snap, &buffer(deltas(), 100), &asks, &bids, 160).unwrap();
+    assert!(!v.aligned);
+
+    // cleared on a resync: nothing to roll forward with
+    let mut buf = buffer(deltas(), 2);
+    buf.clear();
+    assert!(validate(&snap, &buf, &asks, &bids, 100).unwrap().aligned);
+    assert!(!validate(&snap, &buf, &asks, &bids, 101).unwrap().aligned);
+
+    // a snapshot newer than the book is not compared yet
+    assert!(validate(&rest(153, vec![], vec![]), &buffer(deltas(), 100), &asks, &bids, 152).is_none());
+}
+
+#[test]
+fn mismatches_are_counted_by_kind() {
+    let snap = rest(100, vec![[100.5, 1.0], [101.0, 2.0], [101.5, 1.0]], vec![[100.0, 1.0], [99.5, 2.0]]);
+    let buf = buffer(vec![delta(101, 101, vec![[100.5, 0.5]], vec![])], 100);
+    // 100.5 differs, 101.0 is missing here, 100.7 is missing in REST
+    let (asks, bids) = book(&[[100.5, 0.4], [100.7, 1.0], [101.5, 1.0]], &[[100.0, 1.0], [99.5, 2.0]]);
+    let v = validate(&snap, &buf, &asks, &bids, 101).unwrap();
+    assert!(v.aligned);
+    assert_eq!(v.compared_levels, 6);
+    assert_eq!((v.qty_diff, v.missing_local, v.missing_rest, v.mismatched_levels), (1, 1, 1, 3));
+    assert_eq!(v.mismatch_ratio, 0.5);
+}